@@ -1,3 +1,5 @@
+use std::{error::Error, fmt};
+
 /// A lock-free Single-Producer-Single-Consumer (SPSC) FIFO channel.
 pub mod spsc;
 
@@ -10,14 +12,86 @@ pub mod spmc;
 /// A lock-free Multi-Producer-Multi-Consumer (MPMC) FIFO channel.
 pub mod mpmc;
 
-/// The error of `Sender::send` operation. Occurs if all receivers were
-/// disconnected.
-#[derive(Debug, Clone, Copy)]
+/// A lock-free watch channel, sharing the latest of a value.
+pub mod watch;
+
+/// Multiplexing over receivers of several channels.
+pub mod select;
+
+/// Fan-out of a receiver into several receivers, each getting every message.
+pub mod tee;
+
+pub use self::tee::tee;
+
+/// The former error of `Sender::send` operation of unbounded channels, which
+/// occurred if all receivers were disconnected. Senders now return
+/// [`SendErr::Disconnected`] instead. This type is kept for callers still
+/// using it, and it converts into [`SendErr`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct NoRecv<T> {
     /// The message which was attempted to be sent.
     pub message: T,
 }
 
+impl<T> fmt::Display for NoRecv<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str("all receivers disconnected")
+    }
+}
+
+impl<T> Error for NoRecv<T> where T: fmt::Debug {}
+
+/// The error of every sending operation. Unbounded channels are never full, so
+/// their senders only fail with [`SendErr::Disconnected`]. The message is
+/// always given back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendErr<T> {
+    /// Returned when the channel is full, but there are still receivers
+    /// connected.
+    Full(T),
+    /// Returned when all receivers were disconnected.
+    Disconnected(T),
+}
+
+impl<T> SendErr<T> {
+    /// Gives back the message which was attempted to be sent.
+    pub fn into_message(self) -> T {
+        match self {
+            SendErr::Full(message) | SendErr::Disconnected(message) => message,
+        }
+    }
+
+    /// Tests whether the send failed because the channel was full.
+    pub fn is_full(&self) -> bool {
+        match self {
+            SendErr::Full(_) => true,
+            SendErr::Disconnected(_) => false,
+        }
+    }
+
+    /// Tests whether the send failed because all receivers disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        !self.is_full()
+    }
+}
+
+impl<T> From<NoRecv<T>> for SendErr<T> {
+    fn from(err: NoRecv<T>) -> Self {
+        SendErr::Disconnected(err.message)
+    }
+}
+
+impl<T> fmt::Display for SendErr<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str(match self {
+            SendErr::Full(_) => "channel is full",
+            SendErr::Disconnected(_) => "all receivers disconnected",
+        })
+    }
+}
+
+impl<T> Error for SendErr<T> where T: fmt::Debug {}
+
 /// The error of `Receiver::recv` operation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RecvErr {
@@ -27,3 +101,43 @@ pub enum RecvErr {
     /// Returned when all senders were disconnected.
     NoSender,
 }
+
+impl fmt::Display for RecvErr {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str(match self {
+            RecvErr::NoMessage => "no message available",
+            RecvErr::NoSender => "all senders disconnected",
+        })
+    }
+}
+
+impl Error for RecvErr {}
+
+#[cfg(test)]
+mod test {
+    use channel::{mpsc, NoRecv, RecvErr, SendErr};
+
+    fn send_twice(sender: &mpsc::Sender<u8>) -> Result<(), SendErr<u8>> {
+        sender.send(1)?;
+        sender.send(2)?;
+        Ok(())
+    }
+
+    #[test]
+    fn errors_give_message_back() {
+        let (sender, receiver) = mpsc::create();
+        drop(receiver);
+        let err = send_twice(&sender).unwrap_err();
+        assert!(err.is_disconnected());
+        assert_eq!(err.into_message(), 1);
+
+        let err = SendErr::Full("msg");
+        assert!(err.is_full());
+        assert_eq!(err.to_string(), "channel is full");
+        assert_eq!(err.into_message(), "msg");
+
+        let err = NoRecv { message: 3 };
+        assert_eq!(err.to_string(), "all receivers disconnected");
+        assert_eq!(RecvErr::NoSender.to_string(), "all senders disconnected");
+    }
+}
@@ -0,0 +1,138 @@
+use super::{Receiver, RecvErr, SendErr, Sender};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use future;
+
+impl<T> Sender<T> {
+    /// Creates a future which sends the given message. Since the channel is
+    /// unbounded, it never needs to wait and resolves on the first poll, with
+    /// an error if all receivers disconnected. Requires the `futures` feature.
+    pub fn send_async<'sender>(
+        &'sender self,
+        message: T,
+    ) -> SendFuture<'sender, T> {
+        SendFuture { sender: self, message: Some(message) }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Creates a future which resolves to a received message, waiting
+    /// asynchronously until one is available. The task is woken up by
+    /// [`send`](Sender::send). If all senders disconnected and there are no
+    /// messages left, the future resolves to `None`. Requires the `futures`
+    /// feature.
+    pub fn recv_async<'receiver>(&'receiver self) -> RecvFuture<'receiver, T> {
+        future::PopFuture::new(self, &self.inner.waiters, try_recv)
+    }
+}
+
+/// A future based on [`send`](Sender::send) operation of the MPMC
+/// [`Sender`]. Created by [`send_async`](Sender::send_async).
+pub struct SendFuture<'sender, T>
+where
+    T: 'sender,
+{
+    sender: &'sender Sender<T>,
+    message: Option<T>,
+}
+
+// The message is never pinned: it is just moved into the channel.
+impl<'sender, T> Unpin for SendFuture<'sender, T> {}
+
+impl<'sender, T> Future for SendFuture<'sender, T> {
+    type Output = Result<(), SendErr<T>>;
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<Result<(), SendErr<T>>> {
+        let message =
+            self.message.take().expect("SendFuture polled after completion");
+        Poll::Ready(self.sender.send(message))
+    }
+}
+
+impl<'sender, T> fmt::Debug for SendFuture<'sender, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "SendFuture {} sender: {:?}, sent: {:?} {}",
+            '{',
+            self.sender,
+            self.message.is_none(),
+            '}'
+        )
+    }
+}
+
+/// A future based on [`recv`](Receiver::recv) operation of the MPMC
+/// [`Receiver`]. Created by [`recv_async`](Receiver::recv_async). Dropping it
+/// before completion is fine.
+pub type RecvFuture<'receiver, T> =
+    future::PopFuture<'receiver, Receiver<T>, Option<T>>;
+
+// Succeeds with `None` once all senders disconnected and no messages are left.
+fn try_recv<T>(receiver: &Receiver<T>) -> Option<Option<T>> {
+    match receiver.recv() {
+        Ok(message) => Some(Some(message)),
+        Err(RecvErr::NoSender) => Some(None),
+        Err(RecvErr::NoMessage) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use channel::mpmc;
+    use executor::block_on;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn woken_up_by_send() {
+        const THREADS: usize = 4;
+        const MSGS_PER_THREAD: usize = 200;
+        const MSGS: usize = THREADS * MSGS_PER_THREAD;
+
+        let (sender, receiver) = mpmc::create::<usize>();
+        let mut consumers = Vec::with_capacity(THREADS);
+
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            consumers.push(thread::spawn(move || {
+                let mut sum = 0;
+                while let Some(i) = block_on(receiver.recv_async()) {
+                    sum += i;
+                }
+                sum
+            }));
+        }
+
+        // Giving the consumers time to wait.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. MSGS {
+            if i % 50 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            block_on(sender.send_async(i)).unwrap();
+        }
+        // Every consumer must be woken up by the disconnection.
+        drop(sender);
+
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.join().expect("thread failed");
+        }
+        assert_eq!(sum, MSGS * (MSGS - 1) / 2);
+    }
+
+    #[test]
+    fn send_async_fails_without_receivers() {
+        let (sender, receiver) = mpmc::create();
+        drop(receiver);
+        let err = block_on(sender.send_async(3)).unwrap_err();
+        assert_eq!(err.into_message(), 3);
+    }
+}
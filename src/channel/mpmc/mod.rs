@@ -1,7 +1,11 @@
+#[cfg(feature = "futures")]
+pub use self::future::{RecvFuture, SendFuture};
 pub use super::{
     NoRecv,
     RecvErr::{self, *},
+    SendErr::{self, *},
 };
+use channel::select::Selectable;
 use incin::Pause;
 use owned_alloc::OwnedAlloc;
 use ptr::{bypass_null, check_null_align};
@@ -14,6 +18,10 @@ use std::{
         Arc,
     },
 };
+use wait::WaitList;
+
+#[cfg(feature = "futures")]
+mod future;
 
 /// Creates an asynchronous lock-free Multi-Producer-Multi-Consumer (MPMC)
 /// channel. In order to allow multiple producers and multiple receivers,
@@ -23,7 +31,8 @@ pub fn create<T>() -> (Sender<T>, Receiver<T>) {
 }
 
 /// Same as [`create`], but use a passed incinerator instead of creating a new
-/// one.
+/// one. An incinerator created by [`SharedIncin::with_batch`] reclaims nodes
+/// less frequently, at the cost of memory.
 pub fn with_incin<T>(incin: SharedIncin<T>) -> (Sender<T>, Receiver<T>) {
     check_null_align::<Node<T>>();
 
@@ -39,8 +48,13 @@ pub fn with_incin<T>(incin: SharedIncin<T>) -> (Sender<T>, Receiver<T>) {
     let alloc = OwnedAlloc::new(shared);
     let back = alloc.into_raw();
 
+    // Receivers wait here for messages.
+    let waiters = Arc::new(WaitList::new());
+
     // Put the shared back in the sender.
-    let sender = Sender { inner: Arc::new(SenderInner { back }) };
+    let sender = Sender {
+        inner: Arc::new(SenderInner { back, waiters: waiters.clone() }),
+    };
 
     // And put the shared back and the single node (again) as front in the
     // receiver.
@@ -49,6 +63,7 @@ pub fn with_incin<T>(incin: SharedIncin<T>) -> (Sender<T>, Receiver<T>) {
             front: AtomicPtr::new(single_node.as_ptr()),
             back,
             incin,
+            waiters,
         }),
     };
 
@@ -63,7 +78,7 @@ pub struct Sender<T> {
 
 impl<T> Sender<T> {
     /// Sends a message and if the receiver disconnected, an error is returned.
-    pub fn send(&self, message: T) -> Result<(), NoRecv<T>> {
+    pub fn send(&self, message: T) -> Result<(), SendErr<T>> {
         // First of all we create a node for our message.
         let alloc = OwnedAlloc::new(Node {
             message: Removable::new(message),
@@ -84,7 +99,7 @@ impl<T> Sender<T> {
                 // without sharing it.
                 let mut alloc = unsafe { OwnedAlloc::from_raw(node) };
                 let message = alloc.message.replace(None).unwrap();
-                break Err(NoRecv { message });
+                break Err(Disconnected(message));
             }
 
             // This dereferral is safe because we only deallocate
@@ -110,11 +125,12 @@ impl<T> Sender<T> {
                     // non-deletable last node.
                     let res = unsafe {
                         // We then try to update the next field of the previous
-                        // back with the new back.
+                        // back with the new back. `SeqCst` because of the wait
+                        // list.
                         prev.as_ref().next.compare_exchange(
                             null_mut(),
                             node.as_ptr(),
-                            Release,
+                            SeqCst,
                             Relaxed,
                         )
                     };
@@ -135,6 +151,7 @@ impl<T> Sender<T> {
                         }
                     }
 
+                    self.inner.waiters.notify_one();
                     break Ok(());
                 },
 
@@ -216,6 +233,96 @@ impl<T> Receiver<T> {
         }
     }
 
+    /// Receives up to `max` messages at once, appending them to the given
+    /// buffer, and returns how many were received. The nodes are traversed
+    /// only once, under a single incinerator pause, which is cheaper than
+    /// calling [`recv`](Receiver::recv) repeatedly. If no message was
+    /// received, the error is the same as [`recv`](Receiver::recv)'s.
+    pub fn recv_many(
+        &self,
+        buf: &mut Vec<T>,
+        max: usize,
+    ) -> Result<usize, RecvErr> {
+        let pause = self.inner.incin.inner.pause();
+        let mut count = 0;
+
+        // Bypassing null check is safe because we never store null in
+        // the front.
+        let mut front_nnptr =
+            unsafe { bypass_null(self.inner.front.load(Relaxed)) };
+
+        while count < max {
+            // Safe to derefer this pointer because we paused the incinerator
+            // and we only delete nodes via incinerator.
+            let taken = unsafe { front_nnptr.as_ref().message.take(AcqRel) };
+            if let Some(val) = taken {
+                buf.push(val);
+                count += 1;
+            }
+
+            // Safe to call because we passed a pointer from the front which
+            // was loaded during the very same pause we are passing.
+            match unsafe { self.try_clear_first(front_nnptr, &pause) } {
+                Ok(nnptr) => front_nnptr = nnptr,
+
+                Err(err) if count == 0 => return Err(err),
+
+                Err(_) => break,
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Receives a message, blocking the current thread until one is
+    /// available. The thread is parked rather than spinning, and it is woken
+    /// up by [`send`](Sender::send). If all senders disconnected and there
+    /// are no messages left, `None` is returned.
+    ///
+    /// Another [`Receiver`] may take a message sent while this one waits, in
+    /// which case this one keeps waiting.
+    pub fn recv_wait(&self) -> Option<T> {
+        loop {
+            match self.recv() {
+                Ok(message) => break Some(message),
+                Err(RecvErr::NoSender) => break None,
+                Err(RecvErr::NoMessage) => (),
+            }
+
+            let waiter = self.inner.waiters.register();
+            // Checking again because a send might have happened before we
+            // registered ourselves.
+            match self.recv() {
+                Ok(message) => {
+                    waiter.cancel(&self.inner.waiters);
+                    break Some(message);
+                },
+
+                Err(RecvErr::NoSender) => {
+                    waiter.cancel(&self.inner.waiters);
+                    break None;
+                },
+
+                Err(RecvErr::NoMessage) => waiter.wait(),
+            }
+        }
+    }
+
+    /// Creates an iterator which receives messages, blocking the current
+    /// thread until each one is available, just like
+    /// [`recv_wait`](Receiver::recv_wait). It stops only when all senders
+    /// disconnected and there are no messages left.
+    pub fn iter<'receiver>(&'receiver self) -> Iter<'receiver, T> {
+        Iter { receiver: self }
+    }
+
+    /// Creates an iterator which receives the messages available right now,
+    /// without blocking. It stops when there are no messages or when the
+    /// sender disconnected.
+    pub fn try_iter<'receiver>(&'receiver self) -> TryIter<'receiver, T> {
+        TryIter { receiver: self }
+    }
+
     /// Tests if there are any [`Sender`]s still connected. There are no
     /// guarantees that [`recv`](Receiver::recv) will succeed if this method
     /// returns `true` because the [`Receiver`] may disconnect meanwhile.
@@ -238,11 +345,61 @@ impl<T> Receiver<T> {
             || !front.next.load(Relaxed).is_null()
     }
 
+    /// Counts the messages pending in the channel, without receiving them.
+    /// Since [`Sender`]s may send and other [`Receiver`]s may receive
+    /// meanwhile, this is only an estimate. This operation is linear on the
+    /// number of pending messages.
+    pub fn len(&self) -> usize {
+        // Pausing because we will walk through shared nodes.
+        let _pause = self.inner.incin.inner.pause();
+        let mut count = 0;
+        let mut node_ptr = self.inner.front.load(Acquire);
+
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator. The front is never null.
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            let node = unsafe { nnptr.as_ref() };
+            if node.message.is_present(Acquire) {
+                count += 1;
+            }
+            node_ptr = node.next.load(Acquire);
+        }
+
+        count
+    }
+
+    /// Tests whether there are no messages pending in the channel. Since
+    /// [`Sender`]s may send and other [`Receiver`]s may receive meanwhile,
+    /// this is only an estimate.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// The shared incinerator used by this [`Receiver`].
     pub fn incin(&self) -> SharedIncin<T> {
         self.inner.incin.clone()
     }
 
+    // This function is unsafe because passing the wrong pointers will lead to
+    // undefined behavior. The front must be the front of the only receiver of
+    // the queue whose shared back was passed, and the senders must notify the
+    // given waiters.
+    pub(super) unsafe fn from_raw_parts(
+        front: NonNull<Node<T>>,
+        back: NonNull<SharedBack<T>>,
+        waiters: Arc<WaitList>,
+        incin: SharedIncin<T>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ReceiverInner {
+                front: AtomicPtr::new(front.as_ptr()),
+                back,
+                incin,
+                waiters,
+            }),
+        }
+    }
+
     // This function is unsafe because passing the wrong pointer will lead to
     // undefined behavior. The pointer must have been loaded from the front
     // during the passed pause.
@@ -284,6 +441,36 @@ impl<T> Receiver<T> {
     }
 }
 
+impl<T> Selectable for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        // We need this pause because of use-after-free.
+        let _pause = self.inner.incin.inner.pause();
+        let mut ptr = self.inner.front.load(Acquire);
+
+        // Other receivers may have taken messages without clearing the nodes
+        // yet, so we skip those.
+        loop {
+            // Safe to derefer this pointer because we paused the incinerator
+            // and we only delete nodes via incinerator.
+            let node = unsafe { &*ptr };
+            if node.message.is_present(Acquire) {
+                break true;
+            }
+            ptr = node.next.load(Acquire);
+            if ptr.is_null() {
+                // This is safe because the shared back is only deallocated
+                // when both sides disconnected.
+                let back = unsafe { self.inner.back.as_ref() };
+                break back.ptr.load(Acquire) as usize & 1 == 1;
+            }
+        }
+    }
+
+    fn wait_list(&self) -> &WaitList {
+        &self.inner.waiters
+    }
+}
+
 unsafe impl<T> Send for Receiver<T> where T: Send {}
 unsafe impl<T> Sync for Receiver<T> where T: Send {}
 
@@ -299,8 +486,93 @@ impl<T> fmt::Debug for Receiver<T> {
     }
 }
 
+impl<'receiver, T> IntoIterator for &'receiver Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'receiver, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { receiver: self }
+    }
+}
+
+/// A blocking iterator over the messages of a [`Receiver`]. Created by
+/// [`iter`](Receiver::iter).
+pub struct Iter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for Iter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for Iter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Iter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// A blocking iterator which owns a [`Receiver`]. Created by its
+/// [`IntoIterator`] implementation.
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<T> fmt::Debug for IntoIter<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "IntoIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// An iterator over the messages available in a [`Receiver`], which does not
+/// block. Created by [`try_iter`](Receiver::try_iter).
+pub struct TryIter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for TryIter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for TryIter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "TryIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
 struct SenderInner<T> {
     back: NonNull<SharedBack<T>>,
+    waiters: Arc<WaitList>,
 }
 
 impl<T> Drop for SenderInner<T> {
@@ -326,12 +598,14 @@ impl<T> Drop for SenderInner<T> {
                 self.back
                     .as_ref()
                     .ptr
-                    .swap((ptr as usize | 1) as *mut _, Release)
+                    .swap((ptr as usize | 1) as *mut _, SeqCst)
             };
 
             if res == ptr {
                 // If we succeeded, we will left everything to be deallocated by
-                // the receiver.
+                // the receiver. Every waiting receiver must see the
+                // disconnection.
+                self.waiters.notify_all();
                 return;
             }
         }
@@ -352,6 +626,7 @@ struct ReceiverInner<T> {
     front: AtomicPtr<Node<T>>,
     back: NonNull<SharedBack<T>>,
     incin: SharedIncin<T>,
+    waiters: Arc<WaitList>,
 }
 
 impl<T> ReceiverInner<T> {
@@ -429,18 +704,20 @@ impl<T> Drop for ReceiverInner<T> {
     }
 }
 
-struct SharedBack<T> {
+// The MPSC channel shares the back, the nodes and their deletion with us, so
+// its receiver can be converted into ours.
+pub(super) struct SharedBack<T> {
     // lower bit is 0 when both sides connect, 1 when one disconnect
     // never null
-    ptr: AtomicPtr<Node<T>>,
+    pub(super) ptr: AtomicPtr<Node<T>>,
 }
 
 #[repr(align(/* at least */ 2))]
-struct Node<T> {
-    message: Removable<T>,
+pub(super) struct Node<T> {
+    pub(super) message: Removable<T>,
     // lower bit is 1 means this node (and its subsequent ones) need to be
     // thrown away.
-    next: AtomicPtr<Node<T>>,
+    pub(super) next: AtomicPtr<Node<T>>,
 }
 
 make_shared_incin! {
@@ -453,7 +730,7 @@ make_shared_incin! {
 // loaded from the back, and must be reachable from `curr` if non-null. Also,
 // the conditions for removal of the back needs to be respected. The function
 // stops whenever the pointer or a node whose next field is null is reached.
-unsafe fn delete_before_last<T>(
+pub(super) unsafe fn delete_before_last<T>(
     mut curr: NonNull<Node<T>>,
     last: Option<NonNull<Node<T>>>,
 ) {
@@ -490,6 +767,7 @@ mod test {
             Arc,
         },
         thread,
+        time::Duration,
     };
 
     #[test]
@@ -539,4 +817,102 @@ mod test {
             assert!(status.load(Relaxed));
         }
     }
+
+    #[test]
+    fn iterators() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 512;
+
+        let (sender, receiver) = mpmc::create::<usize>();
+        let mut consumers = Vec::with_capacity(THREADS);
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            consumers.push(thread::spawn(move || {
+                receiver.into_iter().collect::<Vec<_>>()
+            }));
+        }
+
+        // Giving the consumers time to wait.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let mut received = (&receiver).into_iter().collect::<Vec<_>>();
+        for consumer in consumers {
+            received.extend(consumer.join().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn try_iter_does_not_block() {
+        let (sender, receiver) = mpmc::create();
+        assert_eq!(receiver.try_iter().next(), None);
+        for i in 0 .. 3 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        sender.send(3).unwrap();
+        drop(sender);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn len_counts_pending() {
+        let (sender, receiver) = mpmc::create();
+        assert!(receiver.is_empty());
+        for i in 0 .. 5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.len(), 5);
+        assert_eq!(receiver.recv(), Ok(0));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.len(), 3);
+        drop(sender);
+        assert_eq!(receiver.len(), 3);
+        assert_eq!(receiver.try_iter().count(), 3);
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn recv_many_batches() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 2048;
+        const BATCH: usize = 16;
+
+        let (sender, receiver) = mpmc::create::<usize>();
+        let mut buf = Vec::new();
+        assert_eq!(receiver.recv_many(&mut buf, BATCH), Err(mpmc::NoMessage));
+
+        let mut threads = Vec::with_capacity(THREADS);
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::new();
+                loop {
+                    match receiver.recv_many(&mut received, BATCH) {
+                        Ok(count) => assert!(count > 0 && count <= BATCH),
+                        Err(mpmc::NoMessage) => thread::yield_now(),
+                        Err(mpmc::NoSender) => break received,
+                    }
+                }
+            }));
+        }
+        drop(receiver);
+
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let mut received = Vec::with_capacity(MSGS);
+        for thread in threads {
+            received.extend(thread.join().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
 }
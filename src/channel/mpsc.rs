@@ -1,27 +1,40 @@
 pub use super::{
     NoRecv,
     RecvErr::{self, *},
+    SendErr::{self, *},
+};
+use channel::{
+    mpmc::{self, delete_before_last, Node, SharedBack},
+    select::Selectable,
 };
 use owned_alloc::OwnedAlloc;
 use ptr::{bypass_null, check_null_align};
+use recycle::NodeCache;
+use removable::Removable;
 use std::{
     fmt,
-    ptr::{null_mut, NonNull},
+    mem,
+    ptr::{self, null_mut, NonNull},
     sync::{
         atomic::{AtomicPtr, Ordering::*},
         Arc,
     },
 };
+use wait::WaitList;
 
 /// Creates an asynchronous lock-free Multi-Producer-Single-Consumer (MPSC)
 /// channel. In order to allow multiple producers, [`Sender`] is clonable and
 /// does not require mutability.
+///
+/// Messages are stored inline in the nodes, and the nodes freed by the
+/// receiver are kept in a small cache shared with the senders, so a channel
+/// which is kept busy does not go to the allocator on every message.
 pub fn create<T>() -> (Sender<T>, Receiver<T>) {
     check_null_align::<Node<T>>();
 
     // A single empty node shared between two ends.
     let alloc = OwnedAlloc::new(Node {
-        message: None,
+        message: Removable::empty(),
         next: AtomicPtr::new(null_mut()),
     });
     let single_node = alloc.into_raw();
@@ -32,9 +45,19 @@ pub fn create<T>() -> (Sender<T>, Receiver<T>) {
     let alloc = OwnedAlloc::new(shared);
     let back = alloc.into_raw();
 
+    // The receiver blocks here waiting for messages.
+    let waiters = Arc::new(WaitList::new());
+    let cache = Arc::new(NodeCache::new());
+
     // Sender with an Arc because it is shared.
-    let sender = Sender { inner: Arc::new(SenderInner { back }) };
-    let receiver = Receiver { back, front: single_node };
+    let sender = Sender {
+        inner: Arc::new(SenderInner {
+            back,
+            waiters: waiters.clone(),
+            cache: cache.clone(),
+        }),
+    };
+    let receiver = Receiver { back, front: single_node, waiters, cache };
 
     (sender, receiver)
 }
@@ -47,10 +70,10 @@ pub struct Sender<T> {
 
 impl<T> Sender<T> {
     /// Sends a message and if the receiver disconnected, an error is returned.
-    pub fn send(&self, message: T) -> Result<(), NoRecv<T>> {
-        // First we create a node with our message.
-        let alloc = OwnedAlloc::new(Node {
-            message: Some(message),
+    pub fn send(&self, message: T) -> Result<(), SendErr<T>> {
+        // First we create a node with our message, possibly reusing one.
+        let alloc = self.inner.cache.alloc(Node {
+            message: Removable::new(message),
             next: AtomicPtr::new(null_mut()),
         });
         let node = alloc.into_raw();
@@ -67,8 +90,8 @@ impl<T> Sender<T> {
                 // allocation for the node we just created. We did not share the
                 // node.
                 let mut alloc = unsafe { OwnedAlloc::from_raw(node) };
-                let message = alloc.message.take().unwrap();
-                break Err(NoRecv { message });
+                let message = alloc.message.replace(None).unwrap();
+                break Err(Disconnected(message));
             }
 
             // This is safe because we only store nodes allocated via
@@ -96,8 +119,9 @@ impl<T> Sender<T> {
                     let res = unsafe {
                         // The next field is expected to be null. If it is not
                         // null, the receiver marked it
-                        // (it will be null | 1).
-                        prev.as_ref().next.swap(node.as_ptr(), Release)
+                        // (it will be null | 1). `SeqCst` because of the wait
+                        // list.
+                        prev.as_ref().next.swap(node.as_ptr(), SeqCst)
                     };
 
                     // If it was not null, then it means the receiver
@@ -119,6 +143,8 @@ impl<T> Sender<T> {
                             OwnedAlloc::from_raw(prev);
                             delete_before_last(node, None);
                         }
+                    } else {
+                        self.inner.waiters.notify_one();
                     }
 
                     break Ok(());
@@ -161,6 +187,8 @@ impl<T> fmt::Debug for Sender<T> {
 pub struct Receiver<T> {
     back: NonNull<SharedBack<T>>,
     front: NonNull<Node<T>>,
+    waiters: Arc<WaitList>,
+    cache: Arc<NodeCache<Node<T>>>,
 }
 
 impl<T> Receiver<T> {
@@ -178,7 +206,7 @@ impl<T> Receiver<T> {
             let next = node.next.load(Acquire);
 
             // Then we remove logicaly.
-            match node.message.take() {
+            match node.message.replace(None) {
                 Some(message) => {
                     // No need to clear the lower bit since the receiver is the
                     // only one that marks next field.
@@ -187,9 +215,8 @@ impl<T> Receiver<T> {
                         // via `OwnedAlloc`. The queue will always have at
                         // least one node, but if the next field was not null,
                         // this is not the only node.
-                        unsafe {
-                            OwnedAlloc::from_raw(self.front);
-                        };
+                        let alloc = unsafe { OwnedAlloc::from_raw(self.front) };
+                        self.cache.recycle(alloc);
                         // Setting the front to the next pointer.
                         self.front = nnptr;
                     }
@@ -234,10 +261,11 @@ impl<T> Receiver<T> {
                             // Also, if front has a successor, this means the
                             // queue is not empty, the back does not have access
                             // to the front, and thus it is safe to delete it.
-                            unsafe {
+                            let alloc = unsafe {
                                 node = &mut *nnptr.as_ptr();
-                                OwnedAlloc::from_raw(self.front);
+                                OwnedAlloc::from_raw(self.front)
                             };
+                            self.cache.recycle(alloc);
                             // Update our front to its successor. And let's try
                             // again.
                             self.front = nnptr;
@@ -248,6 +276,103 @@ impl<T> Receiver<T> {
         }
     }
 
+    /// Receives up to `max` messages at once, appending them to the given
+    /// buffer, and returns how many were received. The nodes are traversed
+    /// only once, which is cheaper than calling [`recv`](Receiver::recv)
+    /// repeatedly. If no message was received, the error is the same as
+    /// [`recv`](Receiver::recv)'s.
+    pub fn recv_many(
+        &mut self,
+        buf: &mut Vec<T>,
+        max: usize,
+    ) -> Result<usize, RecvErr> {
+        let mut count = 0;
+
+        while count < max {
+            // This is safe because we only store nodes allocated via
+            // `OwnedAlloc`, the queue always has at least one node and we are
+            // the only receiver.
+            let node = unsafe { &mut *self.front.as_ptr() };
+            let next = node.next.load(Acquire);
+
+            if let Some(message) = node.message.replace(None) {
+                buf.push(message);
+                count += 1;
+            }
+
+            match NonNull::new(next) {
+                // Just like in `recv`, if front has a successor, the senders
+                // do not have access to the front anymore, and thus it is safe
+                // to delete it.
+                Some(nnptr) => {
+                    let alloc = unsafe { OwnedAlloc::from_raw(self.front) };
+                    self.cache.recycle(alloc);
+                    self.front = nnptr;
+                },
+
+                // We will not remove the single node of the queue.
+                None => break,
+            }
+        }
+
+        if count == 0 && max > 0 {
+            // Let's find out why there were no messages. A message sent
+            // meanwhile is fine too.
+            return self.recv().map(|message| {
+                buf.push(message);
+                1
+            });
+        }
+
+        Ok(count)
+    }
+
+    /// Receives a message, blocking the current thread until one is
+    /// available. The thread is parked rather than spinning, and it is woken
+    /// up by [`send`](Sender::send). If all senders disconnected and there
+    /// are no messages left, `None` is returned.
+    pub fn recv_wait(&mut self) -> Option<T> {
+        loop {
+            match self.recv() {
+                Ok(message) => break Some(message),
+                Err(RecvErr::NoSender) => break None,
+                Err(RecvErr::NoMessage) => (),
+            }
+
+            let waiter = self.waiters.register();
+            // Checking again because a send might have happened before we
+            // registered ourselves.
+            match self.recv() {
+                Ok(message) => {
+                    waiter.cancel(&self.waiters);
+                    break Some(message);
+                },
+
+                Err(RecvErr::NoSender) => {
+                    waiter.cancel(&self.waiters);
+                    break None;
+                },
+
+                Err(RecvErr::NoMessage) => waiter.wait(),
+            }
+        }
+    }
+
+    /// Creates an iterator which receives messages, blocking the current
+    /// thread until each one is available, just like
+    /// [`recv_wait`](Receiver::recv_wait). It stops only when all senders
+    /// disconnected and there are no messages left.
+    pub fn iter<'receiver>(&'receiver mut self) -> Iter<'receiver, T> {
+        Iter { receiver: self }
+    }
+
+    /// Creates an iterator which receives the messages available right now,
+    /// without blocking. It stops when there are no messages or when the
+    /// sender disconnected.
+    pub fn try_iter<'receiver>(&'receiver mut self) -> TryIter<'receiver, T> {
+        TryIter { receiver: self }
+    }
+
     /// Tests if there any [`Sender`]s still connected. There are no guarantees
     /// that [`recv`](Receiver::recv) will succeed if this method returns `true`
     /// because the [`Receiver`] may disconnect meanwhile. This method may
@@ -263,10 +388,66 @@ impl<T> Receiver<T> {
         // marking (since it means sender disconnected).
         let back = unsafe { self.back.as_ref() };
         back.ptr.load(Acquire) as usize & 1 == 0
-            || front.message.is_some()
+            || front.message.is_present(Acquire)
             || !front.next.load(Acquire).is_null()
     }
 
+    /// Counts the messages pending in the channel, without receiving them.
+    /// Since [`Sender`]s may send meanwhile, this is only an estimate. This
+    /// operation is linear on the number of pending messages.
+    pub fn len(&self) -> usize {
+        let mut count = 0;
+        let mut node_ptr = self.front.as_ptr();
+
+        // Safe because we always have at least one node, and nodes are only
+        // dropped by the receiver or by the last side to disconnect. The
+        // message of a linked node is only changed by the receiver.
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            let node = unsafe { nnptr.as_ref() };
+            if node.message.is_present(Acquire) {
+                count += 1;
+            }
+            node_ptr = node.next.load(Acquire);
+        }
+
+        count
+    }
+
+    /// Tests whether there are no messages pending in the channel. Since
+    /// [`Sender`]s may send meanwhile, this is only an estimate.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Converts this receiver into a receiver of a MPMC channel, which is
+    /// clonable. The [`Sender`]s keep sending to it and the pending messages
+    /// are kept, so consumers can be added later without replacing the
+    /// senders.
+    pub fn into_mpmc(self) -> mpmc::Receiver<T> {
+        self.into_mpmc_with_incin(mpmc::SharedIncin::new())
+    }
+
+    /// Same as [`into_mpmc`](Receiver::into_mpmc), but use a passed
+    /// incinerator instead of creating a new one.
+    pub fn into_mpmc_with_incin(
+        self,
+        incin: mpmc::SharedIncin<T>,
+    ) -> mpmc::Receiver<T> {
+        let front = self.front;
+        let back = self.back;
+        // Safe because we forget the receiver right after, so the waiters and
+        // the cache are not dropped twice.
+        let (waiters, cache) =
+            unsafe { (ptr::read(&self.waiters), ptr::read(&self.cache)) };
+        mem::forget(self);
+        // MPMC receivers reclaim nodes via incinerator. The senders keep
+        // their cache, which is simply not refilled anymore.
+        drop(cache);
+        // Safe because this was the only receiver and our senders follow the
+        // same protocol as MPMC senders, notifying these same waiters.
+        unsafe { mpmc::Receiver::from_raw_parts(front, back, waiters, incin) }
+    }
+
     // This is unsafe because some conditions need to be met. Senders must have
     // disconnected.
     unsafe fn delete_all(&mut self) {
@@ -279,6 +460,28 @@ impl<T> Receiver<T> {
     }
 }
 
+impl<T> Selectable for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        // Safe because we always have at least one node, which is only dropped
+        // in the last side to disconnect's drop.
+        let front = unsafe { self.front.as_ref() };
+        if front.message.is_present(Acquire)
+            || !front.next.load(Acquire).is_null()
+        {
+            return true;
+        }
+        // This is safe because the shared back is only deallocated when both
+        // sides disconnected. Just like in `recv`, the senders disconnected
+        // only if the back is marked and no send is in progress.
+        let back = unsafe { self.back.as_ref().ptr.load(Acquire) as usize };
+        back & 1 == 1 && (back & !1) as *mut _ == self.front.as_ptr()
+    }
+
+    fn wait_list(&self) -> &WaitList {
+        &self.waiters
+    }
+}
+
 impl<T> Drop for Receiver<T> {
     fn drop(&mut self) {
         // This is safe because when senders disconnect, they won't drop the
@@ -346,8 +549,94 @@ impl<T> fmt::Debug for Receiver<T> {
     }
 }
 
+impl<'receiver, T> IntoIterator for &'receiver mut Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'receiver, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { receiver: self }
+    }
+}
+
+/// A blocking iterator over the messages of a [`Receiver`]. Created by
+/// [`iter`](Receiver::iter).
+pub struct Iter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver mut Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for Iter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for Iter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Iter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// A blocking iterator which owns a [`Receiver`]. Created by its
+/// [`IntoIterator`] implementation.
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<T> fmt::Debug for IntoIter<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "IntoIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// An iterator over the messages available in a [`Receiver`], which does not
+/// block. Created by [`try_iter`](Receiver::try_iter).
+pub struct TryIter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver mut Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for TryIter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for TryIter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "TryIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
 struct SenderInner<T> {
     back: NonNull<SharedBack<T>>,
+    waiters: Arc<WaitList>,
+    cache: Arc<NodeCache<Node<T>>>,
 }
 
 impl<T> Drop for SenderInner<T> {
@@ -373,12 +662,15 @@ impl<T> Drop for SenderInner<T> {
                 self.back
                     .as_ref()
                     .ptr
-                    .swap((ptr as usize | 1) as *mut _, Relaxed)
+                    .swap((ptr as usize | 1) as *mut _, SeqCst)
             };
 
             if res == ptr {
                 // If we succeeded, we will left everything to be deallocated by
-                // the receiver.
+                // the receiver. Every waiting receiver must see the
+                // disconnection, and there may be several of them after
+                // `into_mpmc`.
+                self.waiters.notify_all();
                 return;
             }
         }
@@ -395,57 +687,10 @@ impl<T> Drop for SenderInner<T> {
     }
 }
 
-struct SharedBack<T> {
-    // lower bit is 0 when both sides connect, 1 when one disconnect
-    // never null
-    ptr: AtomicPtr<Node<T>>,
-}
-
-#[repr(align(/* at least */ 2))]
-struct Node<T> {
-    message: Option<T>,
-    // lower bit is 1 means this node (and its subsequent ones) need to be
-    // thrown away.
-    next: AtomicPtr<Node<T>>,
-}
-
-// This function is unsafe because passing the wrong pointer may lead to
-// undefined behavior. The pointer `last` needs to be a pointer previously
-// loaded from the back, and must be reachable from `curr` if non-null. Also,
-// the conditions for removal of the back needs to be respected. The function
-// stops whenever the pointer or a node whose next field is null is reached.
-unsafe fn delete_before_last<T>(
-    mut curr: NonNull<Node<T>>,
-    last: Option<NonNull<Node<T>>>,
-) {
-    while last != Some(curr) {
-        // Let's try to mark the next field so other threads can see this node
-        // needs to be thrown away. It is ok to swap since we are the
-        // only ones accessing this node if the update fails.
-        let next = curr
-            .as_ref()
-            .next
-            .swap((null_mut::<Node<T>>() as usize | 1) as *mut _, Acquire);
-
-        match NonNull::new(next) {
-            // Failure. The node was not null. It was a plain node. We need to
-            // deallocate our current node and continue the job with the found
-            // node.
-            Some(next) => {
-                OwnedAlloc::from_raw(curr);
-                curr = next;
-            },
-
-            // Success. We have nothing more to do.
-            None => break,
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
-    use channel::mpsc;
-    use std::thread;
+    use channel::{mpmc, mpsc};
+    use std::{sync::Arc, thread, time::Duration};
 
     #[test]
     fn correct_numbers() {
@@ -490,4 +735,175 @@ mod test {
             assert!(*status);
         }
     }
+
+    #[test]
+    fn recv_wait_wakes_up() {
+        const THREADS: usize = 4;
+        const MSGS_PER_THREAD: usize = 500;
+        const MSGS: usize = THREADS * MSGS_PER_THREAD;
+
+        let (sender, mut receiver) = mpsc::create::<usize>();
+        let consumer = thread::spawn(move || {
+            let mut sum = 0;
+            while let Some(i) = receiver.recv_wait() {
+                sum += i;
+            }
+            sum
+        });
+
+        // Giving the consumer time to block.
+        thread::sleep(Duration::from_millis(20));
+        let mut threads = Vec::with_capacity(THREADS);
+        for i in 0 .. THREADS {
+            let sender = sender.clone();
+            threads.push(thread::spawn(move || {
+                let start = i * MSGS_PER_THREAD;
+                for j in start .. start + MSGS_PER_THREAD {
+                    if j % 50 == 0 {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    sender.send(j).unwrap();
+                }
+            }))
+        }
+        drop(sender);
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(consumer.join().unwrap(), MSGS * (MSGS - 1) / 2);
+    }
+
+    #[test]
+    fn iterators() {
+        const MSGS: usize = 512;
+
+        let (sender, mut receiver) = mpsc::create::<usize>();
+        assert_eq!(receiver.try_iter().next(), None);
+        sender.send(0).unwrap();
+        sender.send(1).unwrap();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0, 1]);
+
+        let producer = thread::spawn(move || {
+            for i in 0 .. MSGS {
+                sender.send(i).unwrap();
+            }
+        });
+        let received = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+        producer.join().unwrap();
+
+        let (sender, receiver) = mpsc::create();
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send(5).unwrap();
+        });
+        assert_eq!(receiver.into_iter().collect::<Vec<_>>(), [5]);
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn len_counts_pending() {
+        let (sender, mut receiver) = mpsc::create();
+        assert!(receiver.is_empty());
+        for i in 0 .. 5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.len(), 5);
+        assert_eq!(receiver.recv(), Ok(0));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.len(), 3);
+        drop(sender);
+        assert_eq!(receiver.len(), 3);
+        assert_eq!(receiver.try_iter().count(), 3);
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn recv_many_batches() {
+        let (sender, mut receiver) = mpsc::create();
+        let mut buf = Vec::new();
+        assert_eq!(receiver.recv_many(&mut buf, 4), Err(mpsc::NoMessage));
+
+        for i in 0 .. 10 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.recv_many(&mut buf, 4), Ok(4));
+        assert_eq!(receiver.recv_many(&mut buf, 0), Ok(0));
+        assert_eq!(receiver.recv_many(&mut buf, 100), Ok(6));
+        assert_eq!(buf, (0 .. 10).collect::<Vec<_>>());
+
+        sender.send(10).unwrap();
+        drop(sender);
+        assert_eq!(receiver.recv_many(&mut buf, 4), Ok(1));
+        assert_eq!(receiver.recv_many(&mut buf, 4), Err(mpsc::NoSender));
+        assert_eq!(buf.len(), 11);
+    }
+
+    #[test]
+    fn into_mpmc_keeps_senders() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 1024;
+
+        let (sender, mut receiver) = mpsc::create::<usize>();
+        sender.send(0).unwrap();
+        sender.send(1).unwrap();
+        assert_eq!(receiver.recv(), Ok(0));
+
+        let receiver = receiver.into_mpmc();
+        let mut threads = Vec::with_capacity(THREADS);
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::new();
+                loop {
+                    match receiver.recv() {
+                        Ok(i) => received.push(i),
+                        Err(mpmc::NoMessage) => thread::yield_now(),
+                        Err(mpmc::NoSender) => break received,
+                    }
+                }
+            }));
+        }
+        drop(receiver);
+
+        for i in 2 .. MSGS {
+            sender.clone().send(i).unwrap();
+        }
+        drop(sender);
+
+        let mut received = Vec::with_capacity(MSGS);
+        for thread in threads {
+            received.extend(thread.join().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (1 .. MSGS).collect::<Vec<_>>());
+
+        let (sender, receiver) = mpsc::create();
+        drop(receiver.into_mpmc());
+        assert_eq!(sender.send(5).unwrap_err().into_message(), 5);
+    }
+
+    #[test]
+    fn recycled_nodes_drop_messages() {
+        let item = Arc::new(());
+        let (sender, mut receiver) = mpsc::create();
+        let mut buf = Vec::new();
+        for _ in 0 .. 100 {
+            for _ in 0 .. 4 {
+                sender.send(item.clone()).unwrap();
+            }
+            assert_eq!(receiver.recv_many(&mut buf, 2), Ok(2));
+            assert_eq!(receiver.recv(), Ok(item.clone()));
+            assert_eq!(receiver.recv(), Ok(item.clone()));
+            buf.clear();
+            assert_eq!(Arc::strong_count(&item), 1);
+        }
+        sender.send(item.clone()).unwrap();
+        let receiver = receiver.into_mpmc();
+        sender.send(item.clone()).unwrap();
+        assert!(receiver.recv().is_ok());
+        drop((sender, receiver));
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
 }
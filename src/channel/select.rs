@@ -0,0 +1,266 @@
+use std::{fmt, thread};
+use wait::{WaitList, Waiter};
+
+/// Receivers which can be registered in a [`Select`]. Implemented by the
+/// receivers of the SPSC, MPSC and MPMC channels.
+pub trait Selectable {
+    /// Tests whether `recv` would return right now without
+    /// [`NoMessage`](super::RecvErr::NoMessage), i.e. a message is available
+    /// or the senders disconnected.
+    fn is_ready(&self) -> bool;
+
+    #[doc(hidden)]
+    fn wait_list(&self) -> &WaitList;
+}
+
+/// A registry of receivers, possibly of different channels and message types,
+/// which blocks the current thread until any of them is ready to receive.
+///
+/// Since receiving from some receivers requires mutability, the registry is
+/// meant to be short-lived: build it, find out which receiver is ready, and
+/// drop it before receiving.
+///
+/// # Example
+/// ```rust
+/// use lockfree::channel::{mpsc, select::Select, spsc};
+/// use std::thread;
+///
+/// let (mut numbers, mut numbers_rx) = spsc::create::<u64>();
+/// let (names, mut names_rx) = mpsc::create::<String>();
+///
+/// thread::spawn(move || {
+///     numbers.send(42).unwrap();
+///     names.send("answer".to_owned()).unwrap();
+/// });
+///
+/// let mut received = 0;
+/// while received < 2 {
+///     let index = {
+///         let mut select = Select::new();
+///         select.add(&numbers_rx);
+///         select.add(&names_rx);
+///         select.ready()
+///     };
+///     match index {
+///         0 => received += numbers_rx.try_iter().count(),
+///         _ => received += names_rx.try_iter().count(),
+///     }
+/// }
+/// ```
+pub struct Select<'receiver> {
+    receivers: Vec<&'receiver dyn Selectable>,
+}
+
+impl<'receiver> Select<'receiver> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self { receivers: Vec::new() }
+    }
+
+    /// Registers a receiver and returns its index, which is the number of
+    /// receivers registered before it.
+    pub fn add<R>(&mut self, receiver: &'receiver R) -> usize
+    where
+        R: Selectable,
+    {
+        self.receivers.push(receiver);
+        self.receivers.len() - 1
+    }
+
+    /// Returns the index of the first ready receiver, if any, without
+    /// blocking.
+    pub fn try_ready(&self) -> Option<usize> {
+        self.receivers.iter().position(|receiver| receiver.is_ready())
+    }
+
+    /// Blocks the current thread until any receiver is ready, and returns its
+    /// index. The thread is parked rather than spinning, and it is woken up by
+    /// the senders. Note that a message of a MPMC channel might be taken by
+    /// another receiver before this one receives it.
+    ///
+    /// # Panics
+    /// Panics if no receiver was registered, since it would block forever.
+    pub fn ready(&self) -> usize {
+        assert!(!self.receivers.is_empty(), "no receivers to select from");
+
+        loop {
+            if let Some(index) = self.try_ready() {
+                break index;
+            }
+
+            let waiters = self
+                .receivers
+                .iter()
+                .map(|receiver| receiver.wait_list().register())
+                .collect::<Vec<_>>();
+            // Checking again because a send might have happened before we
+            // registered ourselves.
+            let found = self.try_ready();
+
+            if found.is_none() {
+                while !waiters.iter().any(Waiter::is_notified) {
+                    thread::park();
+                }
+            }
+
+            for (waiter, receiver) in waiters.into_iter().zip(&self.receivers) {
+                waiter.cancel(receiver.wait_list());
+            }
+
+            if let Some(index) = found {
+                break index;
+            }
+        }
+    }
+
+    /// The number of registered receivers.
+    pub fn len(&self) -> usize {
+        self.receivers.len()
+    }
+
+    /// Tests whether no receiver was registered.
+    pub fn is_empty(&self) -> bool {
+        self.receivers.is_empty()
+    }
+}
+
+impl<'receiver> Default for Select<'receiver> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'receiver> fmt::Debug for Select<'receiver> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Select {} receivers: {} {}", '{', self.len(), '}')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use channel::{mpmc, mpsc, spsc};
+    use std::time::Duration;
+
+    #[test]
+    fn try_ready_finds_first() {
+        let (mut sender_a, receiver_a) = spsc::create::<u8>();
+        let (sender_b, receiver_b) = mpmc::create::<&str>();
+        let mut select = Select::new();
+        assert_eq!(select.add(&receiver_a), 0);
+        assert_eq!(select.add(&receiver_b), 1);
+        assert_eq!(select.try_ready(), None);
+
+        sender_b.send("b").unwrap();
+        assert_eq!(select.try_ready(), Some(1));
+        sender_a.send(1).unwrap();
+        assert_eq!(select.try_ready(), Some(0));
+        assert_eq!(select.ready(), 0);
+    }
+
+    #[test]
+    fn disconnection_is_ready() {
+        let (sender, mut receiver) = mpsc::create::<u8>();
+        drop(sender);
+        let index = {
+            let mut select = Select::new();
+            select.add(&receiver);
+            select.ready()
+        };
+        assert_eq!(index, 0);
+        assert_eq!(receiver.recv(), Err(mpsc::NoSender));
+    }
+
+    #[test]
+    fn ready_leaves_no_waiters_behind() {
+        const MSGS: usize = 1000;
+
+        let (mut spsc_tx, mut spsc_rx) = spsc::create::<usize>();
+        let (_mpsc_tx, mpsc_rx) = mpsc::create::<usize>();
+
+        let producer = thread::spawn(move || {
+            for i in 0 .. MSGS {
+                if i % 100 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                spsc_tx.send(i).unwrap();
+            }
+        });
+
+        for i in 0 .. MSGS {
+            let index = {
+                let mut select = Select::new();
+                select.add(&spsc_rx);
+                select.add(&mpsc_rx);
+                select.ready()
+            };
+            assert_eq!(index, 0);
+            assert_eq!(spsc_rx.recv(), Ok(i));
+        }
+        producer.join().expect("thread failed");
+        // The idle receiver is never notified, so its cancelled waiters must
+        // not pile up.
+        assert_eq!(mpsc_rx.wait_list().registered(), 0);
+    }
+
+    #[test]
+    fn ready_woken_up_by_senders() {
+        const MSGS: usize = 300;
+
+        let (mut spsc_tx, mut spsc_rx) = spsc::create::<usize>();
+        let (mpsc_tx, mut mpsc_rx) = mpsc::create::<usize>();
+        let (mpmc_tx, mpmc_rx) = mpmc::create::<usize>();
+
+        let producer = thread::spawn(move || {
+            // Giving the consumer time to block.
+            thread::sleep(Duration::from_millis(20));
+            for i in 0 .. MSGS {
+                if i % 50 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                match i % 3 {
+                    0 => spsc_tx.send(i).unwrap(),
+                    1 => mpsc_tx.send(i).unwrap(),
+                    _ => mpmc_tx.send(i).unwrap(),
+                }
+            }
+        });
+
+        let mut received = Vec::with_capacity(MSGS);
+        let mut disconnected = [false; 3];
+        while disconnected.iter().any(|&done| !done) {
+            // Disconnected receivers are always ready, so they are left out.
+            let mut channels = Vec::with_capacity(3);
+            let index = {
+                let mut select = Select::new();
+                if !disconnected[0] {
+                    select.add(&spsc_rx);
+                    channels.push(0);
+                }
+                if !disconnected[1] {
+                    select.add(&mpsc_rx);
+                    channels.push(1);
+                }
+                if !disconnected[2] {
+                    select.add(&mpmc_rx);
+                    channels.push(2);
+                }
+                channels[select.ready()]
+            };
+            let res = match index {
+                0 => spsc_rx.recv(),
+                1 => mpsc_rx.recv(),
+                _ => mpmc_rx.recv(),
+            };
+            match res {
+                Ok(i) => received.push(i),
+                Err(spsc::NoSender) => disconnected[index] = true,
+                Err(spsc::NoMessage) => unreachable!(),
+            }
+        }
+
+        producer.join().expect("thread failed");
+        received.sort();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,763 @@
+pub use channel::{
+    RecvErr::{self, *},
+    SendErr::{self, *},
+};
+use collections::Collection;
+use queue::Bounded;
+use std::{
+    fmt,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicUsize, Ordering::*},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use wait::WaitList;
+
+/// Creates a bounded lock-free Single-Producer-Multi-Consumer (SPMC) channel,
+/// able to hold at least the given number of messages. Just like
+/// [`Bounded`](::queue::Bounded), the capacity is rounded up to a power of
+/// two, and it is at least two, so `create(1)` holds two messages. In order to
+/// allow multiple consumers, [`Receiver`] is clonable and does not require
+/// mutability.
+///
+/// A capacity of zero creates a rendezvous channel: [`send`](Sender::send)
+/// only completes once a receiver took the message, which enforces a strict
+/// handoff between the sender and the receivers. A receiver blocked in
+/// [`recv_wait`](Receiver::recv_wait) is a partner for the handoff, so
+/// [`try_send`](Sender::try_send) succeeds while one of them is waiting.
+///
+/// # Design
+/// Messages are kept in a ring buffer allocated at creation. When it is full,
+/// [`try_send`](Sender::try_send) fails and [`send`](Sender::send) parks the
+/// sender until a receiver takes a message, so stalled consumers slow the
+/// producer down instead of making the channel grow. A rendezvous channel
+/// holds a single message at a time, and the sender waits for it to be taken.
+pub fn create<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        buf: Bounded::with_capacity(capacity),
+        rendezvous: capacity == 0,
+        taken: AtomicUsize::new(0),
+        sender_connected: AtomicBool::new(true),
+        receivers: AtomicUsize::new(1),
+        waiters: WaitList::new(),
+        recv_waiters: WaitList::new(),
+        waiting: AtomicUsize::new(0),
+    });
+
+    (Sender { inner: inner.clone(), sent: 0 }, Receiver { inner })
+}
+
+/// The [`Sender`] handle of a bounded SPMC channel. Created by [`create`]
+/// function.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+    // Messages sent so far, in rendezvous mode.
+    sent: usize,
+}
+
+impl<T> Sender<T> {
+    /// Tries to send a message without blocking. If the channel is full,
+    /// [`Err`]`(`[`SendErr::Full`]`)` is returned. If all receivers
+    /// disconnected, [`Err`]`(`[`SendErr::Disconnected`]`)` is returned. A
+    /// rendezvous channel is full for this method unless a receiver is blocked
+    /// in [`recv_wait`](Receiver::recv_wait). In that case, the message is
+    /// handed off to it, and this method waits until it was taken.
+    pub fn try_send(&mut self, message: T) -> Result<(), SendErr<T>> {
+        if !self.is_connected() {
+            return Err(SendErr::Disconnected(message));
+        }
+
+        if self.inner.rendezvous {
+            if self.inner.waiting.load(SeqCst) == 0 {
+                return Err(SendErr::Full(message));
+            }
+            return self.hand_off(message, Patience::WhilePartner);
+        }
+
+        self.inner.buf.try_push(message).map_err(SendErr::Full)?;
+        self.wake_receiver();
+        Ok(())
+    }
+
+    /// Sends a message, blocking the current thread while the channel is full.
+    /// The thread is parked rather than spinning, and it is woken up by
+    /// [`recv`](Receiver::recv). If all receivers disconnected,
+    /// [`Err`]`(`[`SendErr::Disconnected`]`)` is returned, which is the only
+    /// possible error. In a rendezvous channel, this method also blocks until
+    /// a receiver took the message.
+    pub fn send(&mut self, mut message: T) -> Result<(), SendErr<T>> {
+        if self.inner.rendezvous {
+            // Without a deadline, the handoff only fails on disconnection.
+            return self.hand_off(message, Patience::Forever);
+        }
+
+        loop {
+            match self.try_send(message) {
+                Ok(()) => break Ok(()),
+
+                Err(err @ SendErr::Disconnected(_)) => break Err(err),
+
+                Err(SendErr::Full(back)) => message = back,
+            }
+
+            let waiter = self.inner.waiters.register();
+            // Checking again because a receiver might have made room before we
+            // registered ourselves.
+            match self.try_send(message) {
+                Ok(()) => {
+                    waiter.cancel(&self.inner.waiters);
+                    break Ok(());
+                },
+
+                Err(err @ SendErr::Disconnected(_)) => {
+                    waiter.cancel(&self.inner.waiters);
+                    break Err(err);
+                },
+
+                Err(SendErr::Full(back)) => {
+                    message = back;
+                    waiter.wait();
+                },
+            }
+        }
+    }
+
+    /// Sends a message, blocking the current thread while the channel is full,
+    /// but only until the given duration elapses. In that case,
+    /// [`Err`]`(`[`SendErr::Full`]`)` is returned, giving the message back.
+    /// If all receivers disconnected, [`Err`]`(`[`SendErr::Disconnected`]`)`
+    /// is returned. Just like [`send`](Sender::send), the thread is parked
+    /// rather than spinning, and in a rendezvous channel a receiver must take
+    /// the message within the duration.
+    pub fn send_timeout(
+        &mut self,
+        mut message: T,
+        dur: Duration,
+    ) -> Result<(), SendErr<T>> {
+        let deadline = Instant::now() + dur;
+
+        if self.inner.rendezvous {
+            return self.hand_off(message, Patience::Until(deadline));
+        }
+
+        loop {
+            match self.try_send(message) {
+                Err(SendErr::Full(back)) => message = back,
+                res => break res,
+            }
+            if Instant::now() >= deadline {
+                break Err(SendErr::Full(message));
+            }
+
+            let waiter = self.inner.waiters.register();
+            // Checking again because a receiver might have made room before we
+            // registered ourselves.
+            match self.try_send(message) {
+                Err(SendErr::Full(back)) => message = back,
+
+                res => {
+                    waiter.cancel(&self.inner.waiters);
+                    break res;
+                },
+            }
+
+            if !waiter.wait_until(deadline) {
+                waiter.cancel(&self.inner.waiters);
+                // A last try, since a receiver might have made room right
+                // before we gave up.
+                break self.try_send(message);
+            }
+        }
+    }
+
+    /// The number of messages the channel can hold. It is zero for a
+    /// rendezvous channel.
+    pub fn capacity(&self) -> usize {
+        if self.inner.rendezvous {
+            0
+        } else {
+            self.inner.buf.capacity()
+        }
+    }
+
+    /// Tests if there are any [`Receiver`]s still connected. There are no
+    /// guarantees that [`send`](Sender::send) will succeed if this method
+    /// returns `true` because the [`Receiver`] may disconnect meanwhile.
+    pub fn is_connected(&self) -> bool {
+        self.inner.receivers.load(Acquire) > 0
+    }
+
+    fn hand_off(
+        &mut self,
+        message: T,
+        patience: Patience,
+    ) -> Result<(), SendErr<T>> {
+        if !self.is_connected() {
+            return Err(SendErr::Disconnected(message));
+        }
+
+        // The single slot is empty, since the previous message was taken.
+        if let Err(message) = self.inner.buf.try_push(message) {
+            return Err(SendErr::Disconnected(message));
+        }
+        self.sent = self.sent.wrapping_add(1);
+        self.wake_receiver();
+
+        loop {
+            if let Some(res) = self.try_finish_hand_off() {
+                break res;
+            }
+
+            let waiter = self.inner.waiters.register();
+            // Checking again because a receiver might have taken the message
+            // before we registered ourselves.
+            if let Some(res) = self.try_finish_hand_off() {
+                waiter.cancel(&self.inner.waiters);
+                break res;
+            }
+
+            match patience {
+                Patience::Forever => waiter.wait(),
+
+                Patience::Until(deadline) => {
+                    if !waiter.wait_until(deadline) {
+                        waiter.cancel(&self.inner.waiters);
+                        break self.give_up_hand_off();
+                    }
+                },
+
+                Patience::WhilePartner => {
+                    // The receivers which could take the message right away
+                    // are gone.
+                    if self.inner.waiting.load(SeqCst) == 0 {
+                        waiter.cancel(&self.inner.waiters);
+                        break self.give_up_hand_off();
+                    }
+                    waiter.wait();
+                },
+            }
+        }
+    }
+
+    fn wake_receiver(&self) {
+        // Pushing is not a `SeqCst` operation, so the wait list needs a fence.
+        fence(SeqCst);
+        self.inner.recv_waiters.notify_one();
+    }
+
+    fn give_up_hand_off(&mut self) -> Result<(), SendErr<T>> {
+        if let Some(res) = self.try_finish_hand_off() {
+            return res;
+        }
+
+        // Let's take the message back. If it is not there, a receiver is just
+        // about to mark it as taken, so the handoff succeeded.
+        match self.inner.buf.pop() {
+            Some(message) => {
+                self.sent = self.sent.wrapping_sub(1);
+                Err(SendErr::Full(message))
+            },
+
+            None => Ok(()),
+        }
+    }
+
+    fn try_finish_hand_off(&mut self) -> Option<Result<(), SendErr<T>>> {
+        if self.inner.taken.load(Acquire) == self.sent {
+            return Some(Ok(()));
+        }
+
+        if self.is_connected() {
+            return None;
+        }
+
+        // Nobody will take the message anymore, so let's take it back. If it
+        // is not there, a receiver is just about to mark it as taken.
+        self.inner.buf.pop().map(|message| {
+            self.sent = self.sent.wrapping_sub(1);
+            Err(SendErr::Disconnected(message))
+        })
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // `SeqCst` because of the wait list.
+        self.inner.sender_connected.store(false, SeqCst);
+        // Receivers blocked in `recv_wait` must see the disconnection.
+        self.inner.recv_waiters.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "spmc::bounded::Sender {} ptr: {:p} {}",
+            '{', self.inner, '}'
+        )
+    }
+}
+
+/// The [`Receiver`] handle of a bounded SPMC channel. Created by [`create`]
+/// function. It is clonable and does not require mutability.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Tries to receive a message. If no message is available,
+    /// [`Err`]`(`[`RecvErr::NoMessage`]`)` is returned. If the sender
+    /// disconnected, [`Err`]`(`[`RecvErr::NoSender`]`)` is returned. See
+    /// [`recv_wait`](Receiver::recv_wait) for a blocking receive.
+    pub fn recv(&self) -> Result<T, RecvErr> {
+        if let Some(message) = self.inner.buf.pop() {
+            if self.inner.rendezvous {
+                self.inner.taken.fetch_add(1, Release);
+            }
+            // There is room now. Let's wake up the sender if it is blocked.
+            // Popping is not a `SeqCst` operation, so the wait list needs a
+            // fence.
+            fence(SeqCst);
+            self.inner.waiters.notify_one();
+            return Ok(message);
+        }
+
+        if self.inner.sender_connected.load(Acquire) {
+            return Err(RecvErr::NoMessage);
+        }
+
+        // The sender might have sent messages right before disconnecting.
+        self.inner.buf.pop().ok_or(RecvErr::NoSender)
+    }
+
+    /// Receives a message, blocking the current thread until one is
+    /// available. The thread is parked rather than spinning, and it is woken
+    /// up by the [`Sender`]. [`None`] is returned only when the sender
+    /// disconnected and there are no messages left. In a rendezvous channel,
+    /// a receiver blocked here is a partner for
+    /// [`try_send`](Sender::try_send).
+    pub fn recv_wait(&self) -> Option<T> {
+        // `SeqCst` because the sender checks it while registered in the wait
+        // list.
+        self.inner.waiting.fetch_add(1, SeqCst);
+        let res = self.wait_message();
+        self.inner.waiting.fetch_sub(1, SeqCst);
+        if self.inner.rendezvous {
+            // The sender might be waiting on us to take its message, but we
+            // might have taken another one.
+            self.inner.waiters.notify_one();
+        }
+        res
+    }
+
+    /// Creates an iterator which receives messages, blocking the current
+    /// thread until each one is available, just like
+    /// [`recv_wait`](Receiver::recv_wait). It stops only when the sender
+    /// disconnected and there are no messages left.
+    pub fn iter<'receiver>(&'receiver self) -> Iter<'receiver, T> {
+        Iter { receiver: self }
+    }
+
+    /// Tests if the [`Sender`] is still connected. There are no guarantees
+    /// that [`recv`](Receiver::recv) will succeed if this method returns
+    /// `true` because the [`Receiver`] may disconnect meanwhile. This method
+    /// may also return `true` if the [`Sender`] disconnected but there are
+    /// messages pending in the buffer. Note that another [`Receiver`] may pop
+    /// out the pending messages after this method was called.
+    pub fn is_connected(&self) -> bool {
+        self.inner.sender_connected.load(Acquire) || self.inner.buf.len() > 0
+    }
+
+    /// Counts the messages pending in the buffer, without receiving them.
+    /// Since the [`Sender`] may send and other [`Receiver`]s may receive
+    /// meanwhile, this is only an estimate.
+    pub fn len(&self) -> usize {
+        self.inner.buf.len()
+    }
+
+    /// Tests whether there are no messages pending in the buffer. Since the
+    /// [`Sender`] may send and other [`Receiver`]s may receive meanwhile, this
+    /// is only an estimate.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn wait_message(&self) -> Option<T> {
+        loop {
+            match self.recv() {
+                Ok(message) => break Some(message),
+                Err(RecvErr::NoSender) => break None,
+                Err(RecvErr::NoMessage) => (),
+            }
+
+            let waiter = self.inner.recv_waiters.register();
+            // Checking again because a send might have happened before we
+            // registered ourselves.
+            match self.recv() {
+                Ok(message) => {
+                    waiter.cancel(&self.inner.recv_waiters);
+                    break Some(message);
+                },
+
+                Err(RecvErr::NoSender) => {
+                    waiter.cancel(&self.inner.recv_waiters);
+                    break None;
+                },
+
+                Err(RecvErr::NoMessage) => waiter.wait(),
+            }
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.inner.receivers.fetch_add(1, Relaxed);
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // `SeqCst` because of the wait list.
+        if self.inner.receivers.fetch_sub(1, SeqCst) == 1 {
+            // The sender might be blocked waiting for room.
+            self.inner.waiters.notify_one();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "spmc::bounded::Receiver {} ptr: {:p} {}",
+            '{', self.inner, '}'
+        )
+    }
+}
+
+impl<'receiver, T> IntoIterator for &'receiver Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'receiver, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { receiver: self }
+    }
+}
+
+/// A blocking iterator over the messages of a [`Receiver`]. Created by
+/// [`iter`](Receiver::iter).
+pub struct Iter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for Iter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for Iter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Iter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// A blocking iterator which owns a [`Receiver`]. Created by its
+/// [`IntoIterator`] implementation.
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<T> fmt::Debug for IntoIter<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "IntoIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+struct Inner<T> {
+    buf: Bounded<T>,
+    rendezvous: bool,
+    // Messages taken so far, in rendezvous mode.
+    taken: AtomicUsize,
+    sender_connected: AtomicBool,
+    receivers: AtomicUsize,
+    // Only the sender waits here.
+    waiters: WaitList,
+    // Receivers blocked in `recv_wait` wait here.
+    recv_waiters: WaitList,
+    // Receivers in `recv_wait`, which are partners for a rendezvous handoff.
+    waiting: AtomicUsize,
+}
+
+// How long a rendezvous sender waits for its message to be taken.
+enum Patience {
+    Forever,
+    Until(Instant),
+    // While there are receivers blocked in `recv_wait`.
+    WhilePartner,
+}
+
+#[cfg(test)]
+mod test {
+    use channel::spmc::bounded;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn try_send_when_full() {
+        assert_eq!(bounded::create::<u8>(1).0.capacity(), 2);
+        let (mut sender, receiver) = bounded::create(3);
+        assert_eq!(sender.capacity(), 4);
+        for i in 0 .. 4 {
+            sender.try_send(i).unwrap();
+        }
+        assert_eq!(sender.try_send(4), Err(bounded::SendErr::Full(4)));
+        assert_eq!(receiver.recv(), Ok(0));
+        sender.try_send(4).unwrap();
+
+        drop(sender);
+        assert!(receiver.is_connected());
+        for i in 1 .. 5 {
+            assert_eq!(receiver.recv(), Ok(i));
+        }
+        assert_eq!(receiver.recv(), Err(bounded::NoSender));
+        assert!(!receiver.is_connected());
+
+        let (mut sender, receiver) = bounded::create(1);
+        drop(receiver);
+        assert_eq!(sender.try_send(1), Err(bounded::SendErr::Disconnected(1)));
+        assert_eq!(sender.send(1), Err(bounded::SendErr::Disconnected(1)));
+    }
+
+    #[test]
+    fn send_blocks_until_room() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 2000;
+
+        let (mut sender, receiver) = bounded::create::<usize>(8);
+        let mut threads = Vec::with_capacity(THREADS);
+
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::new();
+                loop {
+                    match receiver.recv() {
+                        Ok(i) => received.push(i),
+                        Err(bounded::NoMessage) => thread::yield_now(),
+                        Err(bounded::NoSender) => break received,
+                    }
+                }
+            }));
+        }
+        drop(receiver);
+
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let mut received = Vec::with_capacity(MSGS);
+        for thread in threads {
+            let part = thread.join().unwrap();
+            // A single consumer must see messages in order.
+            assert!(part.windows(2).all(|pair| pair[0] < pair[1]));
+            received.extend(part);
+        }
+        received.sort();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn recv_wait_woken_up_by_sender() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 2000;
+
+        let (mut sender, receiver) = bounded::create::<usize>(8);
+        let mut threads = Vec::with_capacity(THREADS);
+
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::new();
+                while let Some(i) = receiver.recv_wait() {
+                    received.push(i);
+                }
+                received
+            }));
+        }
+        drop(receiver);
+
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let mut received = Vec::with_capacity(MSGS);
+        for thread in threads {
+            received.extend(thread.join().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn recv_wait_ends_when_sender_leaves() {
+        let (mut sender, receiver) = bounded::create(2);
+        let thread = thread::spawn(move || {
+            let first = receiver.recv_wait();
+            (first, receiver.recv_wait())
+        });
+        thread::sleep(Duration::from_millis(10));
+        sender.send(1).unwrap();
+        drop(sender);
+        assert_eq!(thread.join().unwrap(), (Some(1), None));
+    }
+
+    #[test]
+    fn iter_ends_when_sender_leaves() {
+        let (mut sender, receiver) = bounded::create(4);
+        let thread = thread::spawn(move || receiver.into_iter().sum::<u32>());
+        for i in 1 .. 11 {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+        assert_eq!(thread.join().unwrap(), 55);
+
+        let (mut sender, receiver) = bounded::create(0);
+        let thread =
+            thread::spawn(move || receiver.iter().collect::<Vec<_>>());
+        for i in 0 .. 3 {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+        assert_eq!(thread.join().unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn send_fails_if_receivers_leave() {
+        let (mut sender, receiver) = bounded::create(2);
+        sender.send(0).unwrap();
+        sender.send(1).unwrap();
+        let thread = thread::spawn(move || {
+            let res = sender.send(2);
+            res.unwrap_err().into_message()
+        });
+        drop(receiver);
+        assert_eq!(thread.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn rendezvous_hands_off() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 500;
+
+        let (mut sender, receiver) = bounded::create::<usize>(0);
+        assert_eq!(sender.capacity(), 0);
+        assert_eq!(sender.try_send(0), Err(bounded::SendErr::Full(0)));
+        let mut threads = Vec::with_capacity(THREADS);
+
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::new();
+                while let Some(i) = receiver.recv_wait() {
+                    received.push(i);
+                }
+                received
+            }));
+        }
+
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+            // The message was taken before `send` returned.
+            assert!(!receiver.is_connected() || receiver.recv().is_err());
+        }
+        drop(sender);
+
+        let mut received = Vec::with_capacity(MSGS);
+        for thread in threads {
+            received.extend(thread.join().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rendezvous_try_send_to_waiting_receiver() {
+        let (mut sender, receiver) = bounded::create(0);
+        let thread = thread::spawn(move || receiver.recv_wait());
+
+        let mut message = 1;
+        // Only fails until the receiver is blocked waiting.
+        while let Err(bounded::Full(back)) = sender.try_send(message) {
+            message = back;
+            thread::yield_now();
+        }
+        assert_eq!(thread.join().unwrap(), Some(1));
+        assert_eq!(sender.try_send(2), Err(bounded::Disconnected(2)));
+    }
+
+    #[test]
+    fn rendezvous_gives_back() {
+        let (mut sender, receiver) = bounded::create(0);
+        let thread = thread::spawn(move || {
+            let res = sender.send(1);
+            res.unwrap_err().into_message()
+        });
+        drop(receiver);
+        assert_eq!(thread.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn send_timeout_gives_back() {
+        let (mut sender, receiver) = bounded::create(2);
+        let timeout = Duration::from_millis(10);
+        assert_eq!(sender.send_timeout(0, timeout), Ok(()));
+        assert_eq!(sender.send_timeout(1, timeout), Ok(()));
+        assert_eq!(sender.send_timeout(2, timeout), Err(bounded::Full(2)));
+
+        let thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(receiver.recv(), Ok(0));
+            receiver
+        });
+        assert_eq!(sender.send_timeout(2, Duration::from_secs(10)), Ok(()));
+        drop(thread.join().unwrap());
+        assert_eq!(
+            sender.send_timeout(3, timeout),
+            Err(bounded::Disconnected(3))
+        );
+
+        let (mut sender, receiver) = bounded::create(0);
+        assert_eq!(sender.send_timeout(4, timeout), Err(bounded::Full(4)));
+        let thread = thread::spawn(move || receiver.recv_wait());
+        assert_eq!(sender.send_timeout(5, Duration::from_secs(10)), Ok(()));
+        assert_eq!(thread.join().unwrap(), Some(5));
+    }
+}
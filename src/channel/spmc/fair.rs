@@ -0,0 +1,230 @@
+pub use channel::{
+    NoRecv,
+    RecvErr::{self, *},
+    SendErr::{self, *},
+};
+use channel::spsc;
+use std::fmt;
+
+/// Creates a fair lock-free Single-Producer-Multi-Consumer (SPMC) channel with
+/// the given number of receivers. Messages are distributed round-robin, so
+/// every receiver gets an even share of them, no matter how contended the
+/// channel is.
+///
+/// # Design
+/// Each receiver has its own SPSC sub-channel, and the sender sends to each of
+/// them in turn, skipping the disconnected ones. Since receivers do not share
+/// messages, a stalled receiver keeps its share pending instead of giving it
+/// to the others.
+pub fn create<T>(receivers: usize) -> (Sender<T>, Vec<Receiver<T>>) {
+    let mut senders = Vec::with_capacity(receivers);
+    let mut handles = Vec::with_capacity(receivers);
+
+    for _ in 0 .. receivers {
+        let (sender, receiver) = spsc::create();
+        senders.push(sender);
+        handles.push(Receiver { inner: receiver });
+    }
+
+    (Sender { inner: senders, next: 0 }, handles)
+}
+
+/// The [`Sender`] handle of a fair SPMC channel. Created by [`create`]
+/// function.
+pub struct Sender<T> {
+    inner: Vec<spsc::Sender<T>>,
+    // The receiver whose turn is next.
+    next: usize,
+}
+
+impl<T> Sender<T> {
+    /// Sends a message to the receiver whose turn it is. Disconnected
+    /// receivers lose their turn. If all receivers disconnected, an error is
+    /// returned.
+    pub fn send(&mut self, mut message: T) -> Result<(), SendErr<T>> {
+        for _ in 0 .. self.inner.len() {
+            let index = self.next;
+            self.next = (self.next + 1) % self.inner.len();
+            match self.inner[index].send(message) {
+                Ok(()) => return Ok(()),
+                Err(err) => message = err.into_message(),
+            }
+        }
+
+        Err(Disconnected(message))
+    }
+
+    /// Tests if there are any [`Receiver`]s still connected. There are no
+    /// guarantees that [`send`](Sender::send) will succeed if this method
+    /// returns `true` because the [`Receiver`] may disconnect meanwhile.
+    pub fn is_connected(&self) -> bool {
+        self.inner.iter().any(spsc::Sender::is_connected)
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "spmc::fair::Sender {} receivers: {}, next: {} {}",
+            '{',
+            self.inner.len(),
+            self.next,
+            '}'
+        )
+    }
+}
+
+/// The [`Receiver`] handle of a fair SPMC channel. Created by [`create`]
+/// function. It is not clonable, since each receiver has its own share of
+/// the messages.
+pub struct Receiver<T> {
+    inner: spsc::Receiver<T>,
+}
+
+impl<T> Receiver<T> {
+    /// Tries to receive a message sent to this receiver. If no message is
+    /// available, [`Err`]`(`[`RecvErr::NoMessage`]`)` is returned. If the
+    /// sender disconnected, [`Err`]`(`[`RecvErr::NoSender`]`)` is returned.
+    pub fn recv(&mut self) -> Result<T, RecvErr> {
+        self.inner.recv()
+    }
+
+    /// Receives a message sent to this receiver, blocking the current thread
+    /// until one is available. The thread is parked rather than spinning, and
+    /// it is woken up by [`send`](Sender::send). If the sender disconnected
+    /// and there are no messages left, `None` is returned.
+    pub fn recv_wait(&mut self) -> Option<T> {
+        self.inner.recv_wait()
+    }
+
+    /// Creates an iterator which receives the messages sent to this receiver,
+    /// blocking the current thread until each one is available, just like
+    /// [`recv_wait`](Receiver::recv_wait). It stops only when the sender
+    /// disconnected and there are no messages left.
+    pub fn iter<'receiver>(&'receiver mut self) -> spsc::Iter<'receiver, T> {
+        self.inner.iter()
+    }
+
+    /// Tests if the [`Sender`] is still connected. There are no guarantees
+    /// that [`recv`](Receiver::recv) will succeed if this method returns
+    /// `true` because the [`Receiver`] may disconnect meanwhile. This method
+    /// may also return `true` if the [`Sender`] disconnected but there are
+    /// messages pending for this receiver.
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    /// Counts the messages pending for this receiver, without receiving them.
+    /// Since the [`Sender`] may send meanwhile, this is only an estimate.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Tests whether there are no messages pending for this receiver. Since
+    /// the [`Sender`] may send meanwhile, this is only an estimate.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+impl<'receiver, T> IntoIterator for &'receiver mut Receiver<T> {
+    type Item = T;
+    type IntoIter = spsc::Iter<'receiver, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = spsc::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str("spmc::fair::Receiver")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use channel::spmc::fair;
+    use std::thread;
+
+    #[test]
+    fn even_distribution() {
+        const THREADS: usize = 4;
+        const MSGS_PER_THREAD: usize = 256;
+
+        let (mut sender, receivers) = fair::create::<usize>(THREADS);
+        let mut threads = Vec::with_capacity(THREADS);
+
+        for mut receiver in receivers {
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::new();
+                loop {
+                    match receiver.recv() {
+                        Ok(i) => received.push(i),
+                        Err(fair::NoMessage) => thread::yield_now(),
+                        Err(fair::NoSender) => break received,
+                    }
+                }
+            }));
+        }
+
+        for i in 0 .. THREADS * MSGS_PER_THREAD {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        for (i, thread) in threads.into_iter().enumerate() {
+            let received = thread.join().unwrap();
+            let expected = (0 .. MSGS_PER_THREAD)
+                .map(|j| j * THREADS + i)
+                .collect::<Vec<_>>();
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn skips_disconnected() {
+        let (mut sender, mut receivers) = fair::create(3);
+        drop(receivers.remove(1));
+        for i in 0 .. 4 {
+            sender.send(i).unwrap();
+        }
+        assert!(sender.is_connected());
+        assert_eq!(receivers[0].recv(), Ok(0));
+        assert_eq!(receivers[1].recv(), Ok(1));
+        assert_eq!(receivers[0].recv(), Ok(2));
+        assert_eq!(receivers[1].recv(), Ok(3));
+
+        receivers.clear();
+        assert!(!sender.is_connected());
+        assert_eq!(sender.send(4).unwrap_err().into_message(), 4);
+
+        let (mut sender, _) = fair::create(0);
+        assert_eq!(sender.send(5).unwrap_err().into_message(), 5);
+    }
+
+    #[test]
+    fn iterators() {
+        let (mut sender, mut receivers) = fair::create(2);
+        let mut second = receivers.pop().unwrap();
+        let first = receivers.pop().unwrap();
+        let thread =
+            thread::spawn(move || first.into_iter().collect::<Vec<_>>());
+        for i in 0 .. 6 {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+        assert_eq!(second.iter().collect::<Vec<_>>(), [1, 3, 5]);
+        assert_eq!(thread.join().unwrap(), [0, 2, 4]);
+    }
+}
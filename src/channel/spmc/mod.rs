@@ -1,6 +1,7 @@
 pub use super::{
     NoRecv,
     RecvErr::{self, *},
+    SendErr::{self, *},
 };
 use incin::Pause;
 use owned_alloc::OwnedAlloc;
@@ -14,6 +15,14 @@ use std::{
         Arc,
     },
 };
+use wait::WaitList;
+
+/// A bounded variant of the channel, which gives backpressure to the sender.
+pub mod bounded;
+
+/// A fair variant of the channel, which distributes messages round-robin
+/// among a fixed set of receivers.
+pub mod fair;
 
 /// Creates an asynchronous lock-free Single-Producer-Multi-Consumer (SPMC)
 /// channel. In order to allow multiple consumers, [`Receiver`] is clonable and
@@ -23,7 +32,8 @@ pub fn create<T>() -> (Sender<T>, Receiver<T>) {
 }
 
 /// Same as [`create`], but use a passed incinerator instead of creating a new
-/// one.
+/// one. An incinerator created by [`SharedIncin::with_batch`] reclaims nodes
+/// less frequently, at the cost of memory.
 pub fn with_incin<T>(incin: SharedIncin<T>) -> (Sender<T>, Receiver<T>) {
     check_null_align::<Node<T>>();
 
@@ -35,11 +45,13 @@ pub fn with_incin<T>(incin: SharedIncin<T>) -> (Sender<T>, Receiver<T>) {
     let single_node = alloc.into_raw();
 
     // Then put it on back and on the front.
-    let sender = Sender { back: single_node };
+    let waiters = Arc::new(WaitList::new());
+    let sender = Sender { back: single_node, waiters: waiters.clone() };
     let receiver = Receiver {
         inner: Arc::new(ReceiverInner {
             front: AtomicPtr::new(single_node.as_ptr()),
             incin,
+            waiters,
         }),
     };
 
@@ -50,11 +62,13 @@ pub fn with_incin<T>(incin: SharedIncin<T>) -> (Sender<T>, Receiver<T>) {
 /// [`with_incin`] function.
 pub struct Sender<T> {
     back: NonNull<Node<T>>,
+    waiters: Arc<WaitList>,
 }
 
 impl<T> Sender<T> {
     /// Sends a message and if the receiver disconnected, an error is returned.
-    pub fn send(&mut self, message: T) -> Result<(), NoRecv<T>> {
+    /// A receiver waiting in [`recv_wait`](Receiver::recv_wait) is woken up.
+    pub fn send(&mut self, message: T) -> Result<(), SendErr<T>> {
         // First we allocate the node for our message.
         let alloc = OwnedAlloc::new(Node {
             message: Removable::new(message),
@@ -69,11 +83,11 @@ impl<T> Sender<T> {
         let res = unsafe {
             // We try to update the back's next pointer. We want to catch any
             // bit marking here. A marked lower bit means the receiver
-            // disconnected.
+            // disconnected. `SeqCst` so a waiting receiver is notified.
             self.back.as_ref().next.compare_exchange(
                 null_mut(),
                 nnptr.as_ptr(),
-                Release,
+                SeqCst,
                 Relaxed,
             )
         };
@@ -82,6 +96,7 @@ impl<T> Sender<T> {
             // If we succeeded, let's update the back so we keep the invariant
             // "the back has a single node".
             self.back = nnptr;
+            self.waiters.notify_one();
             Ok(())
         } else {
             // If we failed, receiver disconnected. It is safe to dealloc
@@ -89,7 +104,7 @@ impl<T> Sender<T> {
             // it with anyone (cas failed).
             let mut alloc = unsafe { OwnedAlloc::from_raw(nnptr) };
             let message = alloc.message.replace(None).unwrap();
-            Err(NoRecv { message })
+            Err(Disconnected(message))
         }
     }
 
@@ -118,13 +133,16 @@ impl<T> Drop for Sender<T> {
             self.back
                 .as_ref()
                 .next
-                .swap((null_mut::<Node<T>>() as usize | 1) as *mut _, Relaxed)
+                .swap((null_mut::<Node<T>>() as usize | 1) as *mut _, SeqCst)
         };
 
         // If the previously stored value was not null, receiver has already
         // disconnected. It is safe to drop because we are the only ones that
         // have a pointer to the node.
-        if !res.is_null() {
+        if res.is_null() {
+            // Every waiting receiver must see the disconnection.
+            self.waiters.notify_all();
+        } else {
             unsafe { OwnedAlloc::from_raw(self.back) };
         }
     }
@@ -184,6 +202,55 @@ impl<T> Receiver<T> {
         }
     }
 
+    /// Receives a message, blocking the current thread until one is
+    /// available. The thread is parked rather than spinning, and it is woken
+    /// up by [`send`](Sender::send). If the sender disconnected and there are
+    /// no messages left, `None` is returned.
+    ///
+    /// Another [`Receiver`] may take a message sent while this one waits, in
+    /// which case this one keeps waiting.
+    pub fn recv_wait(&self) -> Option<T> {
+        loop {
+            match self.recv() {
+                Ok(message) => break Some(message),
+                Err(RecvErr::NoSender) => break None,
+                Err(RecvErr::NoMessage) => (),
+            }
+
+            let waiter = self.inner.waiters.register();
+            // Checking again because a send might have happened before we
+            // registered ourselves.
+            match self.recv() {
+                Ok(message) => {
+                    waiter.cancel(&self.inner.waiters);
+                    break Some(message);
+                },
+
+                Err(RecvErr::NoSender) => {
+                    waiter.cancel(&self.inner.waiters);
+                    break None;
+                },
+
+                Err(RecvErr::NoMessage) => waiter.wait(),
+            }
+        }
+    }
+
+    /// Creates an iterator which receives messages, blocking the current
+    /// thread until each one is available, just like
+    /// [`recv_wait`](Receiver::recv_wait). It stops only when the sender
+    /// disconnected and there are no messages left.
+    pub fn iter<'receiver>(&'receiver self) -> Iter<'receiver, T> {
+        Iter { receiver: self }
+    }
+
+    /// Creates an iterator which receives the messages available right now,
+    /// without blocking. It stops when there are no messages or when the
+    /// sender disconnected.
+    pub fn try_iter<'receiver>(&'receiver self) -> TryIter<'receiver, T> {
+        TryIter { receiver: self }
+    }
+
     /// Tests if there are any [`Sender`]s still connected. There are no
     /// guarantees that [`recv`](Receiver::recv) will succeed if this method
     /// returns `true` because the [`Receiver`] may disconnect meanwhile.
@@ -201,6 +268,37 @@ impl<T> Receiver<T> {
             || front.next.load(Relaxed) as usize & 1 == 0
     }
 
+    /// Counts the messages pending in the channel, without receiving them.
+    /// Since the [`Sender`] may send and other [`Receiver`]s may receive
+    /// meanwhile, this is only an estimate. This operation is linear on the
+    /// number of pending messages.
+    pub fn len(&self) -> usize {
+        // Pausing because we will walk through shared nodes.
+        let _pause = self.inner.incin.inner.pause();
+        let mut count = 0;
+        let mut node_ptr = self.inner.front.load(Acquire);
+
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator. The front is never null.
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            let node = unsafe { nnptr.as_ref() };
+            if node.message.is_present(Acquire) {
+                count += 1;
+            }
+            // Clearing the bit flag so null pointers are not misused.
+            node_ptr = (node.next.load(Acquire) as usize & !1) as *mut _;
+        }
+
+        count
+    }
+
+    /// Tests whether there are no messages pending in the channel. Since the
+    /// [`Sender`] may send and other [`Receiver`]s may receive meanwhile, this
+    /// is only an estimate.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// The shared incinerator used by this [`Receiver`].
     pub fn incin(&self) -> SharedIncin<T> {
         self.inner.incin.clone()
@@ -265,10 +363,95 @@ impl<T> fmt::Debug for Receiver<T> {
 unsafe impl<T> Send for Receiver<T> where T: Send {}
 unsafe impl<T> Sync for Receiver<T> where T: Send {}
 
+impl<'receiver, T> IntoIterator for &'receiver Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'receiver, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { receiver: self }
+    }
+}
+
+/// A blocking iterator over the messages of a [`Receiver`]. Created by
+/// [`iter`](Receiver::iter).
+pub struct Iter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for Iter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for Iter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Iter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// A blocking iterator which owns a [`Receiver`]. Created by its
+/// [`IntoIterator`] implementation.
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<T> fmt::Debug for IntoIter<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "IntoIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// An iterator over the messages available in a [`Receiver`], which does not
+/// block. Created by [`try_iter`](Receiver::try_iter).
+pub struct TryIter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for TryIter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for TryIter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "TryIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
 struct ReceiverInner<T> {
     // never null
     front: AtomicPtr<Node<T>>,
     incin: SharedIncin<T>,
+    waiters: Arc<WaitList>,
 }
 
 impl<T> Drop for ReceiverInner<T> {
@@ -340,6 +523,7 @@ mod test {
             Arc,
         },
         thread,
+        time::Duration,
     };
 
     #[test]
@@ -382,4 +566,66 @@ mod test {
             assert!(status.load(Relaxed));
         }
     }
+
+    #[test]
+    fn try_iter_does_not_block() {
+        let (mut sender, receiver) = spmc::create();
+        assert_eq!(receiver.try_iter().next(), None);
+        for i in 0 .. 3 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        sender.send(3).unwrap();
+        drop(sender);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn iter_woken_up_by_sender() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 512;
+
+        let (mut sender, receiver) = spmc::create::<usize>();
+        let mut threads = Vec::with_capacity(THREADS);
+
+        for _ in 0 .. THREADS {
+            let receiver = receiver.clone();
+            threads.push(thread::spawn(move || receiver.iter().sum::<usize>()))
+        }
+        let last = thread::spawn(move || receiver.into_iter().sum::<usize>());
+
+        // Giving the receivers time to wait.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. MSGS {
+            if i % 50 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            sender.send(i).unwrap();
+        }
+        // Every receiver must be woken up by the disconnection.
+        drop(sender);
+
+        let mut sum = last.join().expect("thread failed");
+        for thread in threads {
+            sum += thread.join().expect("thread failed");
+        }
+        assert_eq!(sum, MSGS * (MSGS - 1) / 2);
+    }
+
+    #[test]
+    fn len_counts_pending() {
+        let (mut sender, receiver) = spmc::create();
+        assert!(receiver.is_empty());
+        for i in 0 .. 5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.len(), 5);
+        assert_eq!(receiver.recv(), Ok(0));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.len(), 3);
+        drop(sender);
+        assert_eq!(receiver.len(), 3);
+        assert_eq!(receiver.try_iter().count(), 3);
+        assert!(receiver.is_empty());
+    }
 }
@@ -0,0 +1,213 @@
+use super::{Receiver, RecvErr, SendErr, Sender};
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use wait::{TaskWaiter, WaitList};
+
+impl<T> Receiver<T> {
+    /// Converts this receiver into a [`Stream`] of the messages. The task is
+    /// woken up by the sender, and the stream ends once the sender disconnects
+    /// and all messages were received. Requires the `futures` feature.
+    pub fn into_stream(self) -> RecvStream<T> {
+        let waiters = self.waiters.clone();
+        RecvStream { receiver: self, waiters, waiter: TaskWaiter::new() }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Converts this sender into a [`Sink`] of messages. Since the channel is
+    /// unbounded, the sink is always ready, and sending fails only if the
+    /// receiver disconnected or the sink was closed. Closing the sink drops
+    /// the sender, so the receiver sees the disconnection. Requires the
+    /// `futures` feature.
+    pub fn into_sink(self) -> SendSink<T> {
+        SendSink { sender: Some(self) }
+    }
+}
+
+/// A [`Stream`] based on [`recv`](Receiver::recv) operation of the SPSC
+/// [`Receiver`]. Created by [`into_stream`](Receiver::into_stream).
+pub struct RecvStream<T> {
+    receiver: Receiver<T>,
+    // The same list as the receiver's, so the receiver can be borrowed
+    // mutably while we wait.
+    waiters: Arc<WaitList>,
+    waiter: TaskWaiter,
+}
+
+impl<T> Stream for RecvStream<T> {
+    type Item = T;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<T>> {
+        let this = &mut *self;
+        let receiver = &mut this.receiver;
+        this.waiter.poll(&this.waiters, cx, || match receiver.recv() {
+            Ok(message) => Some(Some(message)),
+            Err(RecvErr::NoSender) => Some(None),
+            Err(RecvErr::NoMessage) => None,
+        })
+    }
+}
+
+impl<T> Drop for RecvStream<T> {
+    fn drop(&mut self) {
+        // Passes on a notification we might have received but not used.
+        self.waiter.cancel(&self.waiters);
+    }
+}
+
+impl<T> fmt::Debug for RecvStream<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "RecvStream {} receiver: {:?}, waiter: {:?} {}",
+            '{', self.receiver, self.waiter, '}'
+        )
+    }
+}
+
+/// A [`Sink`] based on [`send`](Sender::send) operation of the SPSC
+/// [`Sender`]. Created by [`into_sink`](Sender::into_sink).
+pub struct SendSink<T> {
+    // `None` once closed.
+    sender: Option<Sender<T>>,
+}
+
+impl<T> Sink<T> for SendSink<T> {
+    type Error = SendErr<T>;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<Result<(), SendErr<T>>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), SendErr<T>> {
+        match &mut self.sender {
+            Some(sender) => sender.send(item),
+            None => Err(SendErr::Disconnected(item)),
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<Result<(), SendErr<T>>> {
+        // Messages are published as soon as they are sent.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<Result<(), SendErr<T>>> {
+        // Disconnects, so the receiver knows no messages are left.
+        self.sender = None;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> fmt::Debug for SendSink<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "SendSink {} sender: {:?} {}", '{', self.sender, '}')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use channel::spsc;
+    use executor::{block_on, waker};
+    use std::{future, thread, time::Duration};
+
+    fn block_on_next<S>(stream: &mut S) -> Option<S::Item>
+    where
+        S: Stream + Unpin,
+    {
+        block_on(future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)))
+    }
+
+    #[test]
+    fn stream_woken_up_by_sender() {
+        const MSGS: usize = 512;
+
+        let (mut sender, receiver) = spsc::create();
+        let consumer = thread::spawn(move || {
+            let mut stream = receiver.into_stream();
+            let mut received = Vec::new();
+            while let Some(message) = block_on_next(&mut stream) {
+                received.push(message);
+            }
+            received
+        });
+
+        // Giving the consumer time to wait.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. MSGS {
+            if i % 50 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let received = consumer.join().expect("thread failed");
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sink_sends() {
+        let (sender, receiver) = spsc::create();
+        let waker = waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut sink = sender.into_sink();
+        let mut stream = receiver.into_stream();
+
+        for i in 0 .. 3 {
+            let mut pinned = Pin::new(&mut sink);
+            assert!(pinned.as_mut().poll_ready(&mut cx).is_ready());
+            pinned.as_mut().start_send(i).unwrap();
+            assert!(pinned.poll_flush(&mut cx).is_ready());
+        }
+        assert_eq!(block_on_next(&mut stream), Some(0));
+        drop(sink);
+        assert_eq!(block_on_next(&mut stream), Some(1));
+        assert_eq!(block_on_next(&mut stream), Some(2));
+        assert_eq!(block_on_next(&mut stream), None);
+
+        let (sender, receiver) = spsc::create();
+        let mut sink = sender.into_sink();
+        drop(receiver);
+        let err = Pin::new(&mut sink).start_send(5).unwrap_err();
+        assert_eq!(err.into_message(), 5);
+    }
+
+    #[test]
+    fn closed_sink_ends_stream() {
+        let (sender, receiver) = spsc::create();
+        let waker = waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut sink = sender.into_sink();
+        let mut stream = receiver.into_stream();
+
+        Pin::new(&mut sink).start_send(1).unwrap();
+        assert!(Pin::new(&mut sink).poll_close(&mut cx).is_ready());
+        let err = Pin::new(&mut sink).start_send(2).unwrap_err();
+        assert!(err.is_disconnected());
+        assert_eq!(err.into_message(), 2);
+
+        // The sink is still alive, but the stream ends anyway.
+        assert_eq!(block_on_next(&mut stream), Some(1));
+        assert_eq!(block_on_next(&mut stream), None);
+        drop(sink);
+    }
+}
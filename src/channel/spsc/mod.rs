@@ -0,0 +1,709 @@
+#[cfg(feature = "futures")]
+pub use self::future::{RecvStream, SendSink};
+pub use super::{
+    NoRecv,
+    RecvErr::{self, *},
+    SendErr::{self, *},
+};
+use channel::select::Selectable;
+use owned_alloc::OwnedAlloc;
+use ptr::check_null_align;
+use recycle::NodeCache;
+use std::{
+    fmt,
+    mem,
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{AtomicPtr, Ordering::*},
+        Arc,
+    },
+};
+use wait::WaitList;
+
+#[cfg(feature = "futures")]
+mod future;
+
+/// Creates an asynchronous lock-free Single-Producer-Single-Consumer (SPSC)
+/// channel.
+///
+/// Messages are stored inline in the nodes, and the nodes freed by the
+/// receiver are kept in a small cache shared with the sender, so a channel
+/// which is kept busy does not go to the allocator on every message.
+pub fn create<T>() -> (Sender<T>, Receiver<T>) {
+    check_null_align::<Node<T>>();
+
+    // A single empty node shared between two ends.
+    let alloc = OwnedAlloc::new(Node {
+        message: None,
+        next: AtomicPtr::new(null_mut()),
+    });
+    let nnptr = alloc.into_raw();
+
+    // The receiver waits here for messages.
+    let waiters = Arc::new(WaitList::new());
+    let cache = Arc::new(NodeCache::new());
+
+    let sender =
+        Sender { back: nnptr, waiters: waiters.clone(), cache: cache.clone() };
+    let receiver = Receiver { front: nnptr, waiters, cache };
+    (sender, receiver)
+}
+
+/// The `Sender` handle of a SPSC channel. Created by [`create`] function.
+pub struct Sender<T> {
+    back: NonNull<Node<T>>,
+    waiters: Arc<WaitList>,
+    cache: Arc<NodeCache<Node<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a message and if the receiver disconnected, an error is returned.
+    pub fn send(&mut self, message: T) -> Result<(), SendErr<T>> {
+        // First we create a node for our message, possibly reusing one.
+        let alloc = self.cache.alloc(Node {
+            message: Some(message),
+            next: AtomicPtr::new(null_mut()),
+        });
+        let nnptr = alloc.into_raw();
+
+        // This dereferral is safe because the queue will always have at least
+        // one node. Our back is a single node. In any case, back will always be
+        // present. Also, we only put valid pointers allocated via `OwnedAlloc`.
+        let res = unsafe {
+            // First we try to publish the new node through the back's next
+            // field. The receiver will see our changes because at some point it
+            // will reach our current back.
+            //
+            // We compare to null because, when disconnecting, the receiver will
+            // mark the lower bit of the pointer. In order words, it will be
+            // null | 1. We do not need to publish the new node if we receiver
+            // disconnected. `SeqCst` because of the wait list.
+            self.back.as_ref().next.compare_exchange(
+                null_mut(),
+                nnptr.as_ptr(),
+                SeqCst,
+                Relaxed,
+            )
+        };
+
+        if res.is_ok() {
+            // If we succeeded, let's update our back so we respect the rule of
+            // having a single node in the back.
+            self.back = nnptr;
+            self.waiters.notify_one();
+            Ok(())
+        } else {
+            // If we failed, the receiver disconnected and marked the bit.
+            let mut alloc = unsafe { OwnedAlloc::from_raw(nnptr) };
+            let message = alloc.message.take().unwrap();
+            Err(Disconnected(message))
+        }
+    }
+
+    /// Sends all the messages of the given iterable, in order. The nodes are
+    /// linked locally first, and then published with a single atomic
+    /// operation. If the receiver disconnected, the messages are given back.
+    pub fn send_iter<I>(&mut self, iterable: I) -> Result<(), SendErr<Vec<T>>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut chain = Chain { first: null_mut(), last: null_mut() };
+
+        for message in iterable {
+            let alloc = self.cache.alloc(Node {
+                message: Some(message),
+                next: AtomicPtr::new(null_mut()),
+            });
+            let ptr = alloc.into_raw().as_ptr();
+            if chain.last.is_null() {
+                chain.first = ptr;
+            } else {
+                // Safe because the chain is not shared yet and its nodes are
+                // not null.
+                unsafe { (*chain.last).next.store(ptr, Relaxed) };
+            }
+            chain.last = ptr;
+        }
+
+        let last = match NonNull::new(chain.last) {
+            Some(nnptr) => nnptr,
+            None => return Ok(()),
+        };
+
+        // Safe for the same reasons as in `send`.
+        let res = unsafe {
+            self.back.as_ref().next.compare_exchange(
+                null_mut(),
+                chain.first,
+                SeqCst,
+                Relaxed,
+            )
+        };
+
+        if res.is_ok() {
+            // The nodes now belong to the channel.
+            mem::forget(chain);
+            self.back = last;
+            self.waiters.notify_one();
+            Ok(())
+        } else {
+            // If we failed, the receiver disconnected and marked the bit.
+            let mut message = Vec::new();
+            let mut ptr = chain.first;
+            while let Some(mut nnptr) = NonNull::new(ptr) {
+                // Safe because the chain was not shared.
+                let node = unsafe { nnptr.as_mut() };
+                message.extend(node.message.take());
+                ptr = *node.next.get_mut();
+            }
+            Err(Disconnected(message))
+        }
+    }
+
+    /// Tests if the [`Receiver`] is still connected. There are no guarantees
+    /// that [`send`](Sender::send) will succeed if this method returns `true`
+    /// because the [`Receiver`] may disconnect meanwhile.
+    pub fn is_connected(&self) -> bool {
+        // Safe because we always have at least one node, which is only dropped
+        // in the last side to disconnect's drop.
+        let back = unsafe { self.back.as_ref() };
+        back.next.load(Relaxed).is_null()
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // This dereferral is safe because the queue will always have at least
+        // one node. Also, we only put nodes allocated from `OwnedAlloc`.
+        let res = unsafe {
+            // Let's try to mark next's bit so that receiver will see we
+            // disconnected, if it hasn't disconnected by itself. It is ok to
+            // just swap, since we have only two possible values (null and
+            // null | 1) and we everyone will be setting to the same value
+            // (null | 1).
+            self.back
+                .as_ref()
+                .next
+                .swap((null_mut::<Node<T>>() as usize | 1) as *mut _, SeqCst)
+        };
+
+        // If the previously stored value was not null, receiver has already
+        // disconnected. It is safe to drop because we are the only ones that
+        // have a pointer to the node.
+        if !res.is_null() {
+            unsafe { OwnedAlloc::from_raw(self.back) };
+        }
+
+        // A stream of the receiver must see the disconnection.
+        self.waiters.notify_one();
+    }
+}
+
+unsafe impl<T> Send for Sender<T> where T: Send {}
+unsafe impl<T> Sync for Sender<T> where T: Send {}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str("spsc::Sender")
+    }
+}
+
+/// The [`Receiver`] handle of a SPSC channel. Created by [`create`] function.
+pub struct Receiver<T> {
+    front: NonNull<Node<T>>,
+    waiters: Arc<WaitList>,
+    cache: Arc<NodeCache<Node<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Tries to receive a message. If no message is available,
+    /// [`Err`]`(`[`RecvErr::NoMessage`]`)` is returned. If the sender
+    /// disconnected, [`Err`]`(`[`RecvErr::NoSender`]`)` is returned.
+    pub fn recv(&mut self) -> Result<T, RecvErr> {
+        loop {
+            // This dereferral is safe because we only put nodes allocated from
+            // `OwnedAlloc`.
+            let node = unsafe { &mut *self.front.as_ptr() };
+
+            // We will try to replace the current node with this.
+            let next = node.next.load(Acquire);
+
+            // First we remove a node logically.
+            match node.message.take() {
+                Some(message) => {
+                    let cleared = (next as usize & !1) as *mut _;
+                    // But only if we have a new node. Otherwise we will not
+                    // remove the only node of the queue. Also, let's clear the
+                    // bit flag so null pointers are not misused.
+                    if let Some(nnptr) = NonNull::new(cleared) {
+                        // This is safe because the node was allocated with
+                        // `OwnedAlloc` and we have the only pointer to it (back
+                        // is something else).
+                        let alloc = unsafe { OwnedAlloc::from_raw(self.front) };
+                        self.cache.recycle(alloc);
+                        self.front = nnptr;
+                    }
+
+                    break Ok(message);
+                },
+
+                None => {
+                    if next as usize & 1 == 0 {
+                        // Lower bit clean. Let's try to remove the next.
+                        match NonNull::new(next) {
+                            Some(nnptr) => {
+                                // This is safe because the node was allocated
+                                // with `OwnedAlloc` and we have the only
+                                // pointer to it (back is something else since
+                                // it has a single node).
+                                let alloc =
+                                    unsafe { OwnedAlloc::from_raw(self.front) };
+                                self.cache.recycle(alloc);
+                                self.front = nnptr;
+                            },
+
+                            // If the next is null, we have no message and we
+                            // will not remove this list's single node.
+                            None => break Err(RecvErr::NoMessage),
+                        }
+                    } else {
+                        // If the sender marked the lower bit of the pointer, it
+                        // has disconnected.
+                        break Err(RecvErr::NoSender);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Receives up to `max` messages at once, appending them to the given
+    /// buffer, and returns how many were received. If no message was
+    /// received, the error is the same as [`recv`](Receiver::recv)'s.
+    pub fn recv_many(
+        &mut self,
+        buf: &mut Vec<T>,
+        max: usize,
+    ) -> Result<usize, RecvErr> {
+        let mut count = 0;
+
+        while count < max {
+            match self.recv() {
+                Ok(message) => {
+                    buf.push(message);
+                    count += 1;
+                },
+
+                Err(err) if count == 0 => return Err(err),
+
+                Err(_) => break,
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Receives a message, blocking the current thread until one is
+    /// available. The thread is parked rather than spinning, and it is woken
+    /// up by [`send`](Sender::send). If the sender disconnected and there
+    /// are no messages left, `None` is returned.
+    pub fn recv_wait(&mut self) -> Option<T> {
+        loop {
+            match self.recv() {
+                Ok(message) => break Some(message),
+                Err(RecvErr::NoSender) => break None,
+                Err(RecvErr::NoMessage) => (),
+            }
+
+            let waiter = self.waiters.register();
+            // Checking again because a send might have happened before we
+            // registered ourselves.
+            match self.recv() {
+                Ok(message) => {
+                    waiter.cancel(&self.waiters);
+                    break Some(message);
+                },
+
+                Err(RecvErr::NoSender) => {
+                    waiter.cancel(&self.waiters);
+                    break None;
+                },
+
+                Err(RecvErr::NoMessage) => waiter.wait(),
+            }
+        }
+    }
+
+    /// Creates an iterator which receives messages, blocking the current
+    /// thread until each one is available, just like
+    /// [`recv_wait`](Receiver::recv_wait). It stops only when the sender
+    /// disconnected and there are no messages left.
+    pub fn iter<'receiver>(&'receiver mut self) -> Iter<'receiver, T> {
+        Iter { receiver: self }
+    }
+
+    /// Creates an iterator which receives the messages available right now,
+    /// without blocking. It stops when there are no messages or when the
+    /// sender disconnected.
+    pub fn try_iter<'receiver>(&'receiver mut self) -> TryIter<'receiver, T> {
+        TryIter { receiver: self }
+    }
+
+    /// Tests if the [`Sender`] is still connected. There are no guarantees
+    /// that [`recv`](Receiver::recv) will succeed if this method returns `true`
+    /// because the [`Receiver`] may disconnect meanwhile. This method may
+    /// also return `true` if the [`Sender`] disconnected but there are
+    /// messages pending in the buffer.
+    pub fn is_connected(&self) -> bool {
+        // Safe because we always have at least one node, which is only dropped
+        // in the last side to disconnect's drop.
+        let front = unsafe { self.front.as_ref() };
+        front.message.is_some() || front.next.load(Relaxed) as usize & 1 == 0
+    }
+
+    /// Counts the messages pending in the channel, without receiving them.
+    /// Since the [`Sender`] may send meanwhile, this is only an estimate, but
+    /// it never exceeds what [`recv`](Receiver::recv) would get right now.
+    /// This operation is linear on the number of pending messages.
+    pub fn len(&self) -> usize {
+        let mut count = 0;
+        let mut node_ptr = self.front.as_ptr();
+
+        // Safe because we always have at least one node, and nodes are only
+        // dropped by the receiver or by the last side to disconnect. The
+        // message of a linked node is only changed by the receiver.
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            let node = unsafe { nnptr.as_ref() };
+            if node.message.is_some() {
+                count += 1;
+            }
+            // Clearing the bit flag so null pointers are not misused.
+            node_ptr = (node.next.load(Acquire) as usize & !1) as *mut _;
+        }
+
+        count
+    }
+
+    /// Tests whether there are no messages pending in the channel. Since the
+    /// [`Sender`] may send meanwhile, this is only an estimate.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Selectable for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        // Safe because we always have at least one node, which is only dropped
+        // in the last side to disconnect's drop.
+        let front = unsafe { self.front.as_ref() };
+        // A marked next means the sender disconnected, which is also ready.
+        front.message.is_some() || !front.next.load(Acquire).is_null()
+    }
+
+    fn wait_list(&self) -> &WaitList {
+        &self.waiters
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        loop {
+            // This dereferral is safe because we only put nodes allocated from
+            // `OwnedAlloc`.
+            let next = unsafe {
+                // Let's try to mark next's bit so that sender will see we
+                // disconnected, if it hasn't disconnected by itself. It is ok
+                // to just swap, since we have only two possible
+                // values (null and null | 1) and we everyone
+                // will be setting to the same value (null | 1).
+                self.front.as_ref().next.swap(
+                    (null_mut::<Node<T>>() as usize | 1) as *mut _,
+                    Acquire,
+                )
+            };
+
+            // Then we check for null (success of our swap).
+            let next_nnptr = match NonNull::new(next) {
+                Some(nnptr) => nnptr,
+                // If it was null, no other action is required. We should not
+                // deallocate it because the sender still sees it through back.
+                None => break,
+            };
+
+            // It is safe to drop because we are the only ones that
+            // have a pointer to the node.
+            unsafe { OwnedAlloc::from_raw(self.front) };
+
+            // if next is marked, it is actually null | 1, but we can deallocate
+            // it because the sender already disconnected.
+            if next as usize & 1 == 1 {
+                break;
+            }
+
+            // Update the front just like in pop.
+            self.front = next_nnptr;
+        }
+    }
+}
+
+unsafe impl<T> Send for Receiver<T> where T: Send {}
+unsafe impl<T> Sync for Receiver<T> where T: Send {}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str("spsc::Receiver")
+    }
+}
+
+impl<'receiver, T> IntoIterator for &'receiver mut Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'receiver, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { receiver: self }
+    }
+}
+
+/// A blocking iterator over the messages of a [`Receiver`]. Created by
+/// [`iter`](Receiver::iter).
+pub struct Iter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver mut Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for Iter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for Iter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Iter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// A blocking iterator which owns a [`Receiver`]. Created by its
+/// [`IntoIterator`] implementation.
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_wait()
+    }
+}
+
+impl<T> fmt::Debug for IntoIter<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "IntoIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+/// An iterator over the messages available in a [`Receiver`], which does not
+/// block. Created by [`try_iter`](Receiver::try_iter).
+pub struct TryIter<'receiver, T>
+where
+    T: 'receiver,
+{
+    receiver: &'receiver mut Receiver<T>,
+}
+
+impl<'receiver, T> Iterator for TryIter<'receiver, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'receiver, T> fmt::Debug for TryIter<'receiver, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "TryIter {} receiver: {:?} {}", '{', self.receiver, '}')
+    }
+}
+
+#[repr(align(/* at least */ 2))]
+struct Node<T> {
+    message: Option<T>,
+    // lower bit is 1 for "disconnected" and 0 for "connected"
+    next: AtomicPtr<Node<T>>,
+}
+
+// Nodes linked locally before being sent. Frees them in case of panic or
+// disconnection.
+struct Chain<T> {
+    first: *mut Node<T>,
+    last: *mut Node<T>,
+}
+
+impl<T> Drop for Chain<T> {
+    fn drop(&mut self) {
+        while let Some(nnptr) = NonNull::new(self.first) {
+            // This is safe because the nodes were allocated via `OwnedAlloc`
+            // and they are not shared.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            self.first = *node.next.get_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use channel::spsc;
+    use std::{sync::Arc, thread, time::Duration};
+
+    #[test]
+    fn correct_sequence() {
+        const MSGS: usize = 512;
+
+        let (mut sender, mut receiver) = spsc::create::<usize>();
+        let thread = thread::spawn(move || {
+            for i in 0 .. MSGS {
+                loop {
+                    match receiver.recv() {
+                        Ok(j) => {
+                            assert_eq!(i, j);
+                            break;
+                        },
+
+                        Err(spsc::NoMessage) => (),
+
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        });
+
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+        }
+
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn batches() {
+        const MSGS: usize = 512;
+        const BATCH: usize = 16;
+
+        let (mut sender, mut receiver) = spsc::create::<usize>();
+        let thread = thread::spawn(move || {
+            let mut buf = Vec::new();
+            while buf.len() < MSGS {
+                match receiver.recv_many(&mut buf, BATCH) {
+                    Ok(count) => assert!(count > 0 && count <= BATCH),
+                    Err(spsc::NoMessage) => (),
+                    _ => unreachable!(),
+                }
+            }
+            assert_eq!(buf, (0 .. MSGS).collect::<Vec<_>>());
+            receiver
+        });
+
+        for i in 0 .. MSGS / BATCH {
+            sender.send_iter(i * BATCH .. (i + 1) * BATCH).unwrap();
+        }
+        sender.send_iter(Vec::new()).unwrap();
+
+        let mut receiver = thread.join().unwrap();
+        sender.send_iter(vec![1, 2]).unwrap();
+        drop(sender);
+        let mut buf = Vec::new();
+        assert_eq!(receiver.recv_many(&mut buf, 5), Ok(2));
+        assert_eq!(receiver.recv_many(&mut buf, 5), Err(spsc::NoSender));
+        assert_eq!(buf, [1, 2]);
+    }
+
+    #[test]
+    fn send_iter_gives_back() {
+        let (mut sender, receiver) = spsc::create();
+        drop(receiver);
+        let res = sender.send_iter(vec![1, 2, 3]);
+        assert_eq!(res.unwrap_err().into_message(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn iterators() {
+        const MSGS: usize = 512;
+
+        let (mut sender, mut receiver) = spsc::create::<usize>();
+        let producer = thread::spawn(move || {
+            for i in 0 .. MSGS {
+                sender.send(i).unwrap();
+            }
+        });
+        let received = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+        producer.join().unwrap();
+
+        let (mut sender, receiver) = spsc::create();
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send(5).unwrap();
+        });
+        assert_eq!(receiver.into_iter().collect::<Vec<_>>(), [5]);
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn try_iter_does_not_block() {
+        let (mut sender, mut receiver) = spsc::create();
+        assert_eq!(receiver.try_iter().next(), None);
+        for i in 0 .. 3 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        sender.send(3).unwrap();
+        drop(sender);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn len_counts_pending() {
+        let (mut sender, mut receiver) = spsc::create();
+        assert!(receiver.is_empty());
+        for i in 0 .. 5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.len(), 5);
+        assert_eq!(receiver.recv(), Ok(0));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.len(), 3);
+        drop(sender);
+        assert_eq!(receiver.len(), 3);
+        assert_eq!(receiver.try_iter().count(), 3);
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn recycled_nodes_drop_messages() {
+        let item = Arc::new(());
+        let (mut sender, mut receiver) = spsc::create();
+        for _ in 0 .. 100 {
+            for _ in 0 .. 4 {
+                sender.send(item.clone()).unwrap();
+            }
+            assert_eq!(receiver.try_iter().count(), 4);
+            assert_eq!(Arc::strong_count(&item), 1);
+        }
+        sender.send_iter(vec![item.clone(), item.clone()]).unwrap();
+        drop((sender, receiver));
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+}
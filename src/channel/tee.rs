@@ -0,0 +1,230 @@
+pub use super::RecvErr::{self, *};
+use channel::{mpmc, mpsc, spmc, spsc};
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering::*},
+        Arc,
+    },
+};
+
+/// Receivers which can be the source of a [`tee`]. Implemented by the
+/// receivers of the FIFO channels of this crate.
+pub trait Source<T> {
+    /// Tries to receive a message, just like the receiver's own `recv`.
+    fn recv(&mut self) -> Result<T, RecvErr>;
+}
+
+/// Splits a receiver into `n` receivers, each one getting a clone of every
+/// message, in the same order. No thread is spawned: whichever receiver finds
+/// itself without messages receives from the source and delivers the clones
+/// to the others. The source is dropped once all the returned receivers are.
+///
+/// Messages not yet received by a slow receiver are kept in its buffer, so a
+/// receiver which is never used should be dropped rather than kept around.
+///
+/// # Example
+/// ```rust
+/// use lockfree::channel::{self, mpsc};
+///
+/// let (sender, receiver) = mpsc::create();
+/// let mut receivers = channel::tee(receiver, 2);
+/// sender.send("event").unwrap();
+/// assert_eq!(receivers[0].recv(), Ok("event"));
+/// assert_eq!(receivers[1].recv(), Ok("event"));
+/// ```
+pub fn tee<S, T>(source: S, n: usize) -> Vec<Receiver<T>>
+where
+    S: Source<T> + Send + 'static,
+    T: Clone,
+{
+    let mut senders = Vec::with_capacity(n);
+    let mut receivers = Vec::with_capacity(n);
+
+    for _ in 0 .. n {
+        let (sender, receiver) = spsc::create();
+        senders.push(sender);
+        receivers.push(receiver);
+    }
+
+    let shared = Arc::new(Shared {
+        state: UnsafeCell::new(State { source: Box::new(source), senders }),
+        pulling: AtomicBool::new(false),
+    });
+
+    receivers
+        .into_iter()
+        .map(|inner| Receiver { shared: shared.clone(), inner })
+        .collect()
+}
+
+/// A [`Receiver`] handle of a tee. Created by [`tee`] function. It is not
+/// clonable, since each receiver has its own buffer.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    inner: spsc::Receiver<T>,
+}
+
+impl<T> Receiver<T>
+where
+    T: Clone,
+{
+    /// Tries to receive a message. If no message is available,
+    /// [`Err`]`(`[`RecvErr::NoMessage`]`)` is returned. If the source's
+    /// senders disconnected and this receiver got every message,
+    /// [`Err`]`(`[`RecvErr::NoSender`]`)` is returned.
+    ///
+    /// This method never waits for other receivers: if another one is
+    /// receiving from the source right now, it delivers the message later.
+    pub fn recv(&mut self) -> Result<T, RecvErr> {
+        match self.inner.recv() {
+            Err(RecvErr::NoMessage) => (),
+            res => return res,
+        }
+
+        if self
+            .shared
+            .pulling
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            return Err(RecvErr::NoMessage);
+        }
+
+        {
+            // Safe because only the receiver which set the pulling flag
+            // accesses the state, until it clears the flag.
+            let state = unsafe { &mut *self.shared.state.get() };
+
+            match state.source.recv() {
+                Ok(message) => {
+                    let senders = &mut state.senders;
+                    if let Some((last, init)) = senders.split_last_mut() {
+                        // Disconnected receivers simply miss the message.
+                        for sender in init {
+                            let _ = sender.send(message.clone());
+                        }
+                        let _ = last.send(message);
+                    }
+                },
+
+                // Disconnecting our side as well. Receivers will still get
+                // what is left in their buffers.
+                Err(RecvErr::NoSender) => state.senders.clear(),
+
+                Err(RecvErr::NoMessage) => (),
+            }
+        }
+
+        self.shared.pulling.store(false, Release);
+        self.inner.recv()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "tee::Receiver {} ptr: {:p} {}", '{', self.shared, '}')
+    }
+}
+
+impl<T> Source<T> for spsc::Receiver<T> {
+    fn recv(&mut self) -> Result<T, RecvErr> {
+        spsc::Receiver::recv(self)
+    }
+}
+
+impl<T> Source<T> for mpsc::Receiver<T> {
+    fn recv(&mut self) -> Result<T, RecvErr> {
+        mpsc::Receiver::recv(self)
+    }
+}
+
+impl<T> Source<T> for spmc::Receiver<T> {
+    fn recv(&mut self) -> Result<T, RecvErr> {
+        spmc::Receiver::recv(self)
+    }
+}
+
+impl<T> Source<T> for spmc::bounded::Receiver<T> {
+    fn recv(&mut self) -> Result<T, RecvErr> {
+        spmc::bounded::Receiver::recv(self)
+    }
+}
+
+impl<T> Source<T> for spmc::fair::Receiver<T> {
+    fn recv(&mut self) -> Result<T, RecvErr> {
+        spmc::fair::Receiver::recv(self)
+    }
+}
+
+impl<T> Source<T> for mpmc::Receiver<T> {
+    fn recv(&mut self) -> Result<T, RecvErr> {
+        mpmc::Receiver::recv(self)
+    }
+}
+
+struct Shared<T> {
+    state: UnsafeCell<State<T>>,
+    pulling: AtomicBool,
+}
+
+unsafe impl<T> Send for Shared<T> where T: Send {}
+unsafe impl<T> Sync for Shared<T> where T: Send {}
+
+struct State<T> {
+    source: Box<dyn Source<T> + Send>,
+    senders: Vec<spsc::Sender<T>>,
+}
+
+#[cfg(test)]
+mod test {
+    use channel::{mpmc, spsc, tee};
+    use std::thread;
+
+    #[test]
+    fn every_receiver_gets_everything() {
+        const THREADS: usize = 4;
+        const MSGS: usize = 1024;
+
+        let (mut sender, receiver) = spsc::create::<usize>();
+        let mut threads = Vec::with_capacity(THREADS);
+
+        for mut receiver in tee(receiver, THREADS) {
+            threads.push(thread::spawn(move || {
+                let mut received = Vec::with_capacity(MSGS);
+                loop {
+                    match receiver.recv() {
+                        Ok(i) => received.push(i),
+                        Err(tee::NoMessage) => thread::yield_now(),
+                        Err(tee::NoSender) => break received,
+                    }
+                }
+            }));
+        }
+
+        for i in 0 .. MSGS {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        for thread in threads {
+            let received = thread.join().unwrap();
+            assert_eq!(received, (0 .. MSGS).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn dropping_receivers() {
+        let (sender, receiver) = mpmc::create();
+        let mut receivers = tee(receiver, 3);
+        drop(receivers.remove(1));
+        sender.send(1).unwrap();
+        assert_eq!(receivers[1].recv(), Ok(1));
+        assert_eq!(receivers[1].recv(), Err(tee::NoMessage));
+        assert_eq!(receivers[0].recv(), Ok(1));
+
+        drop(receivers);
+        assert!(!sender.is_connected());
+    }
+}
@@ -0,0 +1,345 @@
+pub use super::{
+    NoRecv,
+    SendErr::{self, *},
+};
+use owned_alloc::OwnedAlloc;
+use std::{
+    fmt,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicPtr, AtomicUsize, Ordering::*},
+        Arc,
+    },
+};
+
+/// Creates a lock-free watch channel, holding the given initial value. Senders
+/// overwrite a single shared value, and receivers read the most recent one,
+/// keeping track of whether it changed since their last read. Intermediate
+/// values may be missed. In order to allow multiple producers and multiple
+/// receivers, [`Sender`] and [`Receiver`] are clonable and do not require
+/// mutability for sending and reading.
+///
+/// # Design
+/// The value is stored in an allocation together with a version number, and
+/// senders replace the allocation with a CAS. Replaced allocations are
+/// reclaimed via incinerator, since receivers may still be reading them.
+pub fn create<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+    with_incin(initial, SharedIncin::new())
+}
+
+/// Same as [`create`], but use a passed incinerator instead of creating a new
+/// one. An incinerator created by [`SharedIncin::with_batch`] reclaims old
+/// values less frequently, at the cost of memory.
+pub fn with_incin<T>(
+    initial: T,
+    incin: SharedIncin<T>,
+) -> (Sender<T>, Receiver<T>) {
+    let alloc = OwnedAlloc::new(Version { val: initial, number: 0 });
+    let inner = Arc::new(Inner {
+        current: AtomicPtr::new(alloc.into_raw().as_ptr()),
+        incin,
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+    });
+
+    (Sender { inner: inner.clone() }, Receiver { inner, seen: 0 })
+}
+
+/// The [`Sender`] handle of a watch channel. Created by [`create`] or
+/// [`with_incin`] function.
+/// It is clonable and does not require mutability.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Replaces the shared value, and if all receivers disconnected, an error
+    /// is returned.
+    pub fn send(&self, val: T) -> Result<(), SendErr<T>> {
+        if !self.is_connected() {
+            return Err(Disconnected(val));
+        }
+
+        let pause = self.inner.incin.inner.pause();
+        let mut loaded = self.inner.current.load(Acquire);
+        let mut alloc = OwnedAlloc::new(Version { val, number: 0 });
+
+        loop {
+            // Safe because we paused the incinerator and we only delete
+            // versions via incinerator.
+            alloc.number = unsafe { (*loaded).number.wrapping_add(1) };
+            let new = alloc.raw().as_ptr();
+            match self.inner.current.compare_exchange(
+                loaded,
+                new,
+                AcqRel,
+                Acquire,
+            ) {
+                Ok(_) => {
+                    alloc.into_raw();
+                    // Safe because we removed the old version from the shared
+                    // context and we are adding it to the incinerator rather
+                    // than dropping it directly.
+                    let old = unsafe {
+                        OwnedAlloc::from_raw(NonNull::new_unchecked(loaded))
+                    };
+                    pause.add_to_incin(old);
+                    break Ok(());
+                },
+
+                Err(new_loaded) => loaded = new_loaded,
+            }
+        }
+    }
+
+    /// Tests if there are any [`Receiver`]s still connected. There are no
+    /// guarantees that [`send`](Sender::send) will succeed if this method
+    /// returns `true` because the [`Receiver`] may disconnect meanwhile.
+    pub fn is_connected(&self) -> bool {
+        self.inner.receivers.load(Acquire) > 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Relaxed);
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.senders.fetch_sub(1, Release);
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "watch::Sender {} ptr: {:p} {}", '{', self.inner, '}')
+    }
+}
+
+/// The [`Receiver`] handle of a watch channel. Created by [`create`] or
+/// [`with_incin`] function.
+/// It is clonable, and each clone keeps track of its own last read version.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+    seen: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Reads a copy of the most recent value, without marking it as seen.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.read(|version| version.val.clone())
+    }
+
+    /// Reads a copy of the most recent value, but only if it changed since
+    /// the last value marked as seen. The value is then marked as seen.
+    pub fn get_changed(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let seen = self.seen;
+        let (number, val) = self.read(|version| {
+            let val = if version.number == seen {
+                None
+            } else {
+                Some(version.val.clone())
+            };
+            (version.number, val)
+        });
+        self.seen = number;
+        val
+    }
+
+    /// Reads a copy of the most recent value and marks it as seen.
+    pub fn get_and_mark(&mut self) -> T
+    where
+        T: Clone,
+    {
+        let (number, val) =
+            self.read(|version| (version.number, version.val.clone()));
+        self.seen = number;
+        val
+    }
+
+    /// Tests whether the value changed since the last value marked as seen.
+    pub fn has_changed(&self) -> bool {
+        self.read(|version| version.number) != self.seen
+    }
+
+    /// Tests if there are any [`Sender`]s still connected. The last value is
+    /// kept readable even after all senders disconnected.
+    pub fn is_connected(&self) -> bool {
+        self.inner.senders.load(Acquire) > 0
+    }
+
+    /// The shared incinerator used by this [`Receiver`].
+    pub fn incin(&self) -> SharedIncin<T> {
+        self.inner.incin.clone()
+    }
+
+    fn read<F, A>(&self, reader: F) -> A
+    where
+        F: FnOnce(&Version<T>) -> A,
+    {
+        let _pause = self.inner.incin.inner.pause();
+        // Safe because we paused the incinerator and we only delete versions
+        // via incinerator. The value is never moved out of a version.
+        reader(unsafe { &*self.inner.current.load(Acquire) })
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.inner.receivers.fetch_add(1, Relaxed);
+        Self { inner: self.inner.clone(), seen: self.seen }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.receivers.fetch_sub(1, Release);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "watch::Receiver {} ptr: {:p}, seen: {} {}",
+            '{', self.inner, self.seen, '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for Sender<T> where T: Send + Sync {}
+unsafe impl<T> Sync for Sender<T> where T: Send + Sync {}
+unsafe impl<T> Send for Receiver<T> where T: Send + Sync {}
+unsafe impl<T> Sync for Receiver<T> where T: Send + Sync {}
+
+struct Inner<T> {
+    // never null
+    current: AtomicPtr<Version<T>>,
+    incin: SharedIncin<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // Safe because we never store null, we have exclusive access, and the
+        // current version was allocated via `OwnedAlloc`.
+        let ptr = *self.current.get_mut();
+        unsafe { OwnedAlloc::from_raw(NonNull::new_unchecked(ptr)) };
+    }
+}
+
+struct Version<T> {
+    val: T,
+    number: usize,
+}
+
+make_shared_incin! {
+    { "`watch::Receiver`" }
+    pub SharedIncin<T> of OwnedAlloc<Version<T>>
+}
+
+#[cfg(test)]
+mod test {
+    use channel::watch;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn latest_value_and_changes() {
+        let (sender, mut receiver) = watch::create(1);
+        assert_eq!(receiver.get(), 1);
+        assert!(!receiver.has_changed());
+        assert_eq!(receiver.get_changed(), None);
+
+        sender.send(2).unwrap();
+        sender.send(3).unwrap();
+        assert!(receiver.has_changed());
+        let mut other = receiver.clone();
+        assert_eq!(receiver.get_changed(), Some(3));
+        assert_eq!(receiver.get_changed(), None);
+        assert_eq!(other.get_and_mark(), 3);
+        assert!(!other.has_changed());
+
+        drop(sender);
+        assert!(!receiver.is_connected());
+        assert_eq!(receiver.get(), 3);
+
+        let (sender, receiver) = watch::create(0);
+        drop((receiver, other));
+        assert_eq!(sender.send(5).unwrap_err().into_message(), 5);
+    }
+
+    #[test]
+    fn drops_values() {
+        let item = Arc::new(());
+        let (sender, receiver) = watch::create(item.clone());
+        for _ in 0 .. 10 {
+            sender.send(item.clone()).unwrap();
+        }
+        assert!(Arc::ptr_eq(&receiver.get(), &item));
+        drop((sender, receiver));
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn batched_reclamation() {
+        let item = Arc::new(());
+        let incin = watch::SharedIncin::with_batch(4);
+        let (sender, receiver) = watch::with_incin(item.clone(), incin);
+        for _ in 0 .. 3 {
+            sender.send(item.clone()).unwrap();
+        }
+        // The three old versions are kept as garbage.
+        assert_eq!(Arc::strong_count(&item), 5);
+        sender.send(item.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&item), 2);
+        drop((sender, receiver));
+        assert_eq!(Arc::strong_count(&item), 1);
+
+        let (sender, _receiver) = watch::create(item.clone());
+        sender.send(item.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&item), 2);
+    }
+
+    #[test]
+    fn no_torn_snapshots() {
+        const THREADS: usize = 4;
+        const SENDS: usize = 2000;
+
+        let (sender, receiver) = watch::create((0, 0));
+        let mut threads = Vec::with_capacity(THREADS * 2);
+
+        for _ in 0 .. THREADS {
+            let sender = sender.clone();
+            threads.push(thread::spawn(move || {
+                for i in 1 ..= SENDS {
+                    sender.send((i, i * 2)).unwrap();
+                }
+            }));
+        }
+
+        for _ in 0 .. THREADS {
+            let mut receiver = receiver.clone();
+            threads.push(thread::spawn(move || {
+                for _ in 0 .. SENDS {
+                    if let Some((a, b)) = receiver.get_changed() {
+                        assert_eq!(a * 2, b);
+                    }
+                }
+            }));
+        }
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/// Structural statistics of a collection. All the values are estimates when
+/// the collection is shared between threads, since other threads may modify it
+/// while the statistics are being collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// The (estimated) number of elements stored in the collection.
+    pub len: usize,
+    /// The (estimated) number of internal allocations reachable from the
+    /// collection, including sentinels and logically removed nodes which were
+    /// not yet physically removed.
+    pub nodes: usize,
+}
+
+/// A common interface over the lock-free collections of this crate. It allows
+/// monitoring and cleanup code to handle heterogeneous collections (e.g. a
+/// registry of `Arc<dyn Collection>`) without downcasting. Every method works
+/// through a shared reference.
+pub trait Collection {
+    /// Counts the elements of the collection. Since other threads may insert
+    /// or remove elements meanwhile, this is only an estimate. This operation
+    /// is usually linear on the number of elements.
+    fn len(&self) -> usize;
+
+    /// Tests if the collection has no elements. Since other threads may insert
+    /// or remove elements meanwhile, this is only an estimate.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all elements of the collection. Elements concurrently inserted
+    /// by other threads may or may not be removed.
+    fn clear(&self);
+
+    /// Collects structural statistics about the collection.
+    fn stats(&self) -> Stats;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::Map;
+    use queue::Queue;
+    use set::Set;
+    use stack::Stack;
+
+    #[test]
+    fn heterogeneous_registry() {
+        let map = Map::new();
+        map.insert(1, 'a');
+        map.insert(2, 'b');
+        let set = Set::new();
+        set.insert(3).unwrap();
+        let queue = Queue::new();
+        queue.push(4);
+        queue.push(5);
+        queue.push(6);
+        let stack = Stack::new();
+        stack.push(7);
+
+        let registry: Vec<&dyn Collection> = vec![&map, &set, &queue, &stack];
+        let lens = registry.iter().map(|coll| coll.len()).collect::<Vec<_>>();
+        assert_eq!(lens, [2, 1, 3, 1]);
+
+        for coll in &registry {
+            assert!(!coll.is_empty());
+            coll.clear();
+            assert!(coll.is_empty());
+            assert_eq!(coll.stats().len, 0);
+        }
+    }
+
+    #[test]
+    fn stats_count_nodes() {
+        let queue = Queue::new();
+        queue.push(1);
+        queue.push(2);
+        let stats = queue.stats();
+        assert_eq!(stats.len, 2);
+        assert!(stats.nodes >= 2);
+
+        let stack = Stack::new();
+        stack.push(1);
+        assert_eq!(stack.stats(), Stats { len: 1, nodes: 1 });
+    }
+}
@@ -0,0 +1,487 @@
+use collections::{Collection, Stats};
+use incin::{Incinerator, Pause};
+use owned_alloc::OwnedAlloc;
+use std::{
+    fmt,
+    mem::ManuallyDrop,
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicPtr, Ordering::*},
+};
+
+/// A lock-free double-ended queue. Values can be pushed and popped at both the
+/// front and the back concurrently, so it can be used both as a FIFO and as a
+/// LIFO (e.g. for sliding-window buffers).
+///
+/// # Design
+/// The deque is a doubly linked list, and its ends are stored in an immutable
+/// "anchor" which is replaced as a whole on every push and pop (Michael's
+/// algorithm). A push leaves the anchor unstable until the link from the
+/// previous end to the new node is fixed, and any thread which finds an
+/// unstable anchor helps fixing it before proceeding.
+pub struct Deque<T> {
+    anchor: AtomicPtr<Anchor<T>>,
+    incin: Incinerator<Garbage<T>>,
+}
+
+impl<T> Deque<T> {
+    /// Creates a new empty deque.
+    pub fn new() -> Self {
+        let anchor = Anchor {
+            front: null_mut(),
+            back: null_mut(),
+            status: Status::Stable,
+        };
+        Self {
+            anchor: AtomicPtr::new(OwnedAlloc::new(anchor).into_raw().as_ptr()),
+            incin: Incinerator::new(),
+        }
+    }
+
+    /// Pushes a value into the front of the deque.
+    pub fn push_front(&self, item: T) {
+        self.push(item, Side::Front)
+    }
+
+    /// Pushes a value into the back of the deque.
+    pub fn push_back(&self, item: T) {
+        self.push(item, Side::Back)
+    }
+
+    /// Takes a value from the front of the deque, if it is avaible.
+    pub fn pop_front(&self) -> Option<T> {
+        self.pop(Side::Front)
+    }
+
+    /// Takes a value from the back of the deque, if it is avaible.
+    pub fn pop_back(&self) -> Option<T> {
+        self.pop(Side::Back)
+    }
+
+    fn push(&self, item: T, side: Side) {
+        // We need this because of ABA problem and use-after-free.
+        let pause = self.incin.pause();
+        let node = OwnedAlloc::new(Node {
+            item: ManuallyDrop::new(item),
+            prev: AtomicPtr::new(null_mut()),
+            next: AtomicPtr::new(null_mut()),
+        });
+        let node = node.into_raw().as_ptr();
+
+        loop {
+            let anchor_ptr = self.anchor.load(Acquire);
+            // Safe because the anchor is never null, and we only delete
+            // anchors via incinerator and we have a pause now.
+            let anchor = unsafe { &*anchor_ptr };
+
+            let new = if anchor.front.is_null() {
+                Anchor { front: node, back: node, status: Status::Stable }
+            } else if anchor.status == Status::Stable {
+                // Safe because the node is not shared yet.
+                let node_ref = unsafe { &*node };
+                match side {
+                    Side::Front => {
+                        node_ref.next.store(anchor.front, Relaxed);
+                        let status = Status::PushedFront;
+                        Anchor { front: node, status, ..*anchor }
+                    },
+
+                    Side::Back => {
+                        node_ref.prev.store(anchor.back, Relaxed);
+                        let status = Status::PushedBack;
+                        Anchor { back: node, status, ..*anchor }
+                    },
+                }
+            } else {
+                // Safe because we have a pause and the anchor was loaded from
+                // the deque.
+                unsafe { self.stabilize(anchor_ptr, &pause) };
+                continue;
+            };
+
+            // Safe because the anchor was loaded from the deque and we have a
+            // pause.
+            let res = unsafe { self.replace(anchor_ptr, new, &pause) };
+            if let Some(new_ptr) = res {
+                // Safe because the anchor was just stored in the deque and we
+                // have a pause.
+                unsafe { self.stabilize(new_ptr, &pause) };
+                break;
+            }
+        }
+    }
+
+    fn pop(&self, side: Side) -> Option<T> {
+        // We need this because of ABA problem and use-after-free.
+        let pause = self.incin.pause();
+
+        loop {
+            let anchor_ptr = self.anchor.load(Acquire);
+            // Safe because the anchor is never null, and we only delete
+            // anchors via incinerator and we have a pause now.
+            let anchor = unsafe { &*anchor_ptr };
+
+            if anchor.front.is_null() {
+                break None;
+            }
+
+            let target = match side {
+                Side::Front => anchor.front,
+                Side::Back => anchor.back,
+            };
+
+            let new = if anchor.front == anchor.back {
+                Anchor {
+                    front: null_mut(),
+                    back: null_mut(),
+                    status: Status::Stable,
+                }
+            } else if anchor.status == Status::Stable {
+                // Safe because the node is in the deque, we only delete nodes
+                // via incinerator and we have a pause. Since the anchor is
+                // stable, the links between the ends are all set.
+                let node = unsafe { &*target };
+                match side {
+                    Side::Front => {
+                        Anchor { front: node.next.load(Acquire), ..*anchor }
+                    },
+
+                    Side::Back => {
+                        Anchor { back: node.prev.load(Acquire), ..*anchor }
+                    },
+                }
+            } else {
+                // Safe because we have a pause and the anchor was loaded from
+                // the deque.
+                unsafe { self.stabilize(anchor_ptr, &pause) };
+                continue;
+            };
+
+            // Safe because the anchor was loaded from the deque and we have a
+            // pause.
+            if unsafe { self.replace(anchor_ptr, new, &pause) }.is_some() {
+                // Safe because we removed the node and only we will read the
+                // value. The node never drops the value by itself.
+                let item = unsafe { (&*(*target).item as *const T).read() };
+                // Safe because we already removed the node and we are adding
+                // to the incinerator rather than dropping it directly.
+                pause.add_to_incin(Garbage::Node(unsafe {
+                    OwnedAlloc::from_raw(NonNull::new_unchecked(target))
+                }));
+                break Some(item);
+            }
+        }
+    }
+
+    // Tries to replace the given anchor with a new one, returning the stored
+    // pointer on success. Unsafe because the old anchor must have been loaded
+    // from this deque while the given pause was active.
+    unsafe fn replace(
+        &self,
+        old: *mut Anchor<T>,
+        new: Anchor<T>,
+        pause: &Pause<Garbage<T>>,
+    ) -> Option<*mut Anchor<T>> {
+        let new = OwnedAlloc::new(new);
+        let new_ptr = new.raw().as_ptr();
+
+        match self.anchor.compare_exchange(old, new_ptr, AcqRel, Acquire) {
+            Ok(_) => {
+                new.into_raw();
+                // Some other thread might be reading the old anchor.
+                pause.add_to_incin(Garbage::Anchor(OwnedAlloc::from_raw(
+                    NonNull::new_unchecked(old),
+                )));
+                Some(new_ptr)
+            },
+
+            Err(_) => None,
+        }
+    }
+
+    // Fixes the link from the previous end to the pushed node and marks the
+    // anchor as stable. Unsafe because the anchor must have been loaded from
+    // this deque while the given pause was active.
+    unsafe fn stabilize(
+        &self,
+        anchor_ptr: *mut Anchor<T>,
+        pause: &Pause<Garbage<T>>,
+    ) {
+        let anchor = &*anchor_ptr;
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator.
+        let (link, target) = match anchor.status {
+            Status::Stable => return,
+            Status::PushedFront => {
+                let next = (*anchor.front).next.load(Acquire);
+                (&(*next).prev, anchor.front)
+            },
+            Status::PushedBack => {
+                let prev = (*anchor.back).prev.load(Acquire);
+                (&(*prev).next, anchor.back)
+            },
+        };
+
+        if self.anchor.load(Acquire) != anchor_ptr {
+            return;
+        }
+
+        let current = link.load(Acquire);
+        if current != target {
+            // If the anchor changed, someone else already fixed the link.
+            if self.anchor.load(Acquire) != anchor_ptr {
+                return;
+            }
+            let res = link.compare_exchange(current, target, AcqRel, Relaxed);
+            if res.is_err() {
+                return;
+            }
+        }
+
+        self.replace(anchor_ptr, anchor.with_status(Status::Stable), pause);
+    }
+}
+
+impl<T> Default for Deque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        // Safe because the anchor is never null and it was allocated via
+        // `OwnedAlloc`. Also, we have exclusive access to it.
+        let anchor = unsafe {
+            OwnedAlloc::from_raw(NonNull::new_unchecked(*self.anchor.get_mut()))
+        };
+        // An unstable anchor only has the link towards the pushed node
+        // missing, so we walk in the direction of the links which are set.
+        let (mut node_ptr, last) = match anchor.status {
+            Status::PushedFront => (anchor.front, anchor.back),
+            _ => (anchor.back, anchor.front),
+        };
+
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            // This is safe because we only store pointers allocated via
+            // `OwnedAlloc`. Also, we have exclusive access to this pointer.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            // Safe because the value is never used again.
+            unsafe { ManuallyDrop::drop(&mut node.item) }
+            if node_ptr == last {
+                break;
+            }
+            node_ptr = match anchor.status {
+                Status::PushedFront => *node.next.get_mut(),
+                _ => *node.prev.get_mut(),
+            };
+        }
+    }
+}
+
+impl<T> Collection for Deque<T> {
+    fn len(&self) -> usize {
+        self.stats().len
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop_front() {}
+    }
+
+    fn stats(&self) -> Stats {
+        // Pausing because we will walk through shared nodes.
+        let _pause = self.incin.pause();
+
+        'retry: loop {
+            let anchor_ptr = self.anchor.load(Acquire);
+            // Safe because the anchor is never null, and we only delete
+            // anchors via incinerator and we have a pause now.
+            let anchor = unsafe { &*anchor_ptr };
+            let mut stats = Stats::default();
+            // An unstable anchor only has the link towards the pushed node
+            // missing, so we walk in the direction of the links which are set.
+            let (mut node_ptr, last) = match anchor.status {
+                Status::PushedFront => (anchor.front, anchor.back),
+                _ => (anchor.back, anchor.front),
+            };
+
+            while let Some(nnptr) = NonNull::new(node_ptr) {
+                stats.len += 1;
+                stats.nodes += 1;
+                if node_ptr == last {
+                    break;
+                }
+                // Safe to derefer the nodes because we paused the incinerator
+                // and we only delete nodes via incinerator.
+                let node = unsafe { nnptr.as_ref() };
+                node_ptr = match anchor.status {
+                    Status::PushedFront => node.next.load(Acquire),
+                    _ => node.prev.load(Acquire),
+                };
+                // If the anchor did not change, nothing was pushed or popped,
+                // so the link we just read is consistent.
+                if self.anchor.load(Acquire) != anchor_ptr {
+                    continue 'retry;
+                }
+            }
+
+            break stats;
+        }
+    }
+}
+
+impl<T> fmt::Debug for Deque<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Deque {} anchor: {:?}, incin: {:?} {}",
+            '{', self.anchor, self.incin, '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for Deque<T> where T: Send {}
+unsafe impl<T> Sync for Deque<T> where T: Send {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Front,
+    Back,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Stable,
+    PushedFront,
+    PushedBack,
+}
+
+struct Anchor<T> {
+    front: *mut Node<T>,
+    back: *mut Node<T>,
+    status: Status,
+}
+
+impl<T> Anchor<T> {
+    fn with_status(&self, status: Status) -> Self {
+        Self { front: self.front, back: self.back, status }
+    }
+}
+
+impl<T> Clone for Anchor<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Anchor<T> {}
+
+struct Node<T> {
+    // Never dropped by the node itself.
+    item: ManuallyDrop<T>,
+    prev: AtomicPtr<Node<T>>,
+    next: AtomicPtr<Node<T>>,
+}
+
+enum Garbage<T> {
+    Node(OwnedAlloc<Node<T>>),
+    Anchor(OwnedAlloc<Anchor<T>>),
+}
+
+impl<T> fmt::Debug for Garbage<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Garbage::Node(ptr) => write!(fmtr, "Garbage::Node({:?})", ptr),
+            Garbage::Anchor(ptr) => write!(fmtr, "Garbage::Anchor({:?})", ptr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn on_empty_pops_are_none() {
+        let deque = Deque::<usize>::new();
+        assert!(deque.pop_front().is_none());
+        assert!(deque.pop_back().is_none());
+        deque.push_front(1);
+        deque.pop_back();
+        assert!(deque.pop_front().is_none());
+        assert!(deque.pop_back().is_none());
+    }
+
+    #[test]
+    fn order() {
+        let deque = Deque::new();
+        deque.push_back(3);
+        deque.push_front(2);
+        deque.push_back(4);
+        deque.push_front(1);
+        assert_eq!(deque.len(), 4);
+        assert_eq!(deque.pop_front(), Some(1));
+        assert_eq!(deque.pop_back(), Some(4));
+        assert_eq!(deque.pop_back(), Some(3));
+        deque.push_back(5);
+        assert_eq!(deque.pop_front(), Some(2));
+        assert_eq!(deque.pop_front(), Some(5));
+        assert!(deque.is_empty());
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let item = Arc::new(());
+        let deque = Deque::new();
+        for i in 0 .. 10 {
+            if i % 2 == 0 {
+                deque.push_back(item.clone());
+            } else {
+                deque.push_front(item.clone());
+            }
+        }
+        deque.pop_back();
+        drop(deque);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+
+        let deque = Arc::new(Deque::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let deque = deque.clone();
+            handles.push(thread::spawn(move || {
+                let mut sum = 0;
+                for j in 0 .. NITER {
+                    let item = i * NITER + j;
+                    if j % 2 == 0 {
+                        deque.push_front(item);
+                    } else {
+                        deque.push_back(item);
+                    }
+                    let popped = if (i + j) % 3 == 0 {
+                        deque.pop_back()
+                    } else {
+                        deque.pop_front()
+                    };
+                    sum += popped.unwrap();
+                }
+                sum
+            }));
+        }
+
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.join().expect("thread failed");
+        }
+
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+        assert!(deque.is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use wait::{TaskWaiter, WaitList};
+
+/// A future which resolves to the output of a pop-like operation on a source
+/// (e.g. [`Queue::pop`](::queue::Queue::pop)), waiting asynchronously until
+/// the operation succeeds. The task is woken up by the operations which make
+/// the source ready (e.g. [`Queue::push`](::queue::Queue::push)). Dropping it
+/// before completion is fine.
+pub struct PopFuture<'source, S, T>
+where
+    S: 'source,
+{
+    source: &'source S,
+    list: &'source WaitList,
+    pop: fn(&S) -> Option<T>,
+    waiter: TaskWaiter,
+}
+
+impl<'source, S, T> PopFuture<'source, S, T> {
+    // Creates a future which calls `pop` on the given source, and waits on
+    // the given list, which must be notified whenever `pop` may succeed.
+    pub(crate) fn new(
+        source: &'source S,
+        list: &'source WaitList,
+        pop: fn(&S) -> Option<T>,
+    ) -> Self {
+        Self { source, list, pop, waiter: TaskWaiter::new() }
+    }
+}
+
+impl<'source, S, T> Future for PopFuture<'source, S, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = &mut *self;
+        let (source, pop) = (this.source, this.pop);
+        this.waiter.poll(this.list, cx, || pop(source))
+    }
+}
+
+impl<'source, S, T> Drop for PopFuture<'source, S, T> {
+    fn drop(&mut self) {
+        // Passes on a notification we might have received but not used.
+        self.waiter.cancel(self.list);
+    }
+}
+
+impl<'source, S, T> fmt::Debug for PopFuture<'source, S, T>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "PopFuture {} source: {:?}, waiter: {:?} {}",
+            '{', self.source, self.waiter, '}'
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use executor::{block_on, waker};
+    use queue::Queue;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering::*},
+            Arc,
+        },
+        task::{Wake, Waker},
+        thread,
+        time::Duration,
+    };
+
+    #[derive(Default)]
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, SeqCst);
+        }
+    }
+
+    #[test]
+    fn used_notification_is_not_passed_on() {
+        let queue = Queue::new();
+        let counters = [Arc::new(CountWakes::default()), Arc::default()];
+        let wakers = [
+            Waker::from(counters[0].clone()),
+            Waker::from(counters[1].clone()),
+        ];
+        let mut futures =
+            [Box::pin(queue.pop_async()), Box::pin(queue.pop_async())];
+        for (future, waker) in futures.iter_mut().zip(&wakers) {
+            let mut cx = Context::from_waker(waker);
+            assert!(future.as_mut().poll(&mut cx).is_pending());
+        }
+
+        queue.push(3);
+        let woken =
+            counters.iter().position(|counter| counter.0.load(SeqCst) == 1);
+        let woken = woken.expect("no task woken up");
+        let mut cx = Context::from_waker(&wakers[woken]);
+        assert_eq!(futures[woken].as_mut().poll(&mut cx), Poll::Ready(3));
+        // The other task must not be woken up for nothing.
+        assert_eq!(counters[1 - woken].0.load(SeqCst), 0);
+    }
+
+    #[test]
+    fn dropped_future_passes_notification() {
+        let queue = Arc::new(Queue::new());
+        let waker = waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut dropped = Box::pin(queue.pop_async());
+        assert!(dropped.as_mut().poll(&mut cx).is_pending());
+
+        let consumer = {
+            let queue = queue.clone();
+            thread::spawn(move || block_on(queue.pop_async()))
+        };
+        thread::sleep(Duration::from_millis(20));
+        queue.push(5);
+        drop(dropped);
+        assert_eq!(consumer.join().expect("thread failed"), 5);
+    }
+}
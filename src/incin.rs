@@ -19,6 +19,10 @@ use tls::ThreadLocal;
 /// When the incinerator is dropped, all the garbage is automatically dropped
 /// too.
 ///
+/// By default, garbage is dropped as soon as possible. An incinerator created
+/// with [`Incinerator::with_batch`] instead keeps garbage until a thread has
+/// enough of it, trading memory for less frequent reclamation.
+///
 /// C11 Implementation: <https://gitlab.com/bzim/c11-incinerator/>
 ///
 /// # Example
@@ -67,12 +71,33 @@ use tls::ThreadLocal;
 pub struct Incinerator<T> {
     counter: AtomicUsize,
     tls_list: ThreadLocal<GarbageList<T>>,
+    batch: usize,
 }
 
 impl<T> Incinerator<T> {
     /// Creates a new incinerator, with no pauses and empty garbage list.
     pub fn new() -> Self {
-        Self { counter: AtomicUsize::new(0), tls_list: ThreadLocal::new() }
+        Self::with_batch(1)
+    }
+
+    /// Creates a new incinerator which only drops the garbage of a thread
+    /// once its garbage list has at least `batch` items, besides the usual
+    /// condition of no active pauses. Larger batches mean less frequent
+    /// reclamation, but more memory held as garbage. A batch of `0` or `1` is
+    /// the same as [`Incinerator::new`]. [`Incinerator::try_clear`] ignores
+    /// the batch.
+    pub fn with_batch(batch: usize) -> Self {
+        Self {
+            counter: AtomicUsize::new(0),
+            tls_list: ThreadLocal::new(),
+            batch,
+        }
+    }
+
+    /// The minimum number of garbage items of a thread before they are
+    /// dropped. See [`Incinerator::with_batch`].
+    pub fn batch(&self) -> usize {
+        self.batch
     }
 
     /// Increments the pause counter and creates a pause associated with this
@@ -125,11 +150,12 @@ impl<T> Incinerator<T> {
 
     /// Adds the given value to the garbage list. The value is only dropped when
     /// the counter is zero. If the counter is zero when the method is called,
-    /// the value is immediately dropped and the garbage list is cleared. You
+    /// the value is immediately dropped and the garbage list is cleared,
+    /// unless the [batch](Incinerator::with_batch) was not reached yet. You
     /// must remove the resource from shared context before calling this method.
     /// This operation performs [`Acquire`] on the pause counter.
     pub fn add(&self, val: T) {
-        if self.counter.load(Acquire) == 0 {
+        if self.counter.load(Acquire) == 0 && self.batch_reached(1) {
             // Safe to drop it all. Note that we check the counter after the
             // resource was removed from shared context. Since we use Thread
             // Local Storage, nobody can add something to the list meanwhile
@@ -163,6 +189,15 @@ impl<T> Incinerator<T> {
     pub fn clear(&mut self) {
         self.tls_list.clear();
     }
+
+    // Tests if the garbage list of this thread would have enough items to be
+    // dropped after adding the given number of items.
+    fn batch_reached(&self, adding: usize) -> bool {
+        // Skipping the lookup in the default case.
+        self.batch <= 1
+            || self.tls_list.get().map_or(0, GarbageList::len) + adding
+                >= self.batch
+    }
 }
 
 impl<T> Default for Incinerator<T> {
@@ -195,7 +230,8 @@ impl<'incin, T> Pause<'incin, T> {
     /// dropped. See documention for [`Incinerator::add`] for more. This
     /// operation performs [`Acquire`] on the pause counter.
     pub fn add_to_incin(&self, val: T) {
-        if self.incin.counter.load(Acquire) == 1 {
+        if self.incin.counter.load(Acquire) == 1 && self.incin.batch_reached(1)
+        {
             // We are the only pause active in this case.
             //
             // Safe to drop it all. Note that we check the counter after the
@@ -222,7 +258,9 @@ impl<'incin, T> Pause<'incin, T> {
 
 impl<'incin, T> Drop for Pause<'incin, T> {
     fn drop(&mut self) {
-        if self.incin.counter.fetch_sub(1, AcqRel) == 1 {
+        if self.incin.counter.fetch_sub(1, AcqRel) == 1
+            && self.incin.batch_reached(0)
+        {
             // If the previous value was 1, this means now it is 0 and... we can
             // delete our local list.
             self.incin.tls_list.get().map(GarbageList::clear);
@@ -256,6 +294,13 @@ impl<T> GarbageList<T> {
     fn clear(&self) {
         self.list.replace(Vec::new());
     }
+
+    fn len(&self) -> usize {
+        let list = self.list.replace(Vec::new());
+        let len = list.len();
+        self.list.replace(list);
+        len
+    }
 }
 
 impl<T> fmt::Debug for GarbageList<T>
@@ -316,6 +361,20 @@ macro_rules! make_shared_incin {
                 }
             }
 
+            doc! {
+                concat!("Creates a new shared incinerator for ", $target,
+                        ", which only drops the garbage of a thread once it \
+                         has at least `batch` items. See \
+                         `Incinerator::with_batch`.");
+                $vis fn with_batch(batch: usize) -> Self {
+                    use std::sync::Arc;
+                    use incin::Incinerator;
+                    Self {
+                        inner: Arc::new(Incinerator::with_batch(batch)),
+                    }
+                }
+            }
+
             doc! {
                 concat!("Tries to clear the incinerator garbage list in the \
                          best possible way given the runtime status of this \
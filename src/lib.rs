@@ -23,13 +23,23 @@
 //! - `[x]` [Set](set::Set)
 //! - `[x]` [Stack](stack::Stack)
 //! - `[x]` [Queue](queue::Queue)
-//! - `[ ]` Deque
+//! - `[x]` [Deque](deque::Deque)
 //!
 //! # Performance Guide
 //! In order to achieve a better time performance with lockfree, it is
 //! recommended to avoid global locking stuff like heap allocation.
 
+#[cfg(feature = "futures")]
+extern crate futures_core;
+#[cfg(feature = "futures")]
+extern crate futures_sink;
 extern crate owned_alloc;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
 /// Provides convenient re-exports.
 pub mod prelude;
@@ -49,6 +59,9 @@ pub mod queue;
 /// A lock-free stack.
 pub mod stack;
 
+/// A lock-free double-ended queue.
+pub mod deque;
+
 /// A lock-free map.
 pub mod map;
 
@@ -66,5 +79,22 @@ pub mod channel;
 /// A shared removable value. No extra allocation is necessary.
 pub mod removable;
 
+/// Futures shared by the asynchronous operations of the collections and
+/// channels. Requires the `futures` feature.
+#[cfg(feature = "futures")]
+pub mod future;
+
+/// A common interface over the collections of this crate.
+pub mod collections;
+
 #[allow(dead_code)]
 mod ptr;
+
+mod recycle;
+
+mod skiplist;
+
+mod wait;
+
+#[cfg(all(test, feature = "futures"))]
+mod executor;
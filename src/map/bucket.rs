@@ -1,4 +1,5 @@
 use super::{
+    flat,
     guard::{ReadGuard, Removed},
     insertion::Inserter,
 };
@@ -245,16 +246,18 @@ impl<K, V> Bucket<K, V> {
         }
     }
 
-    // Unsafe because it might need incinerator's pause and there is no
-    // guarantee the passed pause by this thread comes from the same incinerator
-    // from which other threads pass pauses.
+    // Returns how many logically removed entries were physically removed
+    // meanwhile. Unsafe because it might need incinerator's pause and there is
+    // no guarantee the passed pause by this thread comes from the same
+    // incinerator from which other threads pass pauses.
     pub unsafe fn collect<'map>(
         &'map self,
         pause: &Pause<'map, Garbage<K, V>>,
         out: &mut Vec<ReadGuard<'map, K, V>>,
-    ) {
+    ) -> usize {
         // The length to which we will truncate the vector at each retry.
         let trunc = out.len();
+        let mut cleared = 0;
 
         'retry: loop {
             // Clean-up previous try.
@@ -265,8 +268,11 @@ impl<K, V> Bucket<K, V> {
             loop {
                 match prev_list.load_next(prev, pause) {
                     LoadNextRes::Failed => continue 'retry,
-                    LoadNextRes::End => break 'retry,
-                    LoadNextRes::Cleared { new_prev } => prev = new_prev,
+                    LoadNextRes::End => break 'retry cleared,
+                    LoadNextRes::Cleared { new_prev } => {
+                        cleared += 1;
+                        prev = new_prev;
+                    },
                     LoadNextRes::Ok { list, entry } => {
                         out.push(ReadGuard::new(
                             &*entry.as_ref().pair.as_ptr(),
@@ -280,6 +286,42 @@ impl<K, V> Bucket<K, V> {
         }
     }
 
+    // Physically removes every logically removed entry. Unsafe because it
+    // might need incinerator's pause and there is no guarantee the passed
+    // pause by this thread comes from the same incinerator from which other
+    // threads pass pauses.
+    pub unsafe fn purge(&self, pause: &Pause<Garbage<K, V>>) -> PurgeRes {
+        let mut cleared = 0;
+
+        'retry: loop {
+            let mut prev_list = &self.list;
+            let mut prev = prev_list.load();
+
+            loop {
+                match prev_list.load_next(prev, pause) {
+                    LoadNextRes::Failed => continue 'retry,
+
+                    // If the previous is the root and we reached the end, the
+                    // whole bucket should be deleted.
+                    LoadNextRes::End => {
+                        let delete = prev.as_ref().is_root();
+                        break 'retry PurgeRes { cleared, delete };
+                    },
+
+                    LoadNextRes::Cleared { new_prev } => {
+                        cleared += 1;
+                        prev = new_prev;
+                    },
+
+                    LoadNextRes::Ok { list, entry } => {
+                        prev_list = &*list.as_ptr();
+                        prev = entry;
+                    },
+                }
+            }
+        }
+    }
+
     // Returns whether the bucket is empty. Unsafe because it might need
     // incinerator's pause and there is no guarantee the passed pause by
     // this thread comes from the same incinerator from which other threads
@@ -576,6 +618,7 @@ pub enum Garbage<K, V> {
     Entry(OwnedAlloc<Entry<K, V>>),
     List(OwnedAlloc<List<K, V>>),
     Bucket(OwnedAlloc<Bucket<K, V>>),
+    FlatTable(OwnedAlloc<flat::Table<K, V>>),
 }
 
 impl<K, V> fmt::Debug for Garbage<K, V> {
@@ -585,6 +628,9 @@ impl<K, V> fmt::Debug for Garbage<K, V> {
             Garbage::List(ptr) => write!(fmtr, "Garbage::List({:?})", ptr),
             Garbage::Bucket(ptr) => write!(fmtr, "Garbage::Bucket({:?})", ptr),
             Garbage::Entry(ptr) => write!(fmtr, "Garbage::Entry({:?})", ptr),
+            Garbage::FlatTable(ptr) => {
+                write!(fmtr, "Garbage::FlatTable({:?})", ptr)
+            },
         }
     }
 }
@@ -611,6 +657,11 @@ pub struct RemoveRes<K, V> {
     pub delete: bool,
 }
 
+pub struct PurgeRes {
+    pub cleared: usize,
+    pub delete: bool,
+}
+
 enum FindRes<'map, K, V>
 where
     K: 'map,
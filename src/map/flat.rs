@@ -0,0 +1,985 @@
+use super::{
+    bucket::Garbage,
+    guard::{ReadGuard, Removed},
+    RandomState,
+    SharedIncin,
+};
+use collections::{Collection, Stats};
+use incin::Pause;
+use owned_alloc::OwnedAlloc;
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    iter::FromIterator,
+    marker::PhantomData,
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering::*},
+    thread,
+};
+
+// The smallest number of slots in a table.
+const MIN_SLOTS: usize = 16;
+
+// The number of slots a thread claims at once when migrating a table.
+const MIGRATION_CHUNK: usize = 32;
+
+// The targets of these sentinels are never read. Their addresses are distinct
+// from any allocation.
+static TOMBSTONE: u8 = 0;
+static FROZEN: u8 = 0;
+static MOVED: u8 = 0;
+
+/// A lock-free map implemented with a resizable open-addressing hash table.
+/// This is an alternative to [`Map`](super::Map) for workloads dominated by
+/// reads, since a lookup only probes a contiguous array of slots instead of
+/// walking down a tree of tables and an ordered bucket.
+///
+/// # Design
+/// Each slot is an atomic pointer to an entry, an empty slot, a tombstone (a
+/// removed entry), or a frozen slot, next to the hash of the key which claimed
+/// it. Collisions are resolved by linear probing. A key claims the first empty
+/// slot of its probe sequence and the slot is never reused for another key;
+/// removal leaves a tombstone behind.
+///
+/// When the table becomes three quarters full (counting tombstones), the
+/// entries are migrated to a new table. Every writer which finds the table
+/// being migrated helps, claiming chunks of slots and freezing each of them
+/// so no further modification happens there. A frozen slot keeps its entry
+/// readable, so lookups and iteration never wait for a migration. Entries are
+/// moved, not copied, so guarded references remain valid, and the stored
+/// hashes are used to place them, so no user code runs during a migration.
+/// Since the table is also rebuilt when it is full of tombstones, removals
+/// eventually release their slots.
+///
+/// # Progress
+/// Lookups and iteration never wait. Insertions and removals are lock-free
+/// while the table is not being migrated. During a migration, a writer moves
+/// chunks of slots until none is left, and then waits for the threads still
+/// moving theirs. Therefore, writes may block while resizing if a migrating
+/// thread is suspended.
+///
+/// `FlatMap` shares the incinerator type, the [`ReadGuard`] and the
+/// [`Removed`] types with [`Map`](super::Map). Compared to `Map`, it uses
+/// less indirections per lookup, but writes may wait for each other while
+/// resizing and memory is not released by removals until the next migration.
+pub struct FlatMap<K, V, H = RandomState> {
+    table: AtomicPtr<Table<K, V>>,
+    incin: SharedIncin<K, V>,
+    builder: H,
+}
+
+impl<K, V> FlatMap<K, V> {
+    /// Creates a new [`FlatMap`] with the default hasher builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [`FlatMap`] with the default hasher builder, able to
+    /// hold the given number of entries without resizing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::default())
+    }
+
+    /// Creates the [`FlatMap`] using the given shared incinerator.
+    pub fn with_incin(incin: SharedIncin<K, V>) -> Self {
+        Self::with_hasher_and_incin(RandomState::default(), incin)
+    }
+}
+
+impl<K, V, H> FlatMap<K, V, H> {
+    /// Creates an iterator over guarded references to the key-value entries.
+    pub fn iter<'map>(&'map self) -> FlatIter<'map, K, V, H> {
+        self.into_iter()
+    }
+
+    /// The shared incinerator used by this [`FlatMap`].
+    pub fn incin(&self) -> SharedIncin<K, V> {
+        self.incin.clone()
+    }
+
+    /// The hasher buider used by this [`FlatMap`].
+    pub fn hasher(&self) -> &H {
+        &self.builder
+    }
+
+    /// The number of slots of the current table. Removed entries keep their
+    /// slots until the next resize.
+    pub fn slots(&self) -> usize {
+        let pause = self.incin.inner.pause();
+        // Safe because we paused properly.
+        let table = unsafe { &*self.table.load(Acquire) };
+        let len = table.slots.len();
+        pause.resume();
+        len
+    }
+
+    // Helps migrating the entries of the given table to a new table, and
+    // returns once the map no longer uses the given table.
+    fn help_resize(&self, table: &Table<K, V>, pause: &Pause<Garbage<K, V>>) {
+        let next = table.next_table();
+        let len = table.slots.len();
+
+        while table.cursor.load(Relaxed) < len {
+            let start = table.cursor.fetch_add(MIGRATION_CHUNK, Relaxed);
+            if start >= len {
+                break;
+            }
+            let end = len.min(start + MIGRATION_CHUNK);
+            for index in start .. end {
+                table.migrate(index, next);
+            }
+            table.migrated.fetch_add(end - start, AcqRel);
+        }
+
+        // Some chunks may still be moved by other threads.
+        while table.migrated.load(Acquire) < len {
+            thread::yield_now();
+        }
+
+        let res = self.table.compare_exchange(
+            table as *const _ as *mut _,
+            next as *const _ as *mut _,
+            AcqRel,
+            Acquire,
+        );
+
+        if res.is_ok() {
+            // Safe because the old table is no longer reachable from the map,
+            // and it was allocated via `OwnedAlloc`.
+            let old = unsafe {
+                OwnedAlloc::from_raw(NonNull::new_unchecked(
+                    table as *const _ as *mut _,
+                ))
+            };
+            pause.add_to_incin(Garbage::FlatTable(old));
+        }
+    }
+}
+
+impl<K, V, H> FlatMap<K, V, H>
+where
+    H: BuildHasher,
+{
+    /// Creates the [`FlatMap`] using the given hasher builder.
+    pub fn with_hasher(builder: H) -> Self {
+        Self::with_hasher_and_incin(builder, SharedIncin::new())
+    }
+
+    /// Creates the [`FlatMap`] using the given hasher builder and shared
+    /// incinerator.
+    pub fn with_hasher_and_incin(builder: H, incin: SharedIncin<K, V>) -> Self {
+        let table = OwnedAlloc::new(Table::new(MIN_SLOTS)).into_raw();
+        Self { table: AtomicPtr::new(table.as_ptr()), incin, builder }
+    }
+
+    /// Creates the [`FlatMap`] using the given hasher builder, able to hold
+    /// the given number of entries without resizing.
+    pub fn with_capacity_and_hasher(capacity: usize, builder: H) -> Self {
+        let table = Table::new(Table::<K, V>::slots_for(capacity));
+        let table = OwnedAlloc::new(table).into_raw();
+        Self {
+            table: AtomicPtr::new(table.as_ptr()),
+            incin: SharedIncin::new(),
+            builder,
+        }
+    }
+
+    /// Searches for the entry identified by the given key. The returned value
+    /// is a guarded reference. Guarded to ensure no thread deallocates the
+    /// allocation for the entry while it is being used. This method will only
+    /// work correctly if [`Hash`] and [`Ord`] are implemented in the same way
+    /// for the borrowed type and the stored type. If the entry was not
+    /// found, [`None`] is returned.
+    pub fn get<'map, Q>(&'map self, key: &Q) -> Option<ReadGuard<'map, K, V>>
+    where
+        Q: ?Sized + Hash + Ord,
+        K: Borrow<Q>,
+    {
+        let hash = self.hash_of(key);
+        let pause = self.incin.inner.pause();
+        // Safe because we paused properly, and tables are only deallocated
+        // via incinerator.
+        let table = unsafe { &*self.table.load(Acquire) };
+
+        match table.find(key, hash) {
+            Find::Found(_, ptr) | Find::Moved(ptr) => {
+                // Safe because entries are only deallocated via incinerator,
+                // which is paused.
+                Some(ReadGuard::new(unsafe { &*ptr }, pause))
+            },
+
+            Find::Absent => None,
+        }
+    }
+
+    /// Inserts unconditionally the given key and value. If there was a
+    /// previously stored entry, the displaced pair is returned as a
+    /// [`Removed`].
+    pub fn insert(&self, key: K, val: V) -> Option<Removed<K, V>>
+    where
+        K: Hash + Ord,
+    {
+        let hash = self.hash_of(&key);
+        let new = OwnedAlloc::new((key, val)).into_raw().as_ptr();
+        let pause = self.incin.inner.pause();
+
+        loop {
+            // Safe because we paused properly, and tables are only
+            // deallocated via incinerator.
+            let table = unsafe { &*self.table.load(Acquire) };
+
+            // Safe because `new` is a valid allocation and we paused.
+            match unsafe { table.insert(new, hash) } {
+                Insert::Created => break None,
+
+                Insert::Updated(old) => {
+                    // Safe because the old entry was removed from the shared
+                    // context by us, and it was allocated via `OwnedAlloc`.
+                    let alloc = unsafe {
+                        OwnedAlloc::from_raw(NonNull::new_unchecked(old))
+                    };
+                    break Some(Removed::new(alloc, &self.incin.inner));
+                },
+
+                Insert::Frozen | Insert::Full => {
+                    self.help_resize(table, &pause)
+                },
+            }
+        }
+    }
+
+    /// Removes unconditionally the entry identified by the given key. If no
+    /// entry was found, [`None`] is returned. This method will only work
+    /// correctly if [`Hash`] and [`Ord`] are implemented in the same way for
+    /// the borrowed type and the stored type.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Removed<K, V>>
+    where
+        Q: ?Sized + Hash + Ord,
+        K: Borrow<Q>,
+    {
+        let hash = self.hash_of(key);
+        let pause = self.incin.inner.pause();
+
+        let removed = loop {
+            // Safe because we paused properly, and tables are only
+            // deallocated via incinerator.
+            let table = unsafe { &*self.table.load(Acquire) };
+
+            match table.find(key, hash) {
+                Find::Found(index, ptr) => {
+                    let res = table.slots[index].compare_exchange(
+                        ptr,
+                        tombstone(),
+                        AcqRel,
+                        Acquire,
+                    );
+
+                    if res.is_ok() {
+                        break Some(ptr);
+                    }
+                },
+
+                Find::Moved(_) => self.help_resize(table, &pause),
+
+                Find::Absent => break None,
+            }
+        };
+
+        pause.resume();
+
+        removed.map(|ptr| {
+            // Safe because the entry was removed from the shared context by
+            // us, and it was allocated via `OwnedAlloc`.
+            let alloc =
+                unsafe { OwnedAlloc::from_raw(NonNull::new_unchecked(ptr)) };
+            Removed::new(alloc, &self.incin.inner)
+        })
+    }
+
+    /// Acts just like [`Extend::extend`] but does not require mutability.
+    pub fn extend<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Hash + Ord,
+    {
+        for (key, val) in iterable {
+            self.insert(key, val);
+        }
+    }
+
+    fn hash_of<Q>(&self, key: &Q) -> u64
+    where
+        Q: ?Sized + Hash,
+    {
+        let mut hasher = self.builder.build_hasher();
+        key.hash(&mut hasher);
+        // Zero marks slots not claimed by any key.
+        match hasher.finish() {
+            0 => 1,
+            hash => hash,
+        }
+    }
+}
+
+impl<K, V, H> Default for FlatMap<K, V, H>
+where
+    H: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(H::default())
+    }
+}
+
+impl<K, V, H> Collection for FlatMap<K, V, H>
+where
+    H: BuildHasher,
+    K: Hash + Ord,
+{
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn clear(&self) {
+        for guard in self.iter() {
+            self.remove(guard.key());
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let pause = self.incin.inner.pause();
+        // Safe because we paused properly.
+        let table = unsafe { &*self.table.load(Acquire) };
+        let slots = 0 .. table.slots.len();
+        let len = slots.filter(|&index| table.entry(index).is_some()).count();
+        pause.resume();
+        Stats { len, nodes: len + 1 }
+    }
+}
+
+impl<K, V, H> Drop for FlatMap<K, V, H> {
+    fn drop(&mut self) {
+        let table = self.table.load(Relaxed);
+        // Safe because we are in the destructor and the table was allocated
+        // via `OwnedAlloc`. The entries are not shared anymore.
+        unsafe {
+            let table = OwnedAlloc::from_raw(NonNull::new_unchecked(table));
+            for slot in &table.slots as &[AtomicPtr<(K, V)>] {
+                let loaded = slot.load(Relaxed);
+                if is_entry(loaded) {
+                    OwnedAlloc::from_raw(NonNull::new_unchecked(loaded));
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, H> fmt::Debug for FlatMap<K, V, H>
+where
+    H: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "FlatMap {} table: {:?}, incin: {:?}, build_hasher: {:?} {}",
+            '{', self.table, self.incin.inner, self.builder, '}'
+        )
+    }
+}
+
+impl<'map, K, V, H> IntoIterator for &'map FlatMap<K, V, H> {
+    type Item = ReadGuard<'map, K, V>;
+
+    type IntoIter = FlatIter<'map, K, V, H>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let pause = self.incin.inner.pause();
+        // Safe because we paused properly.
+        let table = unsafe { &*self.table.load(Acquire) };
+        FlatIter { pause, table, index: 0, _marker: PhantomData }
+    }
+}
+
+impl<K, V, H> FromIterator<(K, V)> for FlatMap<K, V, H>
+where
+    H: BuildHasher + Default,
+    K: Hash + Ord,
+{
+    fn from_iter<I>(iterable: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let this = Self::default();
+        this.extend(iterable);
+        this
+    }
+}
+
+impl<K, V, H> Extend<(K, V)> for FlatMap<K, V, H>
+where
+    H: BuildHasher,
+    K: Hash + Ord,
+{
+    fn extend<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        (&*self).extend(iterable)
+    }
+}
+
+unsafe impl<K, V, H> Send for FlatMap<K, V, H>
+where
+    K: Send,
+    V: Send,
+    H: Send,
+{
+}
+
+unsafe impl<K, V, H> Sync for FlatMap<K, V, H>
+where
+    K: Sync,
+    V: Sync,
+    H: Sync,
+{
+}
+
+/// An iterator over key-value entries of a [`FlatMap`]. The `Item` of this
+/// iterator is a [`ReadGuard`]. This iterator may be inconsistent, but still
+/// it is memory-safe. It is guaranteed to yield items that have been in the
+/// `FlatMap` since the iterator creation and the current call to
+/// [`next`](Iterator::next), unless the `FlatMap` is resized during the
+/// iteration: the iteration goes on over the old table, so entries changed
+/// after the resize might be yielded outdated or missed.
+pub struct FlatIter<'map, K, V, H>
+where
+    K: 'map,
+    V: 'map,
+    H: 'map,
+{
+    pause: Pause<'map, Garbage<K, V>>,
+    table: &'map Table<K, V>,
+    index: usize,
+    _marker: PhantomData<&'map FlatMap<K, V, H>>,
+}
+
+impl<'map, K, V, H> Iterator for FlatIter<'map, K, V, H> {
+    type Item = ReadGuard<'map, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.table.slots.len() {
+            let entry = self.table.entry(self.index);
+            self.index += 1;
+            if let Some(ptr) = entry {
+                // Safe because entries are only deallocated via incinerator,
+                // which is paused.
+                let pair = unsafe { &*ptr };
+                return Some(ReadGuard::new(pair, self.pause.clone()));
+            }
+        }
+
+        None
+    }
+}
+
+impl<'map, K, V, H> fmt::Debug for FlatIter<'map, K, V, H> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "FlatIter {} table: {:?}, index: {} {}",
+            '{', self.table, self.index, '}'
+        )
+    }
+}
+
+unsafe impl<'map, K, V, H> Send for FlatIter<'map, K, V, H>
+where
+    K: Send,
+    V: Send,
+    H: Sync,
+{
+}
+
+unsafe impl<'map, K, V, H> Sync for FlatIter<'map, K, V, H>
+where
+    K: Sync,
+    V: Sync,
+    H: Sync,
+{
+}
+
+pub struct Table<K, V> {
+    slots: Box<[AtomicPtr<(K, V)>]>,
+    // The hash of the key which claimed each slot, or zero.
+    hashes: Box<[AtomicU64]>,
+    // The entry of each slot moved to the next table.
+    moved: Box<[AtomicPtr<(K, V)>]>,
+    // Slots claimed by keys, including tombstones.
+    claimed: AtomicUsize,
+    // The table receiving the entries of this one, once it is migrated.
+    next: AtomicPtr<Table<K, V>>,
+    // The first slot not claimed by a migrating thread.
+    cursor: AtomicUsize,
+    // The number of slots already frozen by migrating threads.
+    migrated: AtomicUsize,
+}
+
+impl<K, V> Table<K, V> {
+    fn new(len: usize) -> Self {
+        debug_assert!(len.is_power_of_two());
+        Self {
+            slots: (0 .. len).map(|_| AtomicPtr::new(null_mut())).collect(),
+            hashes: (0 .. len).map(|_| AtomicU64::new(0)).collect(),
+            moved: (0 .. len).map(|_| AtomicPtr::new(null_mut())).collect(),
+            claimed: AtomicUsize::new(0),
+            next: AtomicPtr::new(null_mut()),
+            cursor: AtomicUsize::new(0),
+            migrated: AtomicUsize::new(0),
+        }
+    }
+
+    // The number of slots needed for the given number of entries, so that the
+    // table is at most half full.
+    fn slots_for(entries: usize) -> usize {
+        entries.saturating_mul(2).max(MIN_SLOTS).next_power_of_two()
+    }
+
+    // The number of slots which can be claimed before the table is full.
+    fn limit(&self) -> usize {
+        self.slots.len() / 4 * 3
+    }
+
+    fn probe(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let mask = self.slots.len() - 1;
+        let start = hash as usize & mask;
+        (0 .. self.slots.len()).map(move |i| (start + i) & mask)
+    }
+
+    // The entry in the slot at the given index, even if it was moved.
+    fn entry(&self, index: usize) -> Option<*mut (K, V)> {
+        let loaded = self.slots[index].load(Acquire);
+
+        if loaded == moved() {
+            Some(self.moved[index].load(Relaxed))
+        } else if is_entry(loaded) {
+            Some(loaded)
+        } else {
+            None
+        }
+    }
+
+    fn find<Q>(&self, key: &Q, hash: u64) -> Find<K, V>
+    where
+        Q: ?Sized + Ord,
+        K: Borrow<Q>,
+    {
+        for index in self.probe(hash) {
+            let loaded = self.slots[index].load(Acquire);
+            let stored = self.hashes[index].load(Acquire);
+
+            if loaded.is_null() || loaded == frozen() {
+                if stored == 0 {
+                    return Find::Absent;
+                }
+                // Claimed by an insertion which did not finish.
+                continue;
+            }
+
+            if loaded == tombstone() || stored != hash {
+                continue;
+            }
+
+            let ptr = if loaded == moved() {
+                self.moved[index].load(Relaxed)
+            } else {
+                loaded
+            };
+
+            // Safe because entries are only deallocated via incinerator, and
+            // our caller paused it.
+            if matches(unsafe { &*ptr }, key) {
+                return if ptr == loaded {
+                    Find::Found(index, ptr)
+                } else {
+                    Find::Moved(ptr)
+                };
+            }
+        }
+
+        Find::Absent
+    }
+
+    // Unsafe because the incinerator needs to be paused and the entry needs to
+    // be a valid allocation.
+    unsafe fn insert(&self, new: *mut (K, V), hash: u64) -> Insert<K, V>
+    where
+        K: Ord,
+    {
+        let key = &(*new).0;
+
+        for index in self.probe(hash) {
+            let slot = &self.slots[index];
+            let mut loaded = slot.load(Acquire);
+
+            loop {
+                if loaded == frozen() || loaded == moved() {
+                    return Insert::Frozen;
+                }
+
+                if loaded.is_null() {
+                    match self.claim(index, hash) {
+                        Claim::Claimed => (),
+                        // Not our slot.
+                        Claim::Taken => break,
+                        Claim::Full => return Insert::Full,
+                    }
+
+                    match slot.compare_exchange(loaded, new, AcqRel, Acquire) {
+                        Ok(_) => return Insert::Created,
+                        // Let's examine the slot again.
+                        Err(new_loaded) => loaded = new_loaded,
+                    }
+                } else if loaded == tombstone()
+                    || self.hashes[index].load(Relaxed) != hash
+                    || !matches(&*loaded, key)
+                {
+                    // Not our slot.
+                    break;
+                } else {
+                    match slot.compare_exchange(loaded, new, AcqRel, Acquire) {
+                        Ok(_) => return Insert::Updated(loaded),
+                        // Let's examine the slot again.
+                        Err(new_loaded) => loaded = new_loaded,
+                    }
+                }
+            }
+        }
+
+        Insert::Full
+    }
+
+    // Claims the slot at the given index for keys with the given hash, unless
+    // it was claimed for another hash or the table is full.
+    fn claim(&self, index: usize, hash: u64) -> Claim {
+        let stored = self.hashes[index].load(Acquire);
+        if stored != 0 {
+            return if stored == hash { Claim::Claimed } else { Claim::Taken };
+        }
+
+        let mut claimed = self.claimed.load(Relaxed);
+        loop {
+            if claimed >= self.limit() {
+                return Claim::Full;
+            }
+
+            let res = self.claimed.compare_exchange_weak(
+                claimed,
+                claimed + 1,
+                Relaxed,
+                Relaxed,
+            );
+
+            match res {
+                Ok(_) => break,
+                Err(new_claimed) => claimed = new_claimed,
+            }
+        }
+
+        match self.hashes[index].compare_exchange(0, hash, AcqRel, Acquire) {
+            Ok(_) => Claim::Claimed,
+
+            Err(stored) => {
+                self.claimed.fetch_sub(1, Relaxed);
+                if stored == hash {
+                    Claim::Claimed
+                } else {
+                    Claim::Taken
+                }
+            },
+        }
+    }
+
+    // The table receiving the entries of this one, allocated by the first
+    // thread to ask for it.
+    fn next_table(&self) -> &Self {
+        let mut loaded = self.next.load(Acquire);
+
+        if loaded.is_null() {
+            // No slot is claimed anymore, so the entries not removed yet are
+            // at most the claimed slots which are not tombstones.
+            let slots = self.slots.iter();
+            let tombstones =
+                slots.filter(|slot| slot.load(Acquire) == tombstone()).count();
+            let len = Self::slots_for(self.limit().saturating_sub(tombstones));
+            let new = OwnedAlloc::new(Self::new(len)).into_raw();
+
+            let res = self.next.compare_exchange(
+                loaded,
+                new.as_ptr(),
+                AcqRel,
+                Acquire,
+            );
+
+            loaded = match res {
+                Ok(_) => new.as_ptr(),
+
+                Err(other) => {
+                    // Safe because our table was never shared, and it was
+                    // allocated via `OwnedAlloc`.
+                    unsafe { OwnedAlloc::from_raw(new) };
+                    other
+                },
+            };
+        }
+
+        // Safe because the next table is only deallocated via incinerator
+        // after it is replaced in the map, and the caller paused it.
+        unsafe { &*loaded }
+    }
+
+    // Freezes the slot at the given index and moves its entry, if any, to the
+    // next table. Only one thread migrates a given slot.
+    fn migrate(&self, index: usize, next: &Self) {
+        let slot = &self.slots[index];
+        let mut loaded = slot.load(Acquire);
+
+        loop {
+            // Tombstones are never modified.
+            if loaded == tombstone() {
+                return;
+            }
+
+            let sentinel = if loaded.is_null() {
+                frozen()
+            } else {
+                // Published by the release below, which is the only one to
+                // store the sentinel.
+                self.moved[index].store(loaded, Relaxed);
+                moved()
+            };
+
+            match slot.compare_exchange(loaded, sentinel, AcqRel, Acquire) {
+                Ok(_) => break,
+                // Let's examine the slot again.
+                Err(new_loaded) => loaded = new_loaded,
+            }
+        }
+
+        if !loaded.is_null() {
+            next.place(loaded, self.hashes[index].load(Relaxed));
+        }
+    }
+
+    // Places an entry in a table which is only modified by migrating threads.
+    // Keys are unique, so they are not compared.
+    fn place(&self, ptr: *mut (K, V), hash: u64) {
+        for index in self.probe(hash) {
+            let slot = &self.slots[index];
+            if !slot.load(Acquire).is_null() {
+                continue;
+            }
+
+            if let Claim::Claimed = self.claim(index, hash) {
+                let res =
+                    slot.compare_exchange(null_mut(), ptr, AcqRel, Acquire);
+                if res.is_ok() {
+                    return;
+                }
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+impl<K, V> fmt::Debug for Table<K, V> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Table {} slots: {:?}, claimed: {:?}, next: {:?} {}",
+            '{', self.slots, self.claimed, self.next, '}'
+        )
+    }
+}
+
+enum Find<K, V> {
+    Found(usize, *mut (K, V)),
+    Moved(*mut (K, V)),
+    Absent,
+}
+
+enum Insert<K, V> {
+    Created,
+    Updated(*mut (K, V)),
+    Frozen,
+    Full,
+}
+
+enum Claim {
+    Claimed,
+    Taken,
+    Full,
+}
+
+fn tombstone<K, V>() -> *mut (K, V) {
+    &TOMBSTONE as *const u8 as *mut (K, V)
+}
+
+fn frozen<K, V>() -> *mut (K, V) {
+    &FROZEN as *const u8 as *mut (K, V)
+}
+
+fn moved<K, V>() -> *mut (K, V) {
+    &MOVED as *const u8 as *mut (K, V)
+}
+
+fn is_entry<K, V>(ptr: *mut (K, V)) -> bool {
+    !ptr.is_null() && ptr != tombstone() && ptr != frozen() && ptr != moved()
+}
+
+fn matches<K, V, Q>(pair: &(K, V), key: &Q) -> bool
+where
+    Q: ?Sized + Ord,
+    K: Borrow<Q>,
+{
+    pair.0.borrow().cmp(key) == Ordering::Equal
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::Map;
+    use std::sync::Arc;
+
+    #[test]
+    fn inserts_gets_and_removes() {
+        let map = FlatMap::new();
+        assert!(map.get("five").is_none());
+        assert!(map.insert("five".to_owned(), 5).is_none());
+        assert_eq!(*map.get("five").unwrap().val(), 5);
+        let old = map.insert("five".to_owned(), 50).unwrap();
+        assert_eq!(*old.val(), 5);
+        assert_eq!(*map.get("five").unwrap().val(), 50);
+        let removed = map.remove("five").unwrap();
+        assert_eq!(*removed.val(), 50);
+        assert!(map.get("five").is_none());
+        assert!(map.remove("five").is_none());
+        assert!(map.insert("five".to_owned(), 500).is_none());
+        assert_eq!(*map.get("five").unwrap().val(), 500);
+    }
+
+    #[test]
+    fn resizes() {
+        let map = FlatMap::new();
+        for i in 0 .. 1000 {
+            map.insert(i, i * 2);
+        }
+        assert!(map.slots() >= 1000);
+        for i in 0 .. 1000 {
+            assert_eq!(*map.get(&i).unwrap().val(), i * 2);
+        }
+        assert_eq!(map.len(), 1000);
+    }
+
+    #[test]
+    fn tombstones_are_reclaimed() {
+        let map = FlatMap::with_capacity(8);
+        let slots = map.slots();
+        for i in 0 .. 10000 {
+            map.insert(i, i);
+            map.remove(&i);
+        }
+        assert_eq!(map.slots(), slots);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn guards_survive_resize() {
+        let map = FlatMap::new();
+        map.insert(0, "zero".to_owned());
+        let guard = map.get(&0).unwrap();
+        for i in 1 .. 1000 {
+            map.insert(i, i.to_string());
+        }
+        assert_eq!(guard.val(), "zero");
+    }
+
+    #[test]
+    fn iter_yields_entries() {
+        let map = (0 .. 100).map(|i| (i, i)).collect::<FlatMap<_, _>>();
+        let mut keys = map.iter().map(|guard| *guard.key()).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, (0 .. 100).collect::<Vec<_>>());
+        map.clear();
+        assert_eq!(map.iter().count(), 0);
+    }
+
+    #[test]
+    fn shares_incin_with_map() {
+        let map = Map::new();
+        let flat = FlatMap::with_incin(map.incin());
+        flat.insert(3, 'c');
+        let removed = flat.remove(&3).unwrap();
+        assert!(map.reinsert(removed).created());
+        assert_eq!(*map.get(&3).unwrap().val(), 'c');
+    }
+
+    #[test]
+    fn reads_during_resize() {
+        const KEPT: usize = 100;
+
+        let map = (0 .. KEPT).map(|i| (i, i)).collect::<FlatMap<_, _>>();
+        let map = Arc::new(map);
+        let mut threads = Vec::new();
+        for t in 0 .. 4 {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                for i in 0 .. 2000 {
+                    map.insert(KEPT + t * 2000 + i, i);
+                    map.insert(i % KEPT, i % KEPT);
+                }
+            }));
+        }
+        for _ in 0 .. 4 {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                for i in 0 .. 20000 {
+                    let found = map.get(&(i % KEPT)).map(|guard| *guard.val());
+                    assert_eq!(found, Some(i % KEPT));
+                }
+            }));
+        }
+        for thread in threads {
+            thread.join().expect("thread failed");
+        }
+        assert_eq!(map.len(), KEPT + 4 * 2000);
+    }
+
+    #[test]
+    fn concurrent_inserts_and_removes() {
+        let map = Arc::new(FlatMap::new());
+        let mut threads = Vec::new();
+        for t in 0 .. 8 {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                for i in 0 .. 1000 {
+                    let key = t * 1000 + i;
+                    map.insert(key, i);
+                    if i % 2 == 0 {
+                        assert_eq!(*map.remove(&key).unwrap().val(), i);
+                    }
+                }
+            }));
+        }
+        for thread in threads {
+            thread.join().expect("thread failed");
+        }
+        for t in 0 .. 8 {
+            for i in 0 .. 1000 {
+                let found = map.get(&(t * 1000 + i)).map(|guard| *guard.val());
+                assert_eq!(found, if i % 2 == 0 { None } else { Some(i) });
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::bucket::Garbage;
+use super::{bucket::Garbage, Map, RandomState};
 use incin::{Incinerator, Pause};
 use owned_alloc::OwnedAlloc;
 use std::{
@@ -6,7 +6,7 @@ use std::{
     cmp::Ordering,
     fmt,
     hash::{Hash, Hasher},
-    mem::forget,
+    mem::{forget, transmute},
     ops::Deref,
     ptr::NonNull,
     sync::{Arc, Weak},
@@ -140,6 +140,104 @@ where
 {
 }
 
+/// An owned read-operation guard. Unlike [`ReadGuard`], this does not borrow
+/// the [`Map`]: it keeps the `Map` alive through an [`Arc`] instead. Therefore,
+/// it can be stored in a struct, sent to another thread or held across an
+/// `await` point. Created by [`Map::get_owned`].
+pub struct OwnedReadGuard<K, V, H = RandomState>
+where
+    K: 'static,
+    V: 'static,
+{
+    // The guard must be declared (and so dropped) before the map, since it
+    // actually borrows from the map.
+    guard: ReadGuard<'static, K, V>,
+    map: Arc<Map<K, V, H>>,
+}
+
+impl<K, V, H> OwnedReadGuard<K, V, H>
+where
+    K: 'static,
+    V: 'static,
+{
+    // Unsafe because the guard must have been created from the given map.
+    pub(super) unsafe fn new<'map>(
+        guard: ReadGuard<'map, K, V>,
+        map: Arc<Map<K, V, H>>,
+    ) -> Self {
+        Self {
+            // Lifetime extension. The caller guarantees the guard borrows from
+            // the map, and the map will not move nor be dropped while the
+            // guard is alive, since we keep the `Arc`.
+            guard: transmute::<ReadGuard<'map, K, V>, ReadGuard<'static, K, V>>(
+                guard,
+            ),
+            map,
+        }
+    }
+
+    /// Utility method. Returns the key of this borrowed entry.
+    pub fn key(&self) -> &K {
+        self.guard.key()
+    }
+
+    /// Utility method. Returns the value of this borrowed entry.
+    pub fn val(&self) -> &V {
+        self.guard.val()
+    }
+
+    /// Returns the [`Map`] from which this entry was borrowed.
+    pub fn map(this: &Self) -> &Arc<Map<K, V, H>> {
+        &this.map
+    }
+}
+
+impl<K, V, H> Deref for OwnedReadGuard<K, V, H>
+where
+    K: 'static,
+    V: 'static,
+{
+    type Target = (K, V);
+
+    fn deref(&self) -> &Self::Target {
+        &*self.guard
+    }
+}
+
+impl<K, V, H> AsRef<(K, V)> for OwnedReadGuard<K, V, H>
+where
+    K: 'static,
+    V: 'static,
+{
+    fn as_ref(&self) -> &(K, V) {
+        &**self
+    }
+}
+
+impl<K, V, H> Borrow<(K, V)> for OwnedReadGuard<K, V, H>
+where
+    K: 'static,
+    V: 'static,
+{
+    fn borrow(&self) -> &(K, V) {
+        &**self
+    }
+}
+
+impl<K, V, H> fmt::Debug for OwnedReadGuard<K, V, H>
+where
+    K: 'static + fmt::Debug,
+    V: 'static + fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "OwnedReadGuard {} pair: {:?}, map: {:p} {}",
+            '{', &*self.guard, self.map, '}'
+        )
+    }
+}
+
 /// A removed entry. It can be reinserted at the same [`Map`](super::Map) it was
 /// removed. It can also be inserted on another [`Map`](super::Map), but only if
 /// either the [`Map`](super::Map) is dropped, there are no sensitive reads
@@ -135,6 +135,12 @@ where
         }
     }
 
+    // The pointer to the allocation, regardless of the value being
+    // initialized. It is stable through all the insertion.
+    pub fn raw(&self) -> NonNull<(K, V)> {
+        self.nnptr
+    }
+
     pub fn into_pair(self) -> (K, Option<V>) {
         // Doing this is safe by itself. However, callers should be careful if
         // they used the pointer.
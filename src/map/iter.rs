@@ -5,7 +5,7 @@ use super::{
 };
 use incin::Pause;
 use owned_alloc::OwnedAlloc;
-use std::{fmt, mem::replace, ptr::NonNull, sync::atomic::Ordering::*};
+use std::{fmt, mem::replace, ptr::NonNull, sync::atomic::Ordering::*, vec};
 
 /// An iterator over key-vaue entries of a [`Map`](super::Map). The `Item` of
 /// this iterator is a [`ReadGuard`]. This iterator may be inconsistent, but
@@ -22,21 +22,46 @@ where
     pause: Pause<'map, Garbage<K, V>>,
     tables: Vec<&'map Table<K, V>>,
     curr_table: Option<(&'map Table<K, V>, usize)>,
+    // Only applies to the top table.
+    end: usize,
     cache: Vec<ReadGuard<'map, K, V>>,
+    skipped: usize,
 }
 
 impl<'map, K, V> Iter<'map, K, V> {
     pub(super) fn new(
         pause: Pause<'map, Garbage<K, V>>,
         top: &'map Table<K, V>,
+    ) -> Self {
+        Self::with_slots(pause, top, 0, usize::max_value())
+    }
+
+    // Iterates only over the entries under the top table's slots in the range
+    // `start .. end`.
+    pub(super) fn with_slots(
+        pause: Pause<'map, Garbage<K, V>>,
+        top: &'map Table<K, V>,
+        start: usize,
+        end: usize,
     ) -> Self {
         Self {
             pause,
             tables: Vec::new(),
-            curr_table: Some((top, 0)),
+            curr_table: Some((top, start)),
+            end,
             cache: Vec::new(),
+            skipped: 0,
         }
     }
+
+    /// The number of tombstones met so far: logically removed entries (which
+    /// this iterator removed physically) and empty buckets. A persistently
+    /// high count relative to the yielded entries suggests calling
+    /// [`Map::purge_tombstones`](super::Map::purge_tombstones) or
+    /// [`Map::optimize_space`](super::Map::optimize_space).
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
 }
 
 impl<'map, K, V> Iterator for Iter<'map, K, V> {
@@ -52,7 +77,13 @@ impl<'map, K, V> Iterator for Iter<'map, K, V> {
             // If the iterator was empty, let's try to get a new one from
             // another bucket.
             let (table, index) = self.curr_table?;
-            self.curr_table = match table.load_index(index, Acquire) {
+            let loaded = if index < self.end {
+                table.load_index(index, Acquire)
+            } else {
+                None
+            };
+
+            self.curr_table = match loaded {
                 // If the pointer is null, simply go to the next element.
                 Some(ptr) if ptr.is_null() => Some((table, index + 1)),
 
@@ -70,8 +101,13 @@ impl<'map, K, V> Iterator for Iter<'map, K, V> {
                     //
                     // 3. We only store preoperly allocated nodes in the table
                     // and mark buckets with 0.
-                    unsafe { (*ptr).collect(&self.pause, &mut cache) };
+                    let cleared =
+                        unsafe { (*ptr).collect(&self.pause, &mut cache) };
 
+                    self.skipped += cleared;
+                    if cache.is_empty() {
+                        self.skipped += 1;
+                    }
                     self.cache = cache;
                     Some((table, index + 1))
                 },
@@ -93,8 +129,12 @@ impl<'map, K, V> Iterator for Iter<'map, K, V> {
                     Some((table, index + 1))
                 },
 
-                // If the pointer is null, get the next table.
-                None => self.tables.pop().map(|tbl| (tbl, 0)),
+                // If the pointer is null, get the next table. Inner tables
+                // are always fully iterated.
+                None => {
+                    self.end = usize::max_value();
+                    self.tables.pop().map(|tbl| (tbl, 0))
+                },
             };
         }
     }
@@ -114,6 +154,50 @@ where
 {
 }
 
+/// An iterator over a snapshot of the key-value entries of a
+/// [`Map`](super::Map). The `Item` of this iterator is a [`ReadGuard`]. All
+/// entries are collected under a single pause when the snapshot is created,
+/// and each key is yielded at most once, sorted by key. Every entry present in
+/// the `Map` during the whole creation of the snapshot is yielded. Entries
+/// concurrently inserted or removed may or may not be yielded.
+#[derive(Debug)]
+pub struct Snapshot<'map, K, V>
+where
+    K: 'map,
+    V: 'map,
+{
+    entries: vec::IntoIter<ReadGuard<'map, K, V>>,
+}
+
+impl<'map, K, V> Snapshot<'map, K, V> {
+    pub(super) fn new(iter: Iter<'map, K, V>) -> Self
+    where
+        K: Ord,
+    {
+        let mut entries = iter.collect::<Vec<_>>();
+        // Newer entries are found later. Since the sort is stable, reversing
+        // makes deduplication keep the newest entry of each key.
+        entries.reverse();
+        entries.sort_by(|left, right| left.key().cmp(right.key()));
+        entries.dedup_by(|right, left| left.key() == right.key());
+        Self { entries: entries.into_iter() }
+    }
+}
+
+impl<'map, K, V> Iterator for Snapshot<'map, K, V> {
+    type Item = ReadGuard<'map, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'map, K, V> ExactSizeIterator for Snapshot<'map, K, V> {}
+
 /// An owned iterator over key-vaue entries of a [`Map`](super::Map).
 pub struct IntoIter<K, V> {
     tables: Vec<OwnedAlloc<Table<K, V>>>,
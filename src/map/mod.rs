@@ -3,11 +3,17 @@ mod bucket;
 mod insertion;
 mod guard;
 mod iter;
+mod flat;
+#[cfg(feature = "rayon")]
+mod par;
 
+#[cfg(feature = "rayon")]
+pub use self::par::ParIter;
 pub use self::{
-    guard::{ReadGuard, Removed},
+    flat::{FlatIter, FlatMap},
+    guard::{OwnedReadGuard, ReadGuard, Removed},
     insertion::{Insertion, Preview},
-    iter::{IntoIter, Iter, IterMut},
+    iter::{IntoIter, Iter, IterMut, Snapshot},
 };
 pub use std::collections::hash_map::RandomState;
 
@@ -16,6 +22,7 @@ use self::{
     insertion::{InsertNew, Reinsert},
     table::Table,
 };
+use collections::{Collection, Stats};
 use owned_alloc::OwnedAlloc;
 use ptr::check_null_align;
 use std::{
@@ -24,6 +31,8 @@ use std::{
     hash::{BuildHasher, Hash, Hasher},
     iter::FromIterator,
     mem,
+    ops::Add,
+    sync::Arc,
 };
 
 /// A lock-free map. Implemented using multi-level hash-tables (in a tree
@@ -60,6 +69,19 @@ use std::{
 /// references to the entries, neither allow the user to move out removed
 /// values, as they must be deinitialized correctly. Instead, we return guarded
 /// references to the entries and wrappers over removed entries.
+///
+/// # Memory Ordering
+/// No operation uses `SeqCst` or fences. Pointers to tables, buckets and
+/// entries are published with `Release` (or `AcqRel` compare-and-swaps) and
+/// loaded with `Acquire`, which is the minimum required for a reader to see an
+/// initialized allocation. `Relaxed` is only used where exclusive access is
+/// guaranteed (e.g. `&mut self` methods and allocations not shared yet). The
+/// remaining fixed cost of `get` is the incinerator pause, an `AcqRel`
+/// compare-and-swap on a counter shared by every thread using the incinerator,
+/// plus a decrement when the guard is dropped. Therefore, there is no "relaxed
+/// profile" to opt into. For read-heavy workloads, consider shorter pauses
+/// (e.g. [`get_cloned`](Map::get_cloned)) or [`FlatMap`], whose lookups load
+/// fewer pointers.
 pub struct Map<K, V, H = RandomState> {
     top: OwnedAlloc<Table<K, V>>,
     incin: SharedIncin<K, V>,
@@ -78,6 +100,14 @@ impl<K, V> Map<K, V> {
     pub fn with_incin(incin: SharedIncin<K, V>) -> Self {
         Self::with_hasher_and_incin(RandomState::default(), incin)
     }
+
+    /// Creates a new [`Map`] with the default hasher builder, prepared to hold
+    /// about the given number of entries without creating branches of the
+    /// internal tree during insertion. Inserting fewer entries still works,
+    /// but wastes the memory of the prepared branches.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::default())
+    }
 }
 
 impl<K, V, H> Map<K, V, H> {
@@ -86,6 +116,18 @@ impl<K, V, H> Map<K, V, H> {
         self.into_iter()
     }
 
+    /// Creates an iterator over a snapshot of the key-value entries. Unlike
+    /// [`iter`](Map::iter), each key is yielded only once and every entry
+    /// which stays in the [`Map`] while the snapshot is created is yielded,
+    /// which is useful for exporting the contents during concurrent
+    /// modifications. The entries are collected eagerly and sorted by key.
+    pub fn snapshot_iter<'map>(&'map self) -> Snapshot<'map, K, V>
+    where
+        K: Ord,
+    {
+        Snapshot::new(self.iter())
+    }
+
     /// Creates an iterator over the key-value entries, with a mutable reference
     /// to the value.
     pub fn iter_mut(&mut self) -> IterMut<K, V> {
@@ -95,6 +137,12 @@ impl<K, V, H> Map<K, V, H> {
     /// Tries to optimize space by removing unnecessary tables *without removing
     /// any entry*. This method might also clear delayed resource destruction.
     /// This method cannot be performed in a shared context.
+    ///
+    /// Removals never collapse branches by themselves, so after heavy churn the
+    /// [`Map`] might keep deep branches with few entries. This method compacts
+    /// them: empty branches are freed and branches with a single entry are
+    /// replaced by the entry. Branches cannot be collapsed in a shared context,
+    /// because a concurrent insertion could be lost in the collapsed branch.
     pub fn optimize_space(&mut self) {
         self.incin.clear();
         self.top.optimize_space();
@@ -119,7 +167,9 @@ impl<K, V, H> Map<K, V, H>
 where
     H: BuildHasher,
 {
-    /// Creates the [`Map`] using the given hasher builder.
+    /// Creates the [`Map`] using the given hasher builder. Any
+    /// [`BuildHasher`] is accepted, so faster (but not DoS-resistant) hashers
+    /// or keyed hashers can be plugged in.
     pub fn with_hasher(builder: H) -> Self {
         Self::with_hasher_and_incin(builder, SharedIncin::new())
     }
@@ -130,6 +180,19 @@ where
         Self { top: Table::new_alloc(), incin, builder }
     }
 
+    /// Creates the [`Map`] using the given hasher builder, prepared to hold
+    /// about the given number of entries. See [`Map::with_capacity`].
+    pub fn with_capacity_and_hasher(capacity: usize, builder: H) -> Self {
+        check_null_align::<Table<K, V>>();
+        check_null_align::<Bucket<K, V>>();
+        let levels = Table::<K, V>::levels_for(capacity);
+        Self {
+            top: Table::new_alloc_branched(levels),
+            incin: SharedIncin::new(),
+            builder,
+        }
+    }
+
     /// The shared incinerator used by this [`Map`].
     pub fn incin(&self) -> SharedIncin<K, V> {
         self.incin.clone()
@@ -158,8 +221,43 @@ where
         unsafe { self.top.get(key, hash, pause) }
     }
 
+    /// Searches for the entry identified by the given key and clones its value.
+    /// Unlike [`get`](Map::get), no guard is returned: the incinerator is only
+    /// paused while the value is cloned, which is useful when user code should
+    /// not delay resource reclamation. If the entry was not found, [`None`] is
+    /// returned.
+    pub fn get_cloned<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Hash + Ord,
+        K: Borrow<Q>,
+        V: Clone,
+    {
+        self.get(key).map(|guard| guard.val().clone())
+    }
+
+    /// Searches for the entry identified by the given key, just like
+    /// [`get`](Map::get), but returns an owned guard which keeps the [`Map`]
+    /// alive instead of borrowing it. The guard can be stored, sent to other
+    /// threads or held across `await` points. If the entry was not found,
+    /// [`None`] is returned.
+    pub fn get_owned<Q>(
+        this: &Arc<Self>,
+        key: &Q,
+    ) -> Option<OwnedReadGuard<K, V, H>>
+    where
+        Q: ?Sized + Hash + Ord,
+        K: Borrow<Q> + 'static,
+        V: 'static,
+    {
+        let guard = this.get(key)?;
+        // Safe because the guard was created from this very map.
+        Some(unsafe { OwnedReadGuard::new(guard, this.clone()) })
+    }
+
     /// Inserts unconditionally the given key and value. If there was a
-    /// previously stored value, it is returned.
+    /// previously stored entry, the displaced pair is returned as a
+    /// [`Removed`]. Resources owned by the old pair can be released by
+    /// recovering it with [`Removed::try_into`], or simply by dropping it.
     pub fn insert(&self, key: K, val: V) -> Option<Removed<K, V>>
     where
         K: Hash + Ord,
@@ -183,6 +281,70 @@ where
         }
     }
 
+    /// Searches for the entry identified by the given key and, if it is not
+    /// found, inserts a new entry whose value is generated by the given
+    /// closure. The closure is called at most once. The returned guarded
+    /// reference points to the entry which "won": either the one found or the
+    /// one inserted by this call. Guarded to ensure no thread deallocates the
+    /// allocation for the entry while it is being used.
+    pub fn get_or_insert_with<'map, F>(
+        &'map self,
+        key: K,
+        init: F,
+    ) -> ReadGuard<'map, K, V>
+    where
+        K: Hash + Ord,
+        F: FnOnce() -> V,
+    {
+        let hash = self.hash_of(&key);
+        let pause = self.incin.inner.pause();
+
+        // Safe because we paused properly.
+        if let Some(guard) = unsafe { self.top.get(&key, hash, pause.clone()) } {
+            return guard;
+        }
+
+        // We wrap our pair as a removed entry so it survives failed insertions
+        // without being dropped, and without generating the value again.
+        let alloc = OwnedAlloc::new((key, init()));
+        let mut removed = Removed::new(alloc, &self.incin.inner);
+
+        loop {
+            let nnptr = Removed::raw(&removed);
+            // Safe because we paused properly.
+            let insertion = unsafe {
+                self.top.insert(
+                    Reinsert::new(|_, stored| stored.is_none(), removed),
+                    hash,
+                    &pause,
+                    &self.incin.inner,
+                )
+            };
+
+            match insertion {
+                // Safe because the allocation now belongs to the map and it is
+                // only deallocated via incinerator, which is paused.
+                Insertion::Created => {
+                    break ReadGuard::new(unsafe { &*nnptr.as_ptr() }, pause);
+                },
+
+                Insertion::Failed(inserter) => {
+                    removed = inserter.into_removed();
+                    // Safe because we paused properly. If the found entry was
+                    // concurrently removed, we just try again.
+                    let found = unsafe {
+                        self.top.get(removed.key(), hash, pause.clone())
+                    };
+                    if let Some(guard) = found {
+                        break guard;
+                    }
+                },
+
+                Insertion::Updated(_) => unreachable!(),
+            }
+        }
+    }
+
     /// Inserts _interactively_ the given key. A closure is passed to generate
     /// the value part of the entry and validate it with the found value. Even
     /// though the closure may have already accepted some condition, it might
@@ -225,6 +387,72 @@ where
         }
     }
 
+    /// Updates the value of the entry identified by the given key, using a
+    /// closure which computes the new value from the current one. The entry
+    /// is updated in place (it is not removed and then inserted again), and
+    /// the closure might get recalled many times due to concurrent
+    /// modifications of the [`Map`]. A guarded reference to the updated entry
+    /// is returned. If the entry was not found, [`None`] is returned and no
+    /// entry is created.
+    pub fn update<'map, F>(
+        &'map self,
+        key: K,
+        mut updater: F,
+    ) -> Option<ReadGuard<'map, K, V>>
+    where
+        K: Hash + Ord,
+        F: FnMut(&V) -> V,
+    {
+        let hash = self.hash_of(&key);
+        let pause = self.incin.inner.pause();
+        let inserter = InsertNew::with_key(
+            |_, _, stored: Option<&(K, V)>| match stored {
+                Some((_, val)) => Preview::New(updater(val)),
+                None => Preview::Discard,
+            },
+            key,
+        );
+        let nnptr = inserter.raw();
+        // Safe because we paused properly.
+        let insertion = unsafe {
+            self.top.insert(inserter, hash, &pause, &self.incin.inner)
+        };
+
+        match insertion {
+            // Safe because the allocation now belongs to the map and it is only
+            // deallocated via incinerator, which is paused.
+            Insertion::Updated(_) => {
+                Some(ReadGuard::new(unsafe { &*nnptr.as_ptr() }, pause))
+            },
+            Insertion::Failed(_) => None,
+            Insertion::Created => unreachable!(),
+        }
+    }
+
+    /// Adds the given delta to the value of the entry identified by the given
+    /// key, in a single traversal. If the entry was not found, it is created
+    /// with the delta as its value. The previous value is returned, or [`None`]
+    /// if the entry was created. This is useful for using the [`Map`] as a
+    /// table of counters, e.g. for aggregating metrics.
+    pub fn fetch_add(&self, key: K, delta: V) -> Option<V>
+    where
+        K: Hash + Ord,
+        V: Add<Output = V> + Clone,
+    {
+        let insertion = self.insert_with(key, |_, _, stored| {
+            Preview::New(match stored {
+                Some((_, val)) => val.clone() + delta.clone(),
+                None => delta.clone(),
+            })
+        });
+
+        match insertion {
+            Insertion::Created => None,
+            Insertion::Updated(old) => Some(old.val().clone()),
+            Insertion::Failed(_) => unreachable!(),
+        }
+    }
+
     /// Reinserts a previously removed entry. The entry must have been either:
     ///
     /// 1. Removed from any [`Map`] using the same [`SharedIncin`] as this
@@ -357,6 +585,49 @@ where
         }
     }
 
+    /// Removes the entry identified by the given key only if its value passes
+    /// the given predicate. The predicate is called at most once. If the entry
+    /// is concurrently replaced after the predicate was called, the removal
+    /// does not happen, since the tested value is no longer the stored one.
+    /// If the entry was not found or the predicate rejected it, [`None`] is
+    /// returned. This method will only work correctly if [`Hash`] and [`Ord`]
+    /// are implemented in the same way for the borrowed type and the stored
+    /// type.
+    pub fn remove_if<Q, F>(&self, key: &Q, pred: F) -> Option<Removed<K, V>>
+    where
+        Q: ?Sized + Hash + Ord,
+        K: Borrow<Q>,
+        F: FnOnce(&V) -> bool,
+    {
+        let mut pred = Some(pred);
+        let mut tested: Option<(*const (K, V), bool)> = None;
+
+        self.remove_with(key, |pair| {
+            let ptr = pair as *const (K, V);
+            // Retrying after a failed removal. Since we are paused, the
+            // pointer of the tested entry cannot have been reused.
+            if let Some((tested_ptr, verdict)) = tested {
+                return tested_ptr == ptr && verdict;
+            }
+
+            let pred = pred.take().expect("predicate called once");
+            let verdict = pred(&pair.1);
+            tested = Some((ptr, verdict));
+            verdict
+        })
+    }
+
+    /// Physically removes every logically removed entry and every empty bucket
+    /// left behind by removals, returning how many of them were found. Unlike
+    /// [`optimize_space`](Map::optimize_space), this can be performed in a
+    /// shared context, but empty branches are kept. Long-running processes can
+    /// use the count (or [`Iter::skipped`]) to decide when to compact.
+    pub fn purge_tombstones(&self) -> usize {
+        let pause = self.incin.inner.pause();
+        // Safe because we paused properly.
+        unsafe { self.top.purge(&pause) }
+    }
+
     /// Acts just like [`Extend::extend`] but does not require mutability.
     pub fn extend<I>(&self, iterable: I)
     where
@@ -368,6 +639,29 @@ where
         }
     }
 
+    /// Consumes another [`Map`] and folds its entries into this one. When a key
+    /// is present in both maps, the closure resolves the conflict: it is given
+    /// the key, the value stored in this map and the value from the other map,
+    /// and returns the value to be stored. The closure might get recalled many
+    /// times due to concurrent modifications of the `Map`, so the values are
+    /// cloned before being passed. Since this does not require mutability,
+    /// many maps can be merged into this one concurrently.
+    pub fn merge_from<H2, F>(&self, other: Map<K, V, H2>, mut resolve: F)
+    where
+        K: Hash + Ord,
+        V: Clone,
+        F: FnMut(&K, V, V) -> V,
+    {
+        for (key, val) in other {
+            self.insert_with(key, |key, _, stored| {
+                Preview::New(match stored {
+                    Some((_, old)) => resolve(key, old.clone(), val.clone()),
+                    None => val.clone(),
+                })
+            });
+        }
+    }
+
     fn hash_of<Q>(&self, key: &Q) -> u64
     where
         Q: ?Sized + Hash,
@@ -387,6 +681,30 @@ where
     }
 }
 
+impl<K, V, H> Collection for Map<K, V, H>
+where
+    H: BuildHasher,
+    K: Hash + Ord,
+{
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn clear(&self) {
+        for guard in self {
+            self.remove(guard.key());
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let len = self.len();
+        let _pause = self.incin.inner.pause();
+        // Safe because we paused properly.
+        let nodes = unsafe { self.top.count_nodes() };
+        Stats { len, nodes }
+    }
+}
+
 impl<K, V, H> fmt::Debug for Map<K, V, H>
 where
     H: fmt::Debug,
@@ -512,7 +830,40 @@ impl<K, V> fmt::Debug for SharedIncin<K, V> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::{collections::HashMap, sync::Arc, thread};
+    use std::{
+        collections::HashMap,
+        hash::BuildHasherDefault,
+        sync::Arc,
+        thread,
+    };
+
+    #[derive(Debug, Default)]
+    struct ConstHasher;
+
+    impl Hasher for ConstHasher {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
+
+    #[derive(Debug, Default)]
+    struct XorHasher {
+        state: u64,
+    }
+
+    impl Hasher for XorHasher {
+        fn finish(&self) -> u64 {
+            self.state
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.state = self.state.rotate_left(8) ^ byte as u64;
+            }
+        }
+    }
 
     #[test]
     fn inserts_and_gets() {
@@ -529,6 +880,78 @@ mod test {
         assert_eq!(*guard.val(), 4);
     }
 
+    #[test]
+    fn get_cloned() {
+        let map = Map::new();
+        assert!(map.get_cloned("five").is_none());
+        map.insert("five".to_owned(), vec![5]);
+        let val = map.get_cloned("five").unwrap();
+        map.remove("five");
+        map.incin().clear();
+        assert_eq!(val, [5]);
+    }
+
+    #[test]
+    fn get_owned_outlives_handle() {
+        let map = Arc::new(Map::new());
+        map.insert("five".to_owned(), 5);
+        assert!(Map::get_owned(&map, "four").is_none());
+        let guard = Map::get_owned(&map, "five").unwrap();
+        drop(map);
+
+        let guard = thread::spawn(move || {
+            assert_eq!(guard.key(), "five");
+            assert_eq!(*guard.val(), 5);
+            guard
+        })
+        .join()
+        .expect("thread failed");
+
+        let map = OwnedReadGuard::map(&guard).clone();
+        map.remove("five");
+        assert_eq!(*guard, ("five".to_owned(), 5));
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let map = Map::new();
+        let guard = map.get_or_insert_with("five".to_owned(), || 5);
+        assert_eq!(guard.key(), "five");
+        assert_eq!(*guard.val(), 5);
+        let guard = map.get_or_insert_with("five".to_owned(), || 50);
+        assert_eq!(*guard.val(), 5);
+        assert_eq!(*map.get("five").unwrap().val(), 5);
+    }
+
+    #[test]
+    fn get_or_insert_with_single_winner() {
+        let map = Arc::new(Map::new());
+        let mut threads = Vec::new();
+        for i in 0 .. 16usize {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                *map.get_or_insert_with("key".to_owned(), || i).val()
+            }));
+        }
+        let results = threads
+            .into_iter()
+            .map(|thread| thread.join().expect("thread failed"))
+            .collect::<Vec<_>>();
+        let winner = *map.get("key").unwrap().val();
+        assert!(results.iter().all(|&val| val == winner));
+    }
+
+    #[test]
+    fn custom_build_hasher() {
+        let map = Map::with_hasher(BuildHasherDefault::<XorHasher>::default());
+        for i in 0 .. 100u32 {
+            assert!(map.insert(i, i * 2).is_none());
+        }
+        for i in 0 .. 100u32 {
+            assert_eq!(*map.get(&i).unwrap().val(), i * 2);
+        }
+    }
+
     #[test]
     fn create() {
         let map = Map::new();
@@ -585,6 +1008,59 @@ mod test {
         assert_eq!(*map.get("five").unwrap().val(), 12);
     }
 
+    #[test]
+    fn update_in_place() {
+        let map = Map::new();
+        assert!(map.update("five".to_owned(), |n| n + 1).is_none());
+        assert!(map.get("five").is_none());
+        map.insert("five".to_owned(), 5);
+        let guard = map.update("five".to_owned(), |n| n + 1).unwrap();
+        assert_eq!(guard.key(), "five");
+        assert_eq!(*guard.val(), 6);
+        assert_eq!(*map.get("five").unwrap().val(), 6);
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        let map = Arc::new(Map::new());
+        map.insert(0u8, 0usize);
+        let mut threads = Vec::new();
+        for _ in 0 .. 8 {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                for _ in 0 .. 100 {
+                    map.update(0, |n| n + 1).unwrap();
+                }
+            }));
+        }
+        for thread in threads {
+            thread.join().expect("thread failed");
+        }
+        assert_eq!(*map.get(&0).unwrap().val(), 800);
+    }
+
+    #[test]
+    fn fetch_add_counts() {
+        let map = Arc::new(Map::new());
+        assert_eq!(map.fetch_add("hits", 2u64), None);
+        assert_eq!(map.fetch_add("hits", 3), Some(2));
+        assert_eq!(*map.get("hits").unwrap().val(), 5);
+
+        let mut threads = Vec::new();
+        for _ in 0 .. 8 {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                for _ in 0 .. 100 {
+                    map.fetch_add("misses", 1);
+                }
+            }));
+        }
+        for thread in threads {
+            thread.join().expect("thread failed");
+        }
+        assert_eq!(*map.get("misses").unwrap().val(), 800);
+    }
+
     #[test]
     fn never_inserts() {
         let map = Map::new();
@@ -679,6 +1155,57 @@ mod test {
         assert_eq!(*removed.val(), 4);
     }
 
+    #[test]
+    fn remove_if_checks_value() {
+        let map = Map::new();
+        assert!(map.remove_if("gen", |_| true).is_none());
+        map.insert("gen".to_owned(), 3);
+        assert!(map.remove_if("gen", |&gen| gen == 2).is_none());
+        assert_eq!(*map.get("gen").unwrap().val(), 3);
+        let removed = map.remove_if("gen", |&gen| gen == 3).unwrap();
+        assert_eq!(removed.key(), "gen");
+        assert_eq!(*removed.val(), 3);
+        assert!(map.get("gen").is_none());
+    }
+
+    #[test]
+    fn merge_from_resolves_conflicts() {
+        let map = Arc::new(Map::new());
+        map.insert("common", 1);
+        map.insert("mine", 10);
+
+        let mut threads = Vec::new();
+        for i in 0 .. 4 {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                let other = Map::new();
+                other.insert("common", 1);
+                other.insert(if i % 2 == 0 { "even" } else { "odd" }, 1);
+                map.merge_from(other, |_, old, new| old + new);
+            }));
+        }
+        for thread in threads {
+            thread.join().expect("thread failed");
+        }
+
+        assert_eq!(*map.get("common").unwrap().val(), 5);
+        assert_eq!(*map.get("mine").unwrap().val(), 10);
+        assert_eq!(*map.get("even").unwrap().val(), 2);
+        assert_eq!(*map.get("odd").unwrap().val(), 2);
+    }
+
+    #[test]
+    fn insert_returns_displaced_pair() {
+        let map = Map::new();
+        assert!(map.insert("five".to_owned(), vec![5]).is_none());
+        let old = map.insert("five".to_owned(), vec![50]).unwrap();
+        assert_eq!(old.key(), "five");
+        assert_eq!(*old.val(), [5]);
+        let (key, val) = Removed::try_into(old).unwrap();
+        assert_eq!(key, "five");
+        assert_eq!(val, [5]);
+    }
+
     #[test]
     fn repeated_inserts() {
         let map = Map::new();
@@ -721,6 +1248,93 @@ mod test {
         }
     }
 
+    #[test]
+    fn snapshot_iter_yields_each_key_once() {
+        let map = Arc::new(Map::new());
+        for i in 0 .. 512u32 {
+            map.insert(i, i);
+        }
+
+        let writer = {
+            let map = map.clone();
+            thread::spawn(move || {
+                for round in 1 .. 8 {
+                    for i in 0 .. 512u32 {
+                        map.insert(i, i * round);
+                    }
+                }
+            })
+        };
+
+        let keys = map.snapshot_iter().map(|guard| *guard.key());
+        assert!(keys.eq(0 .. 512));
+        writer.join().expect("thread failed");
+    }
+
+    #[test]
+    fn tombstones_are_counted_and_purged() {
+        let builder = BuildHasherDefault::<ConstHasher>::default();
+        let map = Map::with_hasher(builder);
+        for i in 0 .. 10 {
+            map.insert(i, i);
+        }
+        // All entries are in the same bucket. Removing in reverse order leaves
+        // the removed entries logically removed, since searches stop before
+        // them.
+        for i in (5 .. 10).rev() {
+            map.remove(&i);
+        }
+        assert_eq!(map.purge_tombstones(), 5);
+        assert_eq!(map.purge_tombstones(), 0);
+
+        for i in (1 .. 5).rev() {
+            map.remove(&i);
+        }
+        let mut iter = map.iter();
+        assert_eq!(iter.by_ref().count(), 1);
+        assert_eq!(iter.skipped(), 4);
+        drop(iter);
+        assert_eq!(map.purge_tombstones(), 0);
+        assert_eq!(map.stats(), Stats { len: 1, nodes: 2 });
+    }
+
+    #[test]
+    fn optimize_space_collapses_branches() {
+        let mut map = Map::new();
+        for i in 0 .. 10000 {
+            map.insert(i, i);
+        }
+        for i in 1 .. 10000 {
+            map.remove(&i);
+        }
+        let churned = map.stats();
+        assert_eq!(churned.len, 1);
+
+        map.optimize_space();
+        let compacted = map.stats();
+        assert_eq!(compacted, Stats { len: 1, nodes: 2 });
+        assert!(compacted.nodes < churned.nodes);
+        assert_eq!(*map.get(&0).unwrap().val(), 0);
+    }
+
+    #[test]
+    fn with_capacity_prebuilds_branches() {
+        assert_eq!(Map::<u8, u8>::with_capacity(0).stats().nodes, 1);
+        assert_eq!(Map::<u8, u8>::with_capacity(511).stats().nodes, 1);
+        let map = Map::with_capacity(512);
+        assert_eq!(map.stats(), Stats { len: 0, nodes: 257 });
+        assert!(map.get(&3).is_none());
+
+        for i in 0 .. 512 {
+            assert!(map.insert(i, i * 2).is_none());
+        }
+        for i in 0 .. 512 {
+            assert_eq!(*map.get(&i).unwrap().val(), i * 2);
+        }
+        assert_eq!(map.len(), 512);
+        assert_eq!(map.iter().count(), 512);
+    }
+
     #[test]
     fn optimize_space_preserves_entries() {
         let mut map = Map::new();
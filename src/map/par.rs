@@ -0,0 +1,145 @@
+use super::{
+    guard::ReadGuard,
+    iter::Iter,
+    table::{self, Table},
+    Map,
+};
+use rayon::iter::{
+    plumbing::UnindexedConsumer,
+    IntoParallelIterator,
+    ParallelExtend,
+    ParallelIterator,
+};
+use std::{
+    fmt,
+    hash::{BuildHasher, Hash},
+};
+
+impl<K, V, H> Map<K, V, H> {
+    /// Creates a parallel iterator over guarded references to the key-value
+    /// entries. The work is split by the top-level branches of the [`Map`].
+    /// Requires the `rayon` feature.
+    pub fn par_iter<'map>(&'map self) -> ParIter<'map, K, V, H>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+        H: Sync,
+    {
+        self.into_par_iter()
+    }
+}
+
+impl<K, V, H> Map<K, V, H>
+where
+    H: BuildHasher,
+{
+    /// Acts just like [`ParallelExtend::par_extend`] but does not require
+    /// mutability. Requires the `rayon` feature.
+    pub fn par_extend<I>(&self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+        K: Hash + Ord + Send + Sync,
+        V: Send + Sync,
+        H: Sync,
+    {
+        par_iter.into_par_iter().for_each(|(key, val)| {
+            self.insert(key, val);
+        })
+    }
+}
+
+impl<'map, K, V, H> IntoParallelIterator for &'map Map<K, V, H>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    H: Sync,
+{
+    type Item = ReadGuard<'map, K, V>;
+
+    type Iter = ParIter<'map, K, V, H>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { map: self }
+    }
+}
+
+impl<K, V, H> ParallelExtend<(K, V)> for Map<K, V, H>
+where
+    H: BuildHasher + Sync,
+    K: Hash + Ord + Send + Sync,
+    V: Send + Sync,
+{
+    fn par_extend<I>(&mut self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        (&*self).par_extend(par_iter)
+    }
+}
+
+/// A parallel iterator over key-value entries of a [`Map`]. The `Item` of
+/// this iterator is a [`ReadGuard`]. Each top-level branch of the `Map` is a
+/// separate work item. The same consistency guarantees of [`Iter`] apply.
+pub struct ParIter<'map, K, V, H>
+where
+    K: 'map,
+    V: 'map,
+    H: 'map,
+{
+    map: &'map Map<K, V, H>,
+}
+
+impl<'map, K, V, H> ParallelIterator for ParIter<'map, K, V, H>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    H: Sync,
+{
+    type Item = ReadGuard<'map, K, V>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let map = self.map;
+        let top: &'map Table<K, V> = &map.top;
+
+        (0 .. table::SLOTS)
+            .into_par_iter()
+            .flat_map_iter(move |index| {
+                let pause = map.incin.inner.pause();
+                Iter::with_slots(pause, top, index, index + 1)
+            })
+            .drive_unindexed(consumer)
+    }
+}
+
+impl<'map, K, V, H> fmt::Debug for ParIter<'map, K, V, H> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "ParIter {} map: {:p} {}", '{', self.map, '}')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn par_extend_and_par_iter() {
+        let map = Map::new();
+        map.par_extend((0 .. 10000u32).into_par_iter().map(|i| (i, i * 3)));
+
+        let keys = map
+            .par_iter()
+            .map(|guard| {
+                assert_eq!(*guard.val(), *guard.key() * 3);
+                *guard.key()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys.len(), 10000);
+        let keys = keys.into_iter().collect::<HashSet<_>>();
+        assert_eq!(keys, (0 .. 10000).collect());
+    }
+}
@@ -1,5 +1,5 @@
 use super::{
-    bucket::{Bucket, Garbage, GetRes, InsertRes},
+    bucket::{Bucket, Garbage, GetRes, InsertRes, PurgeRes},
     guard::{ReadGuard, Removed},
     insertion::{Inserter, Insertion},
 };
@@ -21,10 +21,13 @@ use std::{
 
 const BITS: usize = 8;
 
+// The number of nodes in a table.
+pub const SLOTS: usize = 1 << BITS;
+
 // If you remove this alignment, don't remove it. Please, set it to 2.
 #[repr(align(64))]
 pub struct Table<K, V> {
-    nodes: [Node<K, V>; 1 << BITS],
+    nodes: [Node<K, V>; SLOTS],
 }
 
 impl<K, V> Table<K, V> {
@@ -36,6 +39,42 @@ impl<K, V> Table<K, V> {
         }
     }
 
+    // Allocates a table whose nodes are already branches, up to the given
+    // number of levels below this table. Empty branches are valid, so this is
+    // only a way of paying the allocation of branches beforehand.
+    pub fn new_alloc_branched(levels: usize) -> OwnedAlloc<Self> {
+        let table = Self::new_alloc();
+
+        if levels > 0 {
+            for node in &table.nodes as &[Node<K, V>] {
+                let branch = Self::new_alloc_branched(levels - 1).into_raw();
+                // Let's not forget to mark the lower bit!
+                let marked = (branch.as_ptr() as usize | 1) as *mut ();
+                node.atomic.store(marked, Relaxed);
+            }
+        }
+
+        table
+    }
+
+    // Computes how many levels of branches are worth allocating beforehand for
+    // the given expected number of entries. A level is only allocated if the
+    // entries would probably create most of its branches anyway.
+    pub fn levels_for(capacity: usize) -> usize {
+        let mut levels = 0;
+        let mut slots = SLOTS;
+
+        while capacity / 2 >= slots {
+            levels += 1;
+            slots = match slots.checked_mul(SLOTS) {
+                Some(slots) => slots,
+                None => break,
+            };
+        }
+
+        levels
+    }
+
     // Unsafe because passing ininitialized memory may cause leaks.
     #[inline]
     pub unsafe fn init_in_place(&mut self) {
@@ -461,7 +500,9 @@ impl<K, V> Table<K, V> {
                             let nnptr = NonNull::new_unchecked(table_ptr);
                             OwnedAlloc::from_raw(nnptr);
                         }
-                        node.atomic.store(bucket.as_ptr() as *mut _, Relaxed)
+                        node.atomic.store(bucket.as_ptr() as *mut _, Relaxed);
+                        // The bucket might collapse further into our parent.
+                        last_bucket = Some(bucket);
                     },
                 }
             }
@@ -476,6 +517,83 @@ impl<K, V> Table<K, V> {
         }
     }
 
+    // Physically removes logically removed entries and empty buckets reachable
+    // from this table, returning how many were removed. Empty branches are
+    // kept, since unlinking them could lose concurrent insertions. Unsafe
+    // because the incinerator needs to be paused.
+    pub unsafe fn purge(&self, pause: &Pause<Garbage<K, V>>) -> usize {
+        let mut purged = 0;
+        let mut tables = vec![self];
+
+        while let Some(table) = tables.pop() {
+            for node in &table.nodes as &[Node<K, V>] {
+                let loaded = node.atomic.load(Acquire);
+
+                if loaded.is_null() {
+                    continue;
+                }
+
+                if loaded as usize & 1 == 1 {
+                    // A branch. Let's not forget to clear the marked bit.
+                    tables.push(&*((loaded as usize & !1) as *const Self));
+                    continue;
+                }
+
+                let bucket = &*(loaded as *mut Bucket<K, V>);
+                let PurgeRes { cleared, delete } = bucket.purge(pause);
+                purged += cleared;
+
+                if delete {
+                    let res = node.atomic.compare_exchange(
+                        loaded,
+                        null_mut(),
+                        AcqRel,
+                        Relaxed,
+                    );
+
+                    if res.is_ok() {
+                        let alloc = OwnedAlloc::from_raw(
+                            NonNull::new_unchecked(loaded as *mut _),
+                        );
+                        pause.add_to_incin(Garbage::Bucket(alloc));
+                        purged += 1;
+                    }
+                }
+            }
+        }
+
+        purged
+    }
+
+    // Counts the tables and buckets reachable from this table, including the
+    // table itself. Unsafe because the incinerator needs to be paused.
+    pub unsafe fn count_nodes(&self) -> usize {
+        let mut count = 0;
+        let mut tables = vec![self];
+
+        while let Some(table) = tables.pop() {
+            count += 1;
+
+            for node in &table.nodes as &[Node<K, V>] {
+                let loaded = node.atomic.load(Acquire);
+
+                if loaded.is_null() {
+                    continue;
+                }
+
+                if loaded as usize & 1 == 0 {
+                    // A bucket.
+                    count += 1;
+                } else {
+                    // A branch. Let's not forget to clear the marked bit.
+                    tables.push(&*((loaded as usize & !1) as *const Self));
+                }
+            }
+        }
+
+        count
+    }
+
     pub fn load_index(
         &self,
         index: usize,
@@ -0,0 +1,301 @@
+use collections::{Collection, Stats};
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering::*},
+};
+
+/// A lock-free bounded queue. FIFO semanthics are fully respected. It can be
+/// used as multi-producer and multi-consumer channel. Unlike
+/// [`Queue`](super::Queue), the capacity is fixed at creation and no memory is
+/// allocated after it: pushing into a full queue fails and gives the value
+/// back.
+///
+/// # Design
+/// The queue is a ring buffer of slots. Each slot has a sequence number which
+/// tells whether the slot is ready to be written or read in the current lap of
+/// the ring. Producers and consumers claim positions by incrementing the back
+/// and the front counters, respectively.
+pub struct Bounded<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    front: Padded<AtomicUsize>,
+    back: Padded<AtomicUsize>,
+}
+
+impl<T> Bounded<T> {
+    /// Creates a new empty queue able to hold at least the given number of
+    /// items. The capacity is rounded up to a power of two, and it is at
+    /// least two.
+    pub fn with_capacity(capacity: usize) -> Self {
+        // With a single slot, the sequence number of a full slot would be the
+        // same as of an empty one in the next lap.
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0 .. capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i),
+                item: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Self {
+            slots,
+            mask: capacity - 1,
+            front: Padded(AtomicUsize::new(0)),
+            back: Padded(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The number of items this queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Tries to push a value into the back of the queue. If the queue is full,
+    /// the value is given back in the `Err`.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        let mut pos = self.back.0.load(Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Acquire);
+            // The slot is ready to be written in this lap only if its sequence
+            // matches the position.
+            let diff = seq.wrapping_sub(pos) as isize;
+
+            if diff == 0 {
+                let res = self.back.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                );
+
+                match res {
+                    Ok(_) => {
+                        // Safe because we claimed this position, and nobody
+                        // else will access the slot until we publish it.
+                        unsafe { (*slot.item.get()).as_mut_ptr().write(item) };
+                        slot.seq.store(pos.wrapping_add(1), Release);
+                        break Ok(());
+                    },
+
+                    Err(new) => pos = new,
+                }
+            } else if diff < 0 {
+                // The slot was not read in the previous lap yet: full.
+                break Err(item);
+            } else {
+                // Some other producer claimed the position.
+                pos = self.back.0.load(Relaxed);
+            }
+        }
+    }
+
+    /// Takes a value from the front of the queue, if it is avaible.
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.front.0.load(Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Acquire);
+            // The slot is ready to be read in this lap only if a producer
+            // published the position.
+            let diff = seq.wrapping_sub(pos.wrapping_add(1)) as isize;
+
+            if diff == 0 {
+                let res = self.front.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                );
+
+                match res {
+                    Ok(_) => {
+                        // Safe because we claimed this position, and the
+                        // producer initialized the slot before publishing it.
+                        let item =
+                            unsafe { (*slot.item.get()).as_ptr().read() };
+                        // Ready to be written in the next lap.
+                        let next_lap = pos.wrapping_add(self.slots.len());
+                        slot.seq.store(next_lap, Release);
+                        break Some(item);
+                    },
+
+                    Err(new) => pos = new,
+                }
+            } else if diff < 0 {
+                // The slot was not written in this lap yet: empty.
+                break None;
+            } else {
+                // Some other consumer claimed the position.
+                pos = self.front.0.load(Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Bounded<T> {
+    fn drop(&mut self) {
+        while let Some(_) = self.pop() {}
+    }
+}
+
+impl<T> Collection for Bounded<T> {
+    fn len(&self) -> usize {
+        let front = self.front.0.load(Acquire);
+        let back = self.back.0.load(Acquire);
+        back.wrapping_sub(front).min(self.capacity())
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop() {}
+    }
+
+    fn stats(&self) -> Stats {
+        Stats { len: self.len(), nodes: self.capacity() }
+    }
+}
+
+impl<T> fmt::Debug for Bounded<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Bounded {} capacity: {}, front: {:?}, back: {:?} {}",
+            '{',
+            self.capacity(),
+            self.front.0,
+            self.back.0,
+            '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for Bounded<T> where T: Send {}
+unsafe impl<T> Sync for Bounded<T> where T: Send {}
+
+struct Slot<T> {
+    seq: AtomicUsize,
+    item: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Keeps the front and the back in different cache lines, so producers and
+// consumers do not contend for the same line.
+#[repr(align(64))]
+struct Padded<T>(T);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn rounds_capacity() {
+        assert_eq!(Bounded::<u8>::with_capacity(0).capacity(), 2);
+        assert_eq!(Bounded::<u8>::with_capacity(1).capacity(), 2);
+        assert_eq!(Bounded::<u8>::with_capacity(5).capacity(), 8);
+        assert_eq!(Bounded::<u8>::with_capacity(8).capacity(), 8);
+    }
+
+    #[test]
+    fn smallest_queue_gets_full() {
+        let queue = Bounded::with_capacity(1);
+        assert_eq!(queue.try_push(1), Ok(()));
+        assert_eq!(queue.try_push(2), Ok(()));
+        assert_eq!(queue.try_push(3), Err(3));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn full_gives_back() {
+        let queue = Bounded::with_capacity(2);
+        assert!(queue.pop().is_none());
+        queue.try_push(1).unwrap();
+        queue.try_push(2).unwrap();
+        assert_eq!(queue.try_push(3), Err(3));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop(), Some(1));
+        queue.try_push(3).unwrap();
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn wraps_around() {
+        let queue = Bounded::with_capacity(4);
+        for i in 0 .. 100 {
+            queue.try_push(i).unwrap();
+            queue.try_push(i + 1000).unwrap();
+            assert_eq!(queue.pop(), Some(i));
+            assert_eq!(queue.pop(), Some(i + 1000));
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let item = Arc::new(());
+        let queue = Bounded::with_capacity(4);
+        queue.try_push(item.clone()).unwrap();
+        queue.try_push(item.clone()).unwrap();
+        drop(queue);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 1000;
+
+        let queue = Arc::new(Bounded::with_capacity(16));
+        let mut producers = Vec::new();
+        let mut consumers = Vec::new();
+
+        for i in 0 .. NTHREAD {
+            let queue = queue.clone();
+            producers.push(thread::spawn(move || {
+                for j in 0 .. NITER {
+                    let mut item = i * NITER + j;
+                    while let Err(back) = queue.try_push(item) {
+                        item = back;
+                        thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        for _ in 0 .. NTHREAD {
+            let queue = queue.clone();
+            consumers.push(thread::spawn(move || {
+                let mut sum = 0;
+                for _ in 0 .. NITER {
+                    loop {
+                        if let Some(item) = queue.pop() {
+                            sum += item;
+                            break;
+                        }
+                        thread::yield_now();
+                    }
+                }
+                sum
+            }));
+        }
+
+        for producer in producers {
+            producer.join().expect("thread failed");
+        }
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.join().expect("thread failed");
+        }
+
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+        assert!(queue.pop().is_none());
+    }
+}
@@ -0,0 +1,61 @@
+use super::Queue;
+use future;
+
+impl<T> Queue<T> {
+    /// Creates a future which resolves to a value taken from the front of the
+    /// queue, waiting asynchronously until one is available. The task is woken
+    /// up by [`push`](Queue::push). Requires the `futures` feature.
+    pub fn pop_async<'queue>(&'queue self) -> PopFuture<'queue, T> {
+        future::PopFuture::new(self, &self.waiters, Queue::pop)
+    }
+}
+
+/// A future based on [`pop`](Queue::pop) operation of the [`Queue`]. Created
+/// by [`pop_async`](Queue::pop_async). Dropping it before completion is fine.
+pub type PopFuture<'queue, T> = future::PopFuture<'queue, Queue<T>, T>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use executor::block_on;
+    use std::{sync::Arc, thread, time::Duration};
+
+    #[test]
+    fn ready_if_not_empty() {
+        let queue = Queue::new();
+        queue.push(3);
+        assert_eq!(block_on(queue.pop_async()), 3);
+    }
+
+    #[test]
+    fn woken_up_by_push() {
+        const NTHREAD: usize = 4;
+        const NITER: usize = 200;
+
+        let queue = Arc::new(Queue::new());
+        let mut consumers = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let queue = queue.clone();
+            consumers.push(thread::spawn(move || {
+                (0 .. NITER).map(|_| block_on(queue.pop_async())).sum::<usize>()
+            }));
+        }
+
+        // Giving the consumers time to wait.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. NTHREAD * NITER {
+            if i % 50 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            queue.push(i);
+        }
+
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.join().expect("thread failed");
+        }
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+    }
+}
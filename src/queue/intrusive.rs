@@ -0,0 +1,323 @@
+use owned_alloc::OwnedAlloc;
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{AtomicPtr, Ordering::*},
+        Arc,
+    },
+};
+
+/// The link embedded in values of an intrusive queue. Values are chained
+/// through their links, so the queue itself never allocates nodes.
+pub struct Link {
+    next: AtomicPtr<Link>,
+}
+
+impl Link {
+    /// Creates a new unlinked link.
+    pub fn new() -> Self {
+        Self { next: AtomicPtr::new(null_mut()) }
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Link {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Link {} next: {:?} {}", '{', self.next, '}')
+    }
+}
+
+/// Types which embed a [`Link`] and therefore can be pushed into an intrusive
+/// queue.
+///
+/// # Safety
+/// Implementors must be `#[repr(C)]` structs whose first field is the
+/// [`Link`], so a pointer to the value is also a pointer to its link. The
+/// link must not be used for anything else.
+///
+/// # Example
+/// ```rust
+/// use lockfree::queue::intrusive::{self, Link, Linked};
+///
+/// #[repr(C)]
+/// struct Message {
+///     link: Link,
+///     payload: u64,
+/// }
+///
+/// unsafe impl Linked for Message {}
+///
+/// let (producer, mut consumer) = intrusive::create();
+/// producer.push(Box::new(Message { link: Link::new(), payload: 42 }));
+/// assert_eq!(consumer.pop().map(|msg| msg.payload), Some(42));
+/// ```
+pub unsafe trait Linked {}
+
+/// Creates an intrusive lock-free Multi-Producer-Single-Consumer (MPSC) queue.
+/// Values are boxed by the user and linked through their embedded [`Link`],
+/// so pushing and popping never allocate; boxes can be reused. In order to
+/// allow multiple producers, [`Producer`] is clonable and does not require
+/// mutability.
+///
+/// # Design
+/// This is Vyukov's intrusive MPSC queue. Producers append with a single swap
+/// on the back. A stub link owned by the queue keeps it from ever being
+/// empty, and the consumer puts the stub back when it takes the last value.
+pub fn create<T>() -> (Producer<T>, Consumer<T>)
+where
+    T: Linked,
+{
+    let stub = OwnedAlloc::new(Link::new()).into_raw().as_ptr();
+    let inner = Arc::new(Inner {
+        back: AtomicPtr::new(stub),
+        front: UnsafeCell::new(stub),
+        stub,
+        _marker: PhantomData,
+    });
+    (Producer { inner: inner.clone() }, Consumer { inner })
+}
+
+/// The producer handle of an intrusive queue. Created by [`create`] function.
+/// It is clonable and does not require mutability.
+pub struct Producer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Producer<T>
+where
+    T: Linked,
+{
+    /// Pushes a value into the back of the queue. This operation is
+    /// wait-free.
+    pub fn push(&self, item: Box<T>) {
+        // Safe because `Linked` requires the link to be the first field.
+        let link = Box::into_raw(item) as *mut Link;
+        // Safe because the box was just released by us.
+        unsafe { self.inner.push(link) }
+    }
+}
+
+impl<T> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Producer {} inner: {:?} {}", '{', self.inner, '}')
+    }
+}
+
+unsafe impl<T> Send for Producer<T> where T: Send {}
+unsafe impl<T> Sync for Producer<T> where T: Send {}
+
+/// The consumer handle of an intrusive queue. Created by [`create`] function.
+/// There is only one consumer, so popping requires mutability.
+pub struct Consumer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Consumer<T>
+where
+    T: Linked,
+{
+    /// Takes a value from the front of the queue, if it is avaible. Note that
+    /// a value being pushed concurrently may not be available yet, even if
+    /// values pushed after it were already pushed.
+    pub fn pop(&mut self) -> Option<Box<T>> {
+        // Safe because we are the only consumer, and the links in the queue
+        // were all created from boxes of `T`, except for the stub, which is
+        // never returned.
+        unsafe { self.inner.pop().map(|link| Box::from_raw(link as *mut T)) }
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Consumer {} inner: {:?} {}", '{', self.inner, '}')
+    }
+}
+
+unsafe impl<T> Send for Consumer<T> where T: Send {}
+
+struct Inner<T> {
+    back: AtomicPtr<Link>,
+    // Only accessed by the consumer.
+    front: UnsafeCell<*mut Link>,
+    stub: *mut Link,
+    _marker: PhantomData<Box<T>>,
+}
+
+impl<T> Inner<T> {
+    // Unsafe because the link must be valid and not in the queue.
+    unsafe fn push(&self, link: *mut Link) {
+        (*link).next.store(null_mut(), Relaxed);
+        let prev = self.back.swap(link, AcqRel);
+        // This may delay the visibility of the insertion.
+        (*prev).next.store(link, Release);
+    }
+
+    // Unsafe because it must only be called by the consumer.
+    unsafe fn pop(&self) -> Option<*mut Link> {
+        let front_ptr = self.front.get();
+        let mut front = *front_ptr;
+        let mut next = (*front).next.load(Acquire);
+
+        if front == self.stub {
+            // Skipping the stub.
+            if next.is_null() {
+                return None;
+            }
+            *front_ptr = next;
+            front = next;
+            next = (*next).next.load(Acquire);
+        }
+
+        if !next.is_null() {
+            // Nobody will write to the link of the front anymore, since its
+            // next field was already set.
+            *front_ptr = next;
+            return Some(front);
+        }
+
+        if front != self.back.load(Acquire) {
+            // A push is in progress: the back was updated, but the link to
+            // the new node was not set yet.
+            return None;
+        }
+
+        // The front is the last link. We put the stub back so we can take
+        // the front without leaving the queue without links.
+        self.push(self.stub);
+        next = (*front).next.load(Acquire);
+        if next.is_null() {
+            None
+        } else {
+            *front_ptr = next;
+            Some(front)
+        }
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // Safe because we have exclusive access, and no push is in progress.
+        while let Some(link) = unsafe { self.pop() } {
+            // Safe because all links but the stub are boxes of `T`.
+            drop(unsafe { Box::from_raw(link as *mut T) });
+        }
+        // Safe because the stub was allocated via `OwnedAlloc` and it is not
+        // in use anymore.
+        unsafe {
+            OwnedAlloc::from_raw(NonNull::new_unchecked(self.stub));
+        }
+    }
+}
+
+impl<T> fmt::Debug for Inner<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Inner {} back: {:?}, stub: {:?} {}",
+            '{', self.back, self.stub, '}'
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[repr(C)]
+    struct Message<T> {
+        link: Link,
+        payload: T,
+    }
+
+    unsafe impl<T> Linked for Message<T> {}
+
+    fn message<T>(payload: T) -> Box<Message<T>> {
+        Box::new(Message { link: Link::new(), payload })
+    }
+
+    #[test]
+    fn order() {
+        let (producer, mut consumer) = create();
+        assert!(consumer.pop().is_none());
+        producer.push(message(1));
+        producer.push(message(2));
+        assert_eq!(consumer.pop().map(|msg| msg.payload), Some(1));
+        producer.push(message(3));
+        assert_eq!(consumer.pop().map(|msg| msg.payload), Some(2));
+        assert_eq!(consumer.pop().map(|msg| msg.payload), Some(3));
+        assert!(consumer.pop().is_none());
+
+        // Boxes can be reused.
+        let mut msg = message(4);
+        for i in 5 .. 8 {
+            producer.push(msg);
+            msg = consumer.pop().unwrap();
+            msg.payload = i;
+        }
+        assert_eq!(msg.payload, 7);
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let item = Arc::new(());
+        let (producer, mut consumer) = create();
+        for _ in 0 .. 10 {
+            producer.push(message(item.clone()));
+        }
+        consumer.pop();
+        drop(consumer);
+        producer.push(message(item.clone()));
+        drop(producer);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+
+        let (producer, mut consumer) = create();
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let producer = producer.clone();
+            handles.push(thread::spawn(move || {
+                for j in 0 .. NITER {
+                    producer.push(message((i, j)));
+                }
+            }));
+        }
+
+        let mut last = vec![None; NTHREAD];
+        let mut count = 0;
+        while count < NTHREAD * NITER {
+            if let Some(msg) = consumer.pop() {
+                let (i, j) = msg.payload;
+                // Values of a single producer must come in order.
+                assert!(last[i] < Some(j));
+                last[i] = Some(j);
+                count += 1;
+            }
+        }
+
+        for handle in handles {
+            handle.join().expect("thread failed");
+        }
+        assert!(consumer.pop().is_none());
+    }
+}
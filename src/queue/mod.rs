@@ -0,0 +1,970 @@
+mod bounded;
+#[cfg(feature = "futures")]
+mod future;
+/// An intrusive lock-free MPSC queue, whose values embed their own links.
+pub mod intrusive;
+mod priority;
+mod segmented;
+mod work_stealing;
+
+#[cfg(feature = "futures")]
+pub use self::future::PopFuture;
+pub use self::{
+    bounded::Bounded,
+    priority::{PeekGuard, Popped, Priority},
+    segmented::{Segmented, SEGMENT_LEN},
+    work_stealing::{Stealer, WorkStealing},
+};
+
+use collections::{Collection, Stats};
+use incin::Pause;
+use owned_alloc::OwnedAlloc;
+use ptr::{bypass_null, check_null_align};
+use recycle::{NodeCache, Recycled};
+use removable::Removable;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    iter::FromIterator,
+    mem,
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{AtomicPtr, Ordering::*},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use wait::WaitList;
+
+/// A lock-free general-purpouse queue. FIFO semanthics are fully respected.
+/// It can be used as multi-producer and multi-consumer channel.
+pub struct Queue<T> {
+    front: AtomicPtr<Node<T>>,
+    back: AtomicPtr<Node<T>>,
+    incin: SharedIncin<T>,
+    cache: Arc<NodeCache<Node<T>>>,
+    waiters: WaitList,
+}
+
+impl<T> Queue<T> {
+    /// Creates a new empty queue.
+    pub fn new() -> Self {
+        check_null_align::<Node<T>>();
+        Self::with_incin(SharedIncin::new())
+    }
+
+    /// Creates an empty queue using the passed shared incinerator.
+    pub fn with_incin(incin: SharedIncin<T>) -> Self {
+        let node = Node::new(Removable::empty());
+        let sentinel = OwnedAlloc::new(node).into_raw().as_ptr();
+        Self {
+            front: AtomicPtr::new(sentinel),
+            back: AtomicPtr::new(sentinel),
+            incin,
+            cache: Arc::new(NodeCache::new()),
+            waiters: WaitList::new(),
+        }
+    }
+
+    /// Returns the shared incinerator used by this [`Queue`].
+    pub fn incin(&self) -> SharedIncin<T> {
+        self.incin.clone()
+    }
+
+    /// Creates an iterator over copies of the values currently in the queue,
+    /// from the front to the back, without taking them. Values pushed or
+    /// popped by other threads meanwhile may or may not be seen. Since popping
+    /// moves values out of the queue, only `Copy` values can be observed this
+    /// way.
+    pub fn iter<'queue>(&'queue self) -> Iter<'queue, T>
+    where
+        T: Copy,
+    {
+        // Pausing because we will walk through shared nodes.
+        let pause = self.incin.inner.pause();
+        let curr = self.front.load(Acquire);
+        Iter { curr, pause }
+    }
+
+    /// Creates an iterator over `T`s, based on [`pop`](Queue::pop) operation of
+    /// the [`Queue`].
+    pub fn pop_iter<'queue>(&'queue self) -> PopIter<'queue, T> {
+        PopIter { queue: self }
+    }
+
+    /// Pushes a value into the back of the queue. This operation is also
+    /// wait-free, unless there are threads blocked in
+    /// [`pop_wait`](Queue::pop_wait) (or tasks waiting on `pop_async`). In
+    /// that case, one of them is woken up, which is only lock-free.
+    pub fn push(&self, item: T) {
+        // Pretty simple: create a node from the item.
+        let node = Node::new(Removable::new(item));
+        let alloc = self.cache.alloc(node);
+        let node_ptr = alloc.into_raw().as_ptr();
+        // Swap with the previously stored back.
+        let prev_back = self.back.swap(node_ptr, AcqRel);
+        unsafe {
+            // Updates the previous back's next field to our newly allocated
+            // node. This may delay the visibility of the insertion. `SeqCst`
+            // because of the wait list.
+            (*prev_back).next.store(node_ptr, SeqCst);
+        }
+        self.waiters.notify_one();
+    }
+
+    /// Pushes the values of the given iterable into the back of the queue, in
+    /// order. The nodes are linked locally first, and then the whole chain is
+    /// appended with a single update of the back, so this is cheaper than
+    /// calling [`push`](Queue::push) in a loop when there are many producers.
+    pub fn push_iter<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut chain = Chain { first: null_mut(), last: null_mut(), len: 0 };
+
+        for item in iterable {
+            let node = Node::new(Removable::new(item));
+            let node_ptr = self.cache.alloc(node).into_raw().as_ptr();
+            if chain.last.is_null() {
+                chain.first = node_ptr;
+            } else {
+                // Safe because the chain is not shared yet and its nodes are
+                // not null.
+                unsafe { (*chain.last).next.store(node_ptr, Relaxed) };
+            }
+            chain.last = node_ptr;
+            chain.len += 1;
+        }
+
+        if chain.len == 0 {
+            return;
+        }
+
+        // Swap with the previously stored back, just like in `push`.
+        let prev_back = self.back.swap(chain.last, AcqRel);
+        unsafe {
+            // Updates the previous back's next field to the first node of the
+            // chain. This may delay the visibility of the insertion. `SeqCst`
+            // because of the wait list.
+            (*prev_back).next.store(chain.first, SeqCst);
+        }
+
+        let len = chain.len;
+        // The nodes now belong to the queue.
+        mem::forget(chain);
+        for _ in 0 .. len {
+            self.waiters.notify_one();
+        }
+    }
+
+    /// Takes a value from the front of the queue, if it is avaible.
+    pub fn pop(&self) -> Option<T> {
+        // Pausing because of ABA problem involving remotion from linked lists.
+        let pause = self.incin.inner.pause();
+        let mut front_nnptr = unsafe {
+            // The pointer stored in front and back must never be null. The
+            // queue always have at least one node. Front and back are
+            // always connected.
+            bypass_null(self.front.load(Relaxed))
+        };
+
+        loop {
+            // This dereferral is safe because we paused the incinerator and
+            // only delete nodes via incinerator.
+            //
+            // We first remove the node logically.
+            match unsafe { front_nnptr.as_ref().item.take(AcqRel) } {
+                Some(val) => {
+                    // Safe to call because we passed a pointer from the front
+                    // which was loaded during the very same pause we are
+                    // passing.
+                    unsafe { self.try_clear_first(front_nnptr, &pause) };
+                    break Some(val);
+                },
+
+                // Safe to call because we passed a pointer from the front
+                // which was loaded during the very same pause we are
+                // passing.
+                None => unsafe {
+                    front_nnptr = self.try_clear_first(front_nnptr, &pause)?;
+                },
+            }
+        }
+    }
+
+    /// Takes a value from the front of the queue, blocking the current thread
+    /// until one is available. The thread is parked rather than spinning, and
+    /// it is woken up by [`push`](Queue::push).
+    pub fn pop_wait(&self) -> T {
+        loop {
+            if let Some(item) = self.pop() {
+                break item;
+            }
+
+            let waiter = self.waiters.register();
+            // Checking again because a push might have happened before we
+            // registered ourselves.
+            match self.pop() {
+                Some(item) => {
+                    waiter.cancel(&self.waiters);
+                    break item;
+                },
+
+                None => waiter.wait(),
+            }
+        }
+    }
+
+    /// Takes a value from the front of the queue, blocking the current thread
+    /// until one is available or until the given duration elapses, in which
+    /// case `None` is returned. Just like [`pop_wait`](Queue::pop_wait), the
+    /// thread is parked rather than spinning.
+    pub fn pop_timeout(&self, dur: Duration) -> Option<T> {
+        let deadline = Instant::now() + dur;
+
+        loop {
+            if let Some(item) = self.pop() {
+                break Some(item);
+            }
+            if Instant::now() >= deadline {
+                break None;
+            }
+
+            let waiter = self.waiters.register();
+            // Checking again because a push might have happened before we
+            // registered ourselves.
+            if let Some(item) = self.pop() {
+                waiter.cancel(&self.waiters);
+                break Some(item);
+            }
+
+            if !waiter.wait_until(deadline) {
+                waiter.cancel(&self.waiters);
+                // A last try, since a push might have happened right before
+                // we gave up.
+                break self.pop();
+            }
+        }
+    }
+
+    /// Takes up to `max` values from the front of the queue, in order. The
+    /// nodes of the taken values are detached with a single update of the
+    /// front, and the incinerator is paused only once, so this is cheaper than
+    /// calling [`pop`](Queue::pop) in a loop.
+    pub fn pop_batch(&self, max: usize) -> Vec<T> {
+        let mut items = Vec::new();
+        if max == 0 {
+            return items;
+        }
+
+        // Pausing because of ABA problem involving remotion from linked lists.
+        let pause = self.incin.inner.pause();
+        // The pointer stored in front must never be null.
+        let first = unsafe { bypass_null(self.front.load(Relaxed)) };
+        let mut last = first;
+
+        loop {
+            // This dereferral is safe because we paused the incinerator and
+            // only delete nodes via incinerator.
+            let node = unsafe { last.as_ref() };
+            // Taking the item removes it logically, just like in `pop`.
+            if let Some(item) = node.item.take(AcqRel) {
+                items.push(item);
+                if items.len() == max {
+                    break;
+                }
+            }
+
+            match NonNull::new(node.next.load(Acquire)) {
+                Some(next) => last = next,
+                None => break,
+            }
+        }
+
+        // Safe because both pointers were loaded during the passed pause, and
+        // `last` is reachable from `first`.
+        unsafe { self.try_clear_until(first, last, &pause) };
+        items
+    }
+
+    /// Takes every value currently available in the queue, in order. Acts
+    /// just like [`pop_batch`](Queue::pop_batch) with no limit.
+    pub fn drain(&self) -> Vec<T> {
+        self.pop_batch(usize::max_value())
+    }
+
+    /// Pushes elements from the given iterable. Acts just like
+    /// [`Extend::extend`] but does not require mutability.
+    pub fn extend<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for elem in iterable {
+            self.push(elem);
+        }
+    }
+
+    // Returns an `Option` so we can use the try operator (?) with the function.
+    // This function is unsafe because passing the wrong pointer will lead to
+    // undefined behavior. The pointer must have been loaded from the front
+    // during the passed pause.
+    unsafe fn try_clear_first(
+        &self,
+        expected: NonNull<Node<T>>,
+        pause: &Pause<Recycled<Node<T>>>,
+    ) -> Option<NonNull<Node<T>>> {
+        let next = expected.as_ref().next.load(Acquire);
+
+        // If this is the only node, we will not remove it. We want front and
+        // back to share the same node rather than having to set both to null
+        // when the queue is empty.
+        NonNull::new(next).map(|next_nnptr| {
+            let ptr = expected.as_ptr();
+
+            // We are not oblied to succeed. This is just cleanup and some other
+            // thread might do it.
+            match self.front.compare_exchange(ptr, next, Relaxed, Relaxed) {
+                Ok(_) => {
+                    // Only deleting nodes via incinerator due to ABA problem
+                    // and use-after-frees.
+                    pause.add_to_incin(Recycled::new(
+                        OwnedAlloc::from_raw(expected),
+                        &self.cache,
+                    ));
+                    next_nnptr
+                },
+
+                Err(found) => {
+                    // Safe to by-pass the check since we only store non-null
+                    // pointers on the front.
+                    bypass_null(found)
+                },
+            }
+        })
+    }
+    // Detaches the nodes from `first` (inclusive) to `last` (exclusive) by
+    // setting the front to `last`, which is kept as the new first node. The
+    // items of the detached nodes must have been already taken. This function
+    // is unsafe because passing the wrong pointers will lead to undefined
+    // behavior. Both pointers must have been loaded during the passed pause,
+    // `first` from the front, and `last` must be reachable from `first`.
+    unsafe fn try_clear_until(
+        &self,
+        first: NonNull<Node<T>>,
+        last: NonNull<Node<T>>,
+        pause: &Pause<Recycled<Node<T>>>,
+    ) {
+        if first == last {
+            return;
+        }
+
+        // We are not oblied to succeed. This is just cleanup and some other
+        // thread might do it.
+        let res = self.front.compare_exchange(
+            first.as_ptr(),
+            last.as_ptr(),
+            Relaxed,
+            Relaxed,
+        );
+
+        if res.is_ok() {
+            let mut node = first;
+            while node != last {
+                // Next pointers are never changed after being set, and the
+                // chain was already walked until `last`.
+                let next = bypass_null(node.as_ref().next.load(Acquire));
+                // Only deleting nodes via incinerator due to ABA problem and
+                // use-after-frees.
+                pause.add_to_incin(Recycled::new(
+                    OwnedAlloc::from_raw(node),
+                    &self.cache,
+                ));
+                node = next;
+            }
+        }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let front = self.front.get_mut();
+        while let Some(nnptr) = NonNull::new(*front) {
+            // This is safe because we only store pointers allocated via
+            // `OwnedAlloc`. Also, we have exclusive access to this pointer.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            *front = *node.next.get_mut();
+        }
+    }
+}
+
+impl<T> FromIterator<T> for Queue<T> {
+    fn from_iter<I>(iterable: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let this = Self::new();
+        this.extend(iterable);
+        this
+    }
+}
+
+impl<T> Extend<T> for Queue<T> {
+    fn extend<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        (&*self).extend(iterable)
+    }
+}
+
+impl<T> Iterator for Queue<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let front = self.front.get_mut();
+        // Safe to by-pass it because the queue always have at least one node.
+        let mut front_node = unsafe { NonNull::new_unchecked(*front) };
+        loop {
+            // Safe because we allocated everything properly.
+            let (item, next) = unsafe {
+                let node_ref = front_node.as_mut();
+                (node_ref.item.replace(None), *node_ref.next.get_mut())
+            };
+
+            match (item, NonNull::new(next)) {
+                (Some(item), maybe_next) => {
+                    if let Some(next) = maybe_next {
+                        // Ok to drop it like this because we have exclusive
+                        // reference to the queue.
+                        unsafe { OwnedAlloc::from_raw(front_node) };
+                        *front = next.as_ptr();
+                    }
+
+                    break Some(item);
+                },
+
+                (None, None) => break None,
+
+                (None, Some(next)) => {
+                    // Ok to drop it like this because we have exclusive
+                    // reference to the queue.
+                    unsafe { OwnedAlloc::from_raw(front_node) };
+                    *front = next.as_ptr();
+                    front_node = next;
+                },
+            }
+        }
+    }
+}
+
+impl<'queue, T> IntoIterator for &'queue Queue<T> {
+    type Item = T;
+
+    type IntoIter = PopIter<'queue, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pop_iter()
+    }
+}
+
+/// Serializes the values currently in the queue, from the front to the back,
+/// without taking them.
+///
+/// `T: Copy` is required, and this is a limitation rather than an oversight. A
+/// concurrent [`pop`](Queue::pop) moves the value out of its node and hands it
+/// to the caller, who may drop it while the serializer still reads the node.
+/// The node memory stays valid thanks to the incinerator, but the value
+/// itself does not, so only values without destructors or owned resources
+/// can be read there. Other types must be taken out of the queue first, e.g.
+/// by serializing a [`Vec`] collected with [`pop_iter`](Queue::pop_iter).
+#[cfg(feature = "serde")]
+impl<T> Serialize for Queue<T>
+where
+    T: Serialize + Copy,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> Deserialize<'de> for Queue<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        Ok(items.into_iter().collect())
+    }
+}
+
+impl<T> Collection for Queue<T> {
+    fn len(&self) -> usize {
+        self.stats().len
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop() {}
+    }
+
+    fn stats(&self) -> Stats {
+        // Pausing because we will walk through shared nodes.
+        let _pause = self.incin.inner.pause();
+        let mut stats = Stats::default();
+        let mut node_ptr = self.front.load(Acquire);
+
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator. The front is never null.
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            let node = unsafe { nnptr.as_ref() };
+            stats.nodes += 1;
+            if node.item.is_present(Acquire) {
+                stats.len += 1;
+            }
+            node_ptr = node.next.load(Acquire);
+        }
+
+        stats
+    }
+}
+
+impl<T> fmt::Debug for Queue<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Queue {} front: {:?}, back: {:?}, incin: {:?}, waiters: {:?} {}",
+            '{', self.front, self.back, self.incin, self.waiters, '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for Queue<T> where T: Send {}
+unsafe impl<T> Sync for Queue<T> where T: Send {}
+
+/// An iterator based on [`pop`](Queue::pop) operation of the [`Queue`].
+pub struct PopIter<'queue, T>
+where
+    T: 'queue,
+{
+    queue: &'queue Queue<T>,
+}
+
+impl<'queue, T> Iterator for PopIter<'queue, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop()
+    }
+}
+
+impl<'queue, T> fmt::Debug for PopIter<'queue, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "PopIter {} queue: {:?} {}", '{', self.queue, '}')
+    }
+}
+
+/// An iterator over copies of the values of a [`Queue`], created by
+/// [`iter`](Queue::iter). It does not take the values.
+pub struct Iter<'queue, T>
+where
+    T: 'queue,
+{
+    curr: *mut Node<T>,
+    pause: Pause<'queue, Recycled<Node<T>>>,
+}
+
+impl<'queue, T> Iterator for Iter<'queue, T>
+where
+    T: Copy,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            // Safe to derefer the nodes because we paused the incinerator and
+            // we only delete nodes via incinerator.
+            let node = unsafe { NonNull::new(self.curr)?.as_ref() };
+            self.curr = node.next.load(Acquire);
+            if let Some(item) = node.item.get(Acquire) {
+                break Some(item);
+            }
+        }
+    }
+}
+
+impl<'queue, T> fmt::Debug for Iter<'queue, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Iter {} curr: {:?}, pause: {:?} {}",
+            '{', self.curr, self.pause, '}'
+        )
+    }
+}
+
+make_shared_incin! {
+    { "[`Queue`]" }
+    pub SharedIncin<T> of Recycled<Node<T>>
+}
+
+impl<T> fmt::Debug for SharedIncin<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "SharedIncin {} inner: {:?} {}", '{', self.inner, '}')
+    }
+}
+
+#[repr(align(/* at least */ 2))]
+struct Node<T> {
+    item: Removable<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(item: Removable<T>) -> Self {
+        Self { item, next: AtomicPtr::new(null_mut()) }
+    }
+}
+
+// Nodes linked locally before being appended to a queue. Frees them in case of
+// panic.
+struct Chain<T> {
+    first: *mut Node<T>,
+    last: *mut Node<T>,
+    len: usize,
+}
+
+impl<T> Drop for Chain<T> {
+    fn drop(&mut self) {
+        let mut node_ptr = self.first;
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            // This is safe because the nodes were allocated via `OwnedAlloc`
+            // and they are not shared.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            node_ptr = *node.next.get_mut();
+        }
+    }
+}
+
+// Testing the safety of `unsafe` in this module is done with random operations
+// via fuzzing
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        sync::{atomic::AtomicUsize, Arc},
+        thread,
+    };
+
+    #[test]
+    fn on_empty_first_pop_is_none() {
+        let queue = Queue::<usize>::new();
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn on_empty_last_pop_is_none() {
+        let queue = Queue::new();
+        queue.push(3);
+        queue.push(1234);
+        queue.pop();
+        queue.pop();
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn order() {
+        let queue = Queue::new();
+        queue.push(3);
+        queue.push(5);
+        queue.push(6);
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), Some(6));
+    }
+
+    #[test]
+    fn queue_iter() {
+        let mut queue = Queue::new();
+        queue.push(3);
+        queue.push(5);
+        queue.push(6);
+        assert_eq!(queue.next(), Some(3));
+        assert_eq!(queue.next(), Some(5));
+        assert_eq!(queue.next(), Some(6));
+        assert_eq!(queue.next(), None);
+    }
+
+    #[test]
+    fn iterator_integration() {
+        let queue = (0 .. 5).collect::<Queue<_>>();
+        (&queue).extend(5 .. 8);
+        let mut popped = Vec::new();
+        for item in &queue {
+            popped.push(item);
+        }
+        assert_eq!(popped, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(queue.is_empty());
+
+        let mut queue = queue;
+        Extend::extend(&mut queue, vec![8, 9]);
+        assert_eq!(queue.into_iter().collect::<Vec<_>>(), [8, 9]);
+    }
+
+    #[test]
+    fn push_iter_order() {
+        let queue = Queue::new();
+        queue.push_iter(Vec::new());
+        assert!(queue.pop().is_none());
+        queue.push(0);
+        queue.push_iter(1 .. 5);
+        queue.push(5);
+        queue.push_iter(6 .. 7);
+        assert_eq!(queue.pop_iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn push_iter_no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 200;
+        const BATCH: usize = 10;
+
+        let queue = Arc::new(Queue::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let queue = queue.clone();
+            handles.push(thread::spawn(move || {
+                for j in 0 .. NITER {
+                    let start = (i * NITER + j) * BATCH;
+                    queue.push_iter(start .. start + BATCH);
+                    queue.pop();
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.join().expect("thread failed");
+        }
+
+        let mut last = vec![None; NTHREAD];
+        let mut count = 0;
+        while let Some(item) = queue.pop() {
+            // Values of a single thread must come in order.
+            let thread = item / BATCH / NITER;
+            assert!(last[thread] < Some(item));
+            last[thread] = Some(item);
+            count += 1;
+        }
+        assert_eq!(count, NTHREAD * NITER * (BATCH - 1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        use serde_json;
+
+        let queue = Queue::new();
+        queue.extend(vec![1, 2, 3, 4]);
+        queue.pop();
+        let json = serde_json::to_string(&queue).unwrap();
+        assert_eq!(json, "[2,3,4]");
+        assert_eq!(queue.len(), 3);
+
+        let queue = serde_json::from_str::<Queue<i32>>(&json).unwrap();
+        assert_eq!(queue.pop_iter().collect::<Vec<_>>(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn owned_iteration() {
+        let queue = (0 .. 4).collect::<Queue<_>>();
+        queue.pop();
+        assert_eq!(queue.into_iter().collect::<Vec<_>>(), [1, 2, 3]);
+
+        let item = Arc::new(());
+        let queue = (0 .. 4).map(|_| item.clone()).collect::<Queue<_>>();
+        let mut queue = queue.into_iter();
+        queue.next();
+        drop(queue);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn iter_does_not_take() {
+        let queue = Queue::new();
+        assert_eq!(queue.iter().next(), None);
+        queue.extend(vec![1, 2, 3, 4]);
+        queue.pop();
+        assert_eq!(queue.iter().collect::<Vec<_>>(), [2, 3, 4]);
+
+        let mut iter = queue.iter();
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        queue.push(5);
+        assert_eq!(iter.collect::<Vec<_>>(), [4, 5]);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn pop_wait_wakes_up() {
+        const NTHREAD: usize = 4;
+        const NITER: usize = 500;
+
+        let queue = Arc::new(Queue::new());
+        let mut consumers = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let queue = queue.clone();
+            consumers.push(thread::spawn(move || {
+                (0 .. NITER).map(|_| queue.pop_wait()).sum::<usize>()
+            }));
+        }
+
+        // Giving the consumers time to block.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. NTHREAD * NITER {
+            if i % 100 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            queue.push(i);
+        }
+
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.join().expect("thread failed");
+        }
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn pop_timeout_gives_up() {
+        let queue = Arc::new(Queue::new());
+        let start = Instant::now();
+        assert!(queue.pop_timeout(Duration::from_millis(20)).is_none());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        queue.push(1);
+        assert_eq!(queue.pop_timeout(Duration::from_millis(20)), Some(1));
+
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                queue.push(2);
+            })
+        };
+        assert_eq!(queue.pop_timeout(Duration::from_secs(10)), Some(2));
+        producer.join().expect("thread failed");
+    }
+
+    #[test]
+    fn idle_pop_timeout_leaves_no_waiters() {
+        let queue = Queue::<usize>::new();
+        for _ in 0 .. 2000 {
+            assert!(queue.pop_timeout(Duration::from_micros(1)).is_none());
+        }
+        assert_eq!(queue.waiters.registered(), 0);
+    }
+
+    #[test]
+    fn pop_batch_order() {
+        let queue = Queue::new();
+        assert!(queue.pop_batch(3).is_empty());
+        queue.extend(0 .. 10);
+        assert!(queue.pop_batch(0).is_empty());
+        assert_eq!(queue.pop_batch(3), [0, 1, 2]);
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.drain(), [4, 5, 6, 7, 8, 9]);
+        assert!(queue.drain().is_empty());
+        queue.push(10);
+        assert_eq!(queue.pop_batch(5), [10]);
+        assert_eq!(queue.stats().nodes, 1);
+    }
+
+    #[test]
+    fn pop_batch_no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 1000;
+
+        let queue = Arc::new(Queue::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let queue = queue.clone();
+            handles.push(thread::spawn(move || {
+                let mut taken = Vec::new();
+                for j in 0 .. NITER {
+                    queue.push(i * NITER + j);
+                    if j % 7 == 0 {
+                        taken.extend(queue.pop_batch(j % 5 + 1));
+                    }
+                }
+                taken
+            }));
+        }
+
+        let mut all = Vec::new();
+        for handle in handles {
+            all.extend(handle.join().expect("thread failed"));
+        }
+        all.extend(queue.drain());
+        all.sort();
+        assert_eq!(all, (0 .. NTHREAD * NITER).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 20;
+        const NITER: usize = 800;
+        const NMOD: usize = 55;
+
+        let queue = Arc::new(Queue::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+        let removed = Arc::new(AtomicUsize::new(0));
+
+        for i in 0 .. NTHREAD {
+            let removed = removed.clone();
+            let queue = queue.clone();
+            handles.push(thread::spawn(move || {
+                for j in 0 .. NITER {
+                    let val = (i * NITER) + j;
+                    queue.push(val);
+                    if (val + 1) % NMOD == 0 {
+                        if let Some(val) = queue.pop() {
+                            removed.fetch_add(1, Relaxed);
+                            assert!(val < NITER * NTHREAD);
+                        }
+                    }
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.join().expect("thread failed");
+        }
+
+        let expected = NITER * NTHREAD - removed.load(Relaxed);
+        let mut res = 0;
+        while let Some(val) = queue.pop() {
+            assert!(val < NITER * NTHREAD);
+            res += 1;
+        }
+
+        assert_eq!(res, expected);
+    }
+}
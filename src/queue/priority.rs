@@ -0,0 +1,315 @@
+use collections::{Collection, Stats};
+use skiplist::{Detached, NodePause, SkipList};
+use std::{fmt, ops::Deref};
+
+/// A lock-free priority queue. Values are popped from the smallest to the
+/// greatest, and equal values are popped in the order they were pushed. It can
+/// be used as multi-producer and multi-consumer channel.
+///
+/// # Design
+/// The values are kept in a lock-free skiplist. Pushing is `O(log n)` on
+/// average. Popping marks the first node not marked yet, and then unlinks it.
+/// Since other threads may be still comparing against a popped value, it is
+/// returned as a [`Popped`] guard instead of being moved out directly.
+pub struct Priority<T> {
+    list: SkipList<T>,
+}
+
+impl<T> Priority<T> {
+    /// Creates a new empty priority queue.
+    pub fn new() -> Self {
+        Self { list: SkipList::new() }
+    }
+}
+
+impl<T> Priority<T>
+where
+    T: Ord,
+{
+    /// Pushes a value into the queue.
+    pub fn push(&self, item: T) {
+        self.list.insert(item);
+    }
+
+    /// Takes the smallest value from the queue, if any.
+    pub fn pop_min(&self) -> Option<Popped<T>> {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.list.incin().pause();
+
+        loop {
+            let node = self.list.first(&pause)?;
+            if self.list.take(node, &pause) {
+                // Safe because we took the node.
+                let inner = unsafe { Detached::new(node, self.list.incin()) };
+                break Some(Popped { inner });
+            }
+        }
+    }
+
+    /// Returns a guarded reference to the smallest value in the queue, if
+    /// any, without taking it. The value may be concurrently popped.
+    pub fn peek_min<'queue>(&'queue self) -> Option<PeekGuard<'queue, T>> {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.list.incin().pause();
+        let item = self.list.first(&pause)?.item() as *const T;
+        // Safe because the guard keeps the pause as long as the reference.
+        Some(PeekGuard { item: unsafe { &*item }, pause })
+    }
+
+    /// Pushes elements from the given iterable. Acts just like
+    /// [`Extend::extend`] but does not require mutability.
+    pub fn extend<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for elem in iterable {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> Default for Priority<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Collection for Priority<T>
+where
+    T: Ord,
+{
+    fn len(&self) -> usize {
+        self.stats().len
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop_min() {}
+    }
+
+    fn stats(&self) -> Stats {
+        self.list.stats()
+    }
+}
+
+impl<T> fmt::Debug for Priority<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Priority {} list: {:?} {}", '{', self.list, '}')
+    }
+}
+
+/// A value popped from a [`Priority`] queue. Other threads might still be
+/// reading the value when it is popped, so it can only be moved out with
+/// [`try_into_inner`](Popped::try_into_inner).
+pub struct Popped<T> {
+    inner: Detached<T>,
+}
+
+impl<T> Popped<T> {
+    /// Tries to move the value out. Succeeds only if either the original
+    /// [`Priority`] queue was dropped or no sensitive reads are being
+    /// performed.
+    pub fn try_into_inner(this: Self) -> Result<T, Self> {
+        Detached::try_into_inner(this.inner).map_err(|inner| Self { inner })
+    }
+}
+
+impl<T> Deref for Popped<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.inner.item()
+    }
+}
+
+impl<T> AsRef<T> for Popped<T> {
+    fn as_ref(&self) -> &T {
+        &**self
+    }
+}
+
+impl<T> fmt::Debug for Popped<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "{:?}", &**self)
+    }
+}
+
+/// A guarded reference to the smallest value of a [`Priority`] queue. This
+/// ensures the value is not freed while it is borrowed.
+pub struct PeekGuard<'queue, T>
+where
+    T: 'queue,
+{
+    item: &'queue T,
+    pause: NodePause<'queue, T>,
+}
+
+impl<'queue, T> Deref for PeekGuard<'queue, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item
+    }
+}
+
+impl<'queue, T> AsRef<T> for PeekGuard<'queue, T> {
+    fn as_ref(&self) -> &T {
+        self.item
+    }
+}
+
+impl<'queue, T> fmt::Debug for PeekGuard<'queue, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "PeekGuard {} item: {:?}, pause: {:?} {}",
+            '{', self.item, self.pause, '}'
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn pops_in_order() {
+        let queue = Priority::new();
+        assert!(queue.pop_min().is_none());
+        assert!(queue.peek_min().is_none());
+        queue.extend(vec![5, 3, 8, 1, 9, 2, 7]);
+        assert_eq!(*queue.peek_min().unwrap(), 1);
+        assert_eq!(queue.len(), 7);
+
+        let mut popped = Vec::new();
+        while let Some(item) = queue.pop_min() {
+            popped.push(*item);
+        }
+        assert_eq!(popped, [1, 2, 3, 5, 7, 8, 9]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn equal_values_are_fifo() {
+        let queue = Priority::new();
+        queue.push((1, 'a'));
+        queue.push((0, 'b'));
+        queue.push((1, 'c'));
+        assert_eq!(*queue.pop_min().unwrap(), (0, 'b'));
+        queue.push((1, 'd'));
+        assert_eq!(*queue.pop_min().unwrap(), (1, 'a'));
+        assert_eq!(*queue.pop_min().unwrap(), (1, 'c'));
+        assert_eq!(*queue.pop_min().unwrap(), (1, 'd'));
+    }
+
+    #[test]
+    fn into_inner() {
+        let queue = Priority::new();
+        queue.push(String::from("a"));
+        let popped = queue.pop_min().unwrap();
+        assert_eq!(Popped::try_into_inner(popped).unwrap(), "a");
+
+        queue.push(String::from("b"));
+        let popped = queue.pop_min().unwrap();
+        drop(queue);
+        assert_eq!(Popped::try_into_inner(popped).unwrap(), "b");
+    }
+
+    #[test]
+    fn drops_everything() {
+        let item = Arc::new(());
+        let queue = Priority::new();
+        for _ in 0 .. 100 {
+            queue.push(item.clone());
+        }
+        let popped = queue.pop_min().unwrap();
+        drop(queue);
+        drop(popped);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+
+        let queue = Arc::new(Priority::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let queue = queue.clone();
+            handles.push(thread::spawn(move || {
+                let mut taken = Vec::new();
+                for j in 0 .. NITER {
+                    queue.push(j * NTHREAD + i);
+                    if j % 2 == 0 {
+                        if let Some(item) = queue.pop_min() {
+                            taken.push(*item);
+                        }
+                    }
+                }
+                taken
+            }));
+        }
+
+        let mut all = Vec::new();
+        for handle in handles {
+            all.extend(handle.join().expect("thread failed"));
+        }
+        let mut rest = Vec::new();
+        while let Some(item) = queue.pop_min() {
+            rest.push(*item);
+        }
+        // What was left is still in order.
+        assert!(rest.windows(2).all(|pair| pair[0] <= pair[1]));
+        all.extend(rest);
+        all.sort();
+        assert_eq!(all, (0 .. NTHREAD * NITER).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concurrent_duplicates() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+        const NVAL: usize = 8;
+
+        let queue = Arc::new(Priority::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let queue = queue.clone();
+            handles.push(thread::spawn(move || {
+                let mut counts = [0; NVAL];
+                for j in 0 .. NITER {
+                    queue.push(j % NVAL);
+                    if j % 3 != 0 {
+                        if let Some(item) = queue.pop_min() {
+                            counts[*item] += 1;
+                        }
+                    }
+                }
+                counts
+            }));
+        }
+
+        let mut counts = [0; NVAL];
+        for handle in handles {
+            let taken = handle.join().expect("thread failed");
+            for (count, taken) in counts.iter_mut().zip(taken.iter()) {
+                *count += taken;
+            }
+        }
+        assert_eq!(queue.stats().nodes, queue.len());
+        while let Some(item) = queue.pop_min() {
+            counts[*item] += 1;
+        }
+        assert_eq!(counts, [NTHREAD * NITER / NVAL; NVAL]);
+        assert_eq!(queue.stats().nodes, 0);
+    }
+}
@@ -0,0 +1,410 @@
+use collections::{Collection, Stats};
+use incin::Incinerator;
+use owned_alloc::OwnedAlloc;
+use ptr::bypass_null;
+use std::{
+    array,
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering::*},
+};
+
+/// How many values a single segment holds.
+pub const SEGMENT_LEN: usize = 32;
+
+const EMPTY: usize = 0;
+const WRITTEN: usize = 1;
+const TAKEN: usize = 2;
+
+/// A lock-free unbounded queue which allocates its values in segments of
+/// [`SEGMENT_LEN`] slots, rather than one node per value like
+/// [`Queue`](super::Queue) does. FIFO semanthics are fully respected. It can
+/// be used as multi-producer and multi-consumer channel.
+///
+/// # Design
+/// Producers and consumers claim slots of the back and the front segments by
+/// incrementing per-segment counters. A consumer which claims a slot not
+/// written yet marks it as taken, and the producer of that slot tries another
+/// one. A new segment is appended when the back one is full, and the front one
+/// is deleted when it is exhausted.
+pub struct Segmented<T> {
+    front: AtomicPtr<Segment<T>>,
+    back: AtomicPtr<Segment<T>>,
+    incin: Incinerator<OwnedAlloc<Segment<T>>>,
+}
+
+impl<T> Segmented<T> {
+    /// Creates a new empty queue.
+    pub fn new() -> Self {
+        let segment = OwnedAlloc::new(Segment::new());
+        let ptr = segment.into_raw().as_ptr();
+        Self {
+            front: AtomicPtr::new(ptr),
+            back: AtomicPtr::new(ptr),
+            incin: Incinerator::new(),
+        }
+    }
+
+    /// Pushes a value into the back of the queue.
+    pub fn push(&self, mut item: T) {
+        // Pausing because of ABA problem and use-after-free.
+        let _pause = self.incin.pause();
+
+        loop {
+            // Safe to by-pass the check because the back is never null.
+            let back = unsafe { bypass_null(self.back.load(Acquire)) };
+            // Safe to derefer because we paused the incinerator and only
+            // delete segments via incinerator.
+            let segment = unsafe { back.as_ref() };
+            let index = segment.enq_index.fetch_add(1, AcqRel);
+
+            if index < SEGMENT_LEN {
+                // Safe because we claimed this slot for writing.
+                match unsafe { segment.slots[index].write(item) } {
+                    Ok(()) => break,
+                    // A consumer gave up on this slot. Let's try another one.
+                    Err(back) => item = back,
+                }
+                continue;
+            }
+
+            // The segment is full.
+            let next = segment.next.load(Acquire);
+            if let Some(next) = NonNull::new(next) {
+                // Helping to move the back forward.
+                let _ = self.back.compare_exchange(
+                    back.as_ptr(),
+                    next.as_ptr(),
+                    AcqRel,
+                    Relaxed,
+                );
+                continue;
+            }
+
+            let mut new_segment = OwnedAlloc::new(Segment::new());
+            *new_segment.enq_index.get_mut() = 1;
+            // Safe because the segment is not shared yet.
+            unsafe { new_segment.slots[0].init(item) };
+            let new_ptr = new_segment.into_raw();
+
+            let res = segment.next.compare_exchange(
+                null_mut(),
+                new_ptr.as_ptr(),
+                AcqRel,
+                Relaxed,
+            );
+
+            // Safe because if we failed, the segment was not shared.
+            let mut new_segment = match res {
+                Ok(_) => {
+                    let _ = self.back.compare_exchange(
+                        back.as_ptr(),
+                        new_ptr.as_ptr(),
+                        AcqRel,
+                        Relaxed,
+                    );
+                    break;
+                },
+
+                Err(_) => unsafe { OwnedAlloc::from_raw(new_ptr) },
+            };
+
+            // Somebody else appended a segment. Let's take our value back.
+            //
+            // Safe because the slot was initialized by us and the segment was
+            // never shared.
+            item = unsafe { new_segment.slots[0].take_init() };
+        }
+    }
+
+    /// Takes a value from the front of the queue, if it is avaible.
+    pub fn pop(&self) -> Option<T> {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.incin.pause();
+
+        loop {
+            // Safe to by-pass the check because the front is never null.
+            let front = unsafe { bypass_null(self.front.load(Acquire)) };
+            // Safe to derefer because we paused the incinerator and only
+            // delete segments via incinerator.
+            let segment = unsafe { front.as_ref() };
+
+            let deq_index = segment.deq_index.load(Acquire);
+            if deq_index >= segment.enq_index.load(Acquire).min(SEGMENT_LEN)
+                && segment.next.load(Acquire).is_null()
+            {
+                break None;
+            }
+
+            let index = segment.deq_index.fetch_add(1, AcqRel);
+
+            if index < SEGMENT_LEN {
+                match segment.slots[index].take() {
+                    Some(item) => break Some(item),
+                    // Not written yet. The producer will try another slot.
+                    None => continue,
+                }
+            }
+
+            // The segment is exhausted.
+            let next = match NonNull::new(segment.next.load(Acquire)) {
+                Some(next) => next,
+                None => break None,
+            };
+
+            // The back must never point to a deleted segment.
+            let _ = self.back.compare_exchange(
+                front.as_ptr(),
+                next.as_ptr(),
+                AcqRel,
+                Relaxed,
+            );
+
+            let res = self.front.compare_exchange(
+                front.as_ptr(),
+                next.as_ptr(),
+                AcqRel,
+                Relaxed,
+            );
+
+            if res.is_ok() {
+                // Only deleting segments via incinerator due to ABA problem
+                // and use-after-frees.
+                pause.add_to_incin(unsafe { OwnedAlloc::from_raw(front) });
+            }
+        }
+    }
+}
+
+impl<T> Default for Segmented<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Segmented<T> {
+    fn drop(&mut self) {
+        let mut ptr = *self.front.get_mut();
+        while let Some(nnptr) = NonNull::new(ptr) {
+            // This is safe because we only store pointers allocated via
+            // `OwnedAlloc`. Also, we have exclusive access to this pointer.
+            let mut segment = unsafe { OwnedAlloc::from_raw(nnptr) };
+            ptr = *segment.next.get_mut();
+        }
+    }
+}
+
+impl<T> Collection for Segmented<T> {
+    fn len(&self) -> usize {
+        self.stats().len
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop() {}
+    }
+
+    fn stats(&self) -> Stats {
+        // Pausing because we will walk through shared segments.
+        let _pause = self.incin.pause();
+        let mut stats = Stats::default();
+        let mut ptr = self.front.load(Acquire);
+
+        // Safe to derefer the segments because we paused the incinerator and
+        // we only delete segments via incinerator.
+        while let Some(nnptr) = NonNull::new(ptr) {
+            let segment = unsafe { nnptr.as_ref() };
+            stats.nodes += 1;
+            stats.len += segment
+                .slots
+                .iter()
+                .filter(|slot| slot.state.load(Acquire) == WRITTEN)
+                .count();
+            ptr = segment.next.load(Acquire);
+        }
+
+        stats
+    }
+}
+
+impl<T> fmt::Debug for Segmented<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Segmented {} front: {:?}, back: {:?}, incin: {:?} {}",
+            '{', self.front, self.back, self.incin, '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for Segmented<T> where T: Send {}
+unsafe impl<T> Sync for Segmented<T> where T: Send {}
+
+struct Segment<T> {
+    enq_index: AtomicUsize,
+    deq_index: AtomicUsize,
+    slots: [Slot<T>; SEGMENT_LEN],
+    next: AtomicPtr<Segment<T>>,
+}
+
+impl<T> Segment<T> {
+    fn new() -> Self {
+        Self {
+            enq_index: AtomicUsize::new(0),
+            deq_index: AtomicUsize::new(0),
+            slots: array::from_fn(|_| Slot {
+                state: AtomicUsize::new(EMPTY),
+                item: UnsafeCell::new(MaybeUninit::uninit()),
+            }),
+            next: AtomicPtr::new(null_mut()),
+        }
+    }
+}
+
+struct Slot<T> {
+    state: AtomicUsize,
+    item: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    // Writes the value and publishes it, unless a consumer gave up on the
+    // slot, in which case the value is given back. This function is unsafe
+    // because only the producer that claimed the slot may call it.
+    unsafe fn write(&self, item: T) -> Result<(), T> {
+        (*self.item.get()).as_mut_ptr().write(item);
+        match self.state.compare_exchange(EMPTY, WRITTEN, AcqRel, Acquire) {
+            Ok(_) => Ok(()),
+            Err(_) => Err((*self.item.get()).as_ptr().read()),
+        }
+    }
+
+    // Marks the slot as taken, returning the value if it was written. Only the
+    // consumer that claimed the slot may call it.
+    fn take(&self) -> Option<T> {
+        if self.state.swap(TAKEN, AcqRel) == WRITTEN {
+            // Safe because the producer wrote the value before publishing it,
+            // and nobody else will read it.
+            Some(unsafe { (*self.item.get()).as_ptr().read() })
+        } else {
+            None
+        }
+    }
+
+    // Unsafe because the slot must not be shared.
+    unsafe fn init(&mut self, item: T) {
+        self.item.get_mut().as_mut_ptr().write(item);
+        *self.state.get_mut() = WRITTEN;
+    }
+
+    // Unsafe because the slot must not be shared and it must be initialized.
+    unsafe fn take_init(&mut self) -> T {
+        *self.state.get_mut() = TAKEN;
+        self.item.get_mut().as_ptr().read()
+    }
+}
+
+impl<T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == WRITTEN {
+            // Safe because written values are only read after being marked as
+            // taken.
+            unsafe { self.item.get_mut().as_mut_ptr().drop_in_place() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn order() {
+        let queue = Segmented::new();
+        assert!(queue.pop().is_none());
+        for i in 0 .. SEGMENT_LEN * 3 + 5 {
+            queue.push(i);
+        }
+        assert_eq!(queue.len(), SEGMENT_LEN * 3 + 5);
+        for i in 0 .. SEGMENT_LEN * 3 + 5 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert!(queue.pop().is_none());
+        assert_eq!(queue.stats().nodes, 1);
+    }
+
+    #[test]
+    fn allocates_per_segment() {
+        let queue = Segmented::new();
+        for i in 0 .. SEGMENT_LEN * 2 {
+            queue.push(i);
+        }
+        assert_eq!(queue.stats(), Stats { len: SEGMENT_LEN * 2, nodes: 2 });
+        queue.push(0);
+        assert_eq!(queue.stats().nodes, 3);
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let item = Arc::new(());
+        let queue = Segmented::new();
+        for _ in 0 .. SEGMENT_LEN + 3 {
+            queue.push(item.clone());
+        }
+        queue.pop();
+        drop(queue);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+
+        let queue = Arc::new(Segmented::new());
+        let mut producers = Vec::with_capacity(NTHREAD);
+        let mut consumers = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let queue = queue.clone();
+            producers.push(thread::spawn(move || {
+                for j in 0 .. NITER {
+                    queue.push(i * NITER + j);
+                }
+            }));
+        }
+
+        for _ in 0 .. NTHREAD {
+            let queue = queue.clone();
+            consumers.push(thread::spawn(move || {
+                let mut taken = Vec::with_capacity(NITER);
+                while taken.len() < NITER {
+                    match queue.pop() {
+                        Some(item) => taken.push(item),
+                        None => thread::yield_now(),
+                    }
+                }
+                taken
+            }));
+        }
+
+        for producer in producers {
+            producer.join().expect("thread failed");
+        }
+        let mut all = Vec::with_capacity(NTHREAD * NITER);
+        for consumer in consumers {
+            let taken = consumer.join().expect("thread failed");
+            // Values of the same producer are taken in order.
+            for pair in taken.windows(2) {
+                if pair[0] / NITER == pair[1] / NITER {
+                    assert!(pair[0] < pair[1]);
+                }
+            }
+            all.extend(taken);
+        }
+        all.sort();
+        assert_eq!(all, (0 .. NTHREAD * NITER).collect::<Vec<_>>());
+        assert!(queue.pop().is_none());
+    }
+}
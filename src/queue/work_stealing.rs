@@ -0,0 +1,354 @@
+use incin::Incinerator;
+use owned_alloc::OwnedAlloc;
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        atomic::{fence, AtomicIsize, AtomicPtr, Ordering::*},
+        Arc,
+    },
+};
+
+const MIN_CAPACITY: usize = 16;
+
+/// A lock-free work-stealing deque (Chase-Lev), suitable for task
+/// schedulers. The owner of this handle pushes and pops values at one end of
+/// the deque, in LIFO order, while any number of [`Stealer`]s take values from
+/// the other end, in FIFO order. The buffer grows as needed.
+pub struct WorkStealing<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> WorkStealing<T> {
+    /// Creates a new empty deque.
+    pub fn new() -> Self {
+        let buffer = OwnedAlloc::new(Buffer::new(MIN_CAPACITY));
+        Self {
+            inner: Arc::new(Inner {
+                top: AtomicIsize::new(0),
+                bottom: AtomicIsize::new(0),
+                buffer: AtomicPtr::new(buffer.into_raw().as_ptr()),
+                incin: Incinerator::new(),
+            }),
+        }
+    }
+
+    /// Creates a new handle able to steal values from this deque.
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer { inner: self.inner.clone() }
+    }
+
+    /// Pushes a value into the owner's end of the deque.
+    pub fn push(&mut self, item: T) {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Relaxed);
+        let top = inner.top.load(Acquire);
+        let mut buffer = inner.buffer.load(Relaxed);
+
+        // Safe because only the owner replaces the buffer, and the buffer is
+        // never null.
+        if bottom - top >= unsafe { (*buffer).capacity() } as isize {
+            // Safe because the indices are the current ones and we are the
+            // owner.
+            buffer = unsafe { inner.grow(buffer, top, bottom) };
+        }
+
+        // Safe because this position is not visible to stealers until we
+        // update the bottom.
+        unsafe { (*buffer).write(bottom, item) };
+        inner.bottom.store(bottom + 1, Release);
+    }
+
+    /// Pops the last pushed value from the owner's end of the deque, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Relaxed) - 1;
+        let buffer = inner.buffer.load(Relaxed);
+        // Reserving the position before checking the top, so stealers see the
+        // reservation or we see their steal.
+        inner.bottom.store(bottom, Relaxed);
+        fence(SeqCst);
+        let top = inner.top.load(Relaxed);
+
+        if top > bottom {
+            // Empty. Let's undo the reservation.
+            inner.bottom.store(bottom + 1, Relaxed);
+            return None;
+        }
+
+        // Safe because the position is between the top and the bottom.
+        let item = unsafe { (*buffer).read(bottom) };
+
+        if top < bottom {
+            // Safe because stealers cannot reach this position anymore.
+            return Some(unsafe { item.assume_init() });
+        }
+
+        // This is the last value: we race against the stealers for it.
+        let res = inner.top.compare_exchange(top, top + 1, SeqCst, Relaxed);
+        inner.bottom.store(bottom + 1, Relaxed);
+        // Safe because if we won the race, nobody else took the value. If we
+        // lost it, the copy we read is just forgotten.
+        res.ok().map(|_| unsafe { item.assume_init() })
+    }
+}
+
+impl<T> Default for WorkStealing<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for WorkStealing<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "WorkStealing {} inner: {:?} {}", '{', self.inner, '}')
+    }
+}
+
+unsafe impl<T> Send for WorkStealing<T> where T: Send {}
+unsafe impl<T> Sync for WorkStealing<T> where T: Send {}
+
+/// A handle able to steal values from the end of a [`WorkStealing`] deque
+/// opposite to the owner's. Stealers can be freely cloned and shared.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Stealer<T> {
+    /// Takes the first pushed value from the deque, if any.
+    pub fn steal(&self) -> Option<T> {
+        let inner = &*self.inner;
+        // Pausing because the owner may replace the buffer.
+        let _pause = inner.incin.pause();
+
+        loop {
+            let top = inner.top.load(Acquire);
+            // Pairs with the fence in `pop`.
+            fence(SeqCst);
+            let bottom = inner.bottom.load(Acquire);
+
+            if top >= bottom {
+                break None;
+            }
+
+            let buffer = inner.buffer.load(Acquire);
+            // Safe because we paused the incinerator and buffers are only
+            // deleted via incinerator. The value is only used if we claim its
+            // position.
+            let item = unsafe { (*buffer).read(top) };
+
+            let res = inner.top.compare_exchange(top, top + 1, SeqCst, Relaxed);
+            if res.is_ok() {
+                // Safe because we claimed the position.
+                break Some(unsafe { item.assume_init() });
+            }
+        }
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T> fmt::Debug for Stealer<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Stealer {} inner: {:?} {}", '{', self.inner, '}')
+    }
+}
+
+unsafe impl<T> Send for Stealer<T> where T: Send {}
+unsafe impl<T> Sync for Stealer<T> where T: Send {}
+
+struct Inner<T> {
+    top: AtomicIsize,
+    bottom: AtomicIsize,
+    buffer: AtomicPtr<Buffer<T>>,
+    incin: Incinerator<OwnedAlloc<Buffer<T>>>,
+}
+
+impl<T> Inner<T> {
+    // Replaces the buffer with one twice bigger, returning the new one. This
+    // function is unsafe because it must only be called by the owner, and the
+    // passed indices must be the current ones.
+    unsafe fn grow(
+        &self,
+        old: *mut Buffer<T>,
+        top: isize,
+        bottom: isize,
+    ) -> *mut Buffer<T> {
+        let new = Buffer::new((*old).capacity() * 2);
+        for i in top .. bottom {
+            // The old buffer never drops the values, so copying them is fine.
+            new.write(i, (*old).read(i).assume_init());
+        }
+
+        let new = OwnedAlloc::new(new).into_raw().as_ptr();
+        self.buffer.store(new, Release);
+        // Stealers might still be reading the old buffer.
+        self.incin.add(OwnedAlloc::from_raw(ptr::NonNull::new_unchecked(old)));
+        new
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = *self.top.get_mut();
+        let bottom = *self.bottom.get_mut();
+        // Safe because the buffer is never null and allocated via `OwnedAlloc`.
+        // Also, we have exclusive access to it.
+        let buffer = unsafe {
+            OwnedAlloc::from_raw(ptr::NonNull::new_unchecked(
+                *self.buffer.get_mut(),
+            ))
+        };
+        for i in top .. bottom {
+            // Safe because these positions were not taken.
+            drop(unsafe { buffer.read(i).assume_init() });
+        }
+    }
+}
+
+impl<T> fmt::Debug for Inner<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Inner {} top: {:?}, bottom: {:?}, buffer: {:?} {}",
+            '{', self.top, self.bottom, self.buffer, '}'
+        )
+    }
+}
+
+// A buffer never drops the values it holds.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> Self {
+        let slots = (0 .. capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect();
+        Self { slots }
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        // The capacity is always a power of two.
+        self.slots[index as usize & (self.capacity() - 1)].get()
+    }
+
+    // Unsafe because the caller must have exclusive access to the position.
+    unsafe fn write(&self, index: isize, item: T) {
+        (*self.slot(index)).as_mut_ptr().write(item)
+    }
+
+    // Unsafe because the value may be uninitialized or a duplicate, which the
+    // caller must check.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        // A stealer may read a position the owner is writing, and it will
+        // discard the value, so this is a volatile read.
+        ptr::read_volatile(self.slot(index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    #[test]
+    fn owner_is_lifo_stealer_is_fifo() {
+        let mut deque = WorkStealing::new();
+        let stealer = deque.stealer();
+        assert!(deque.pop().is_none());
+        assert!(stealer.steal().is_none());
+        deque.push(1);
+        deque.push(2);
+        deque.push(3);
+        assert_eq!(stealer.steal(), Some(1));
+        assert_eq!(deque.pop(), Some(3));
+        assert_eq!(deque.pop(), Some(2));
+        assert!(deque.pop().is_none());
+        assert!(stealer.steal().is_none());
+    }
+
+    #[test]
+    fn grows() {
+        let mut deque = WorkStealing::new();
+        let stealer = deque.stealer();
+        for i in 0 .. MIN_CAPACITY * 5 {
+            deque.push(i);
+        }
+        assert_eq!(stealer.steal(), Some(0));
+        for i in (1 .. MIN_CAPACITY * 5).rev() {
+            assert_eq!(deque.pop(), Some(i));
+        }
+        assert!(stealer.steal().is_none());
+    }
+
+    #[test]
+    fn drops_remaining() {
+        let item = Arc::new(());
+        let mut deque = WorkStealing::new();
+        let stealer = deque.stealer();
+        for _ in 0 .. MIN_CAPACITY * 2 {
+            deque.push(item.clone());
+        }
+        drop(deque);
+        assert!(stealer.steal().is_some());
+        drop(stealer);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 4;
+        const NITER: usize = 20000;
+
+        let mut deque = WorkStealing::new();
+        let sum = Arc::new(AtomicUsize::new(0));
+        let taken = Arc::new(AtomicUsize::new(0));
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let stealer = deque.stealer();
+            let sum = sum.clone();
+            let taken = taken.clone();
+            handles.push(thread::spawn(move || {
+                while taken.load(Relaxed) < NITER {
+                    if let Some(item) = stealer.steal() {
+                        sum.fetch_add(item, Relaxed);
+                        taken.fetch_add(1, Relaxed);
+                    }
+                }
+            }));
+        }
+
+        for i in 0 .. NITER {
+            deque.push(i);
+            if i % 3 == 0 {
+                if let Some(item) = deque.pop() {
+                    sum.fetch_add(item, Relaxed);
+                    taken.fetch_add(1, Relaxed);
+                }
+            }
+        }
+        while let Some(item) = deque.pop() {
+            sum.fetch_add(item, Relaxed);
+            taken.fetch_add(1, Relaxed);
+        }
+
+        for handle in handles {
+            handle.join().expect("thread failed");
+        }
+        assert_eq!(taken.load(Relaxed), NITER);
+        assert_eq!(sum.load(Relaxed), NITER * (NITER - 1) / 2);
+    }
+}
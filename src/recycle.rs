@@ -0,0 +1,137 @@
+use owned_alloc::{OwnedAlloc, UninitAlloc};
+use std::{
+    fmt,
+    mem::ManuallyDrop,
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{AtomicPtr, Ordering::*},
+        Arc,
+    },
+};
+
+/// The maximum number of allocations kept by a [`NodeCache`].
+pub const CACHE_LEN: usize = 16;
+
+/// A small lock-free cache of node allocations. Nodes reclaimed by the
+/// incinerator are put here instead of being deallocated, so steady-state
+/// workloads do not go to the allocator on every operation. Allocations are
+/// taken from the cache with a swap, so there is no ABA problem.
+pub struct NodeCache<T> {
+    slots: Box<[AtomicPtr<T>]>,
+}
+
+impl<T> NodeCache<T> {
+    /// Creates a new empty cache.
+    pub fn new() -> Self {
+        let slots = (0 .. CACHE_LEN).map(|_| AtomicPtr::new(null_mut()));
+        Self { slots: slots.collect() }
+    }
+
+    /// Allocates the given value, reusing a cached allocation if any.
+    pub fn alloc(&self, val: T) -> OwnedAlloc<T> {
+        for slot in self.slots.iter() {
+            if slot.load(Relaxed).is_null() {
+                continue;
+            }
+            if let Some(nnptr) = NonNull::new(slot.swap(null_mut(), Acquire)) {
+                // Safe because cached pointers were allocated via `OwnedAlloc`
+                // and the swap gave us exclusive access to this one.
+                return unsafe { UninitAlloc::from_raw(nnptr) }.init(val);
+            }
+        }
+
+        OwnedAlloc::new(val)
+    }
+
+    /// Drops the value of the given allocation, and keeps the allocation in
+    /// the cache, if there is room for it.
+    pub fn recycle(&self, alloc: OwnedAlloc<T>) {
+        let ptr = alloc.drop_in_place().into_raw().as_ptr();
+
+        for slot in self.slots.iter() {
+            let res = slot.compare_exchange(null_mut(), ptr, Release, Relaxed);
+            if res.is_ok() {
+                return;
+            }
+        }
+
+        // Safe because the pointer came from an `UninitAlloc` and we did not
+        // store it anywhere.
+        drop(unsafe { UninitAlloc::from_raw(NonNull::new_unchecked(ptr)) });
+    }
+}
+
+impl<T> Drop for NodeCache<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter_mut() {
+            if let Some(nnptr) = NonNull::new(*slot.get_mut()) {
+                // Safe because cached pointers were allocated via `OwnedAlloc`
+                // and we have exclusive access to them.
+                drop(unsafe { UninitAlloc::from_raw(nnptr) });
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for NodeCache<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "NodeCache {} slots: {:?} {}", '{', self.slots, '}')
+    }
+}
+
+/// A garbage node which goes back to its [`NodeCache`] when the incinerator
+/// drops it, i.e. when nobody can be reading it anymore.
+pub struct Recycled<T> {
+    alloc: ManuallyDrop<OwnedAlloc<T>>,
+    cache: Arc<NodeCache<T>>,
+}
+
+impl<T> Recycled<T> {
+    /// Wraps a node removed from a structure which uses the given cache.
+    pub fn new(alloc: OwnedAlloc<T>, cache: &Arc<NodeCache<T>>) -> Self {
+        Self { alloc: ManuallyDrop::new(alloc), cache: cache.clone() }
+    }
+}
+
+impl<T> Drop for Recycled<T> {
+    fn drop(&mut self) {
+        // Safe because the allocation is never used again.
+        let alloc = unsafe { (&*self.alloc as *const OwnedAlloc<T>).read() };
+        self.cache.recycle(alloc);
+    }
+}
+
+impl<T> fmt::Debug for Recycled<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Recycled({:?})", self.alloc.raw())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reuses_allocations() {
+        let cache = NodeCache::new();
+        let first = cache.alloc(1);
+        let ptr = first.raw();
+        cache.recycle(first);
+        let second = cache.alloc(2);
+        assert_eq!(second.raw(), ptr);
+        assert_eq!(*second, 2);
+    }
+
+    #[test]
+    fn drops_values() {
+        let item = Arc::new(());
+        let cache = Arc::new(NodeCache::new());
+        let allocs = (0 .. CACHE_LEN * 2)
+            .map(|_| cache.alloc(item.clone()))
+            .collect::<Vec<_>>();
+        for alloc in allocs {
+            drop(Recycled::new(alloc, &cache));
+        }
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+}
@@ -78,6 +78,23 @@ impl<T> Removable<T> {
         self.present.load(ordering)
     }
 
+    /// Copies the value, if present. Note that the value might be taken by
+    /// some other thread right after it is copied. In terms of memory
+    /// ordering, `Acquire` should be enough.
+    pub fn get(&self, ordering: Ordering) -> Option<T>
+    where
+        T: Copy,
+    {
+        if self.present.load(ordering) {
+            // Safe because if present was true, the memory was initialized,
+            // and taking the value never writes to it. `Copy` values do not own
+            // resources that could be released by whoever took the value.
+            Some(*self.item)
+        } else {
+            None
+        }
+    }
+
     /// Tries to take the value. If no value was present in first place, `None`
     /// is returned. In terms of memory ordering, `AcqRel` should be enough.
     pub fn take(&self, ordering: Ordering) -> Option<T> {
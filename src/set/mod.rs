@@ -1,3 +1,9 @@
+/// An ordered lock-free set, built on a skiplist.
+pub mod ordered;
+
+pub use self::ordered::Ordered;
+
+use collections::{Collection, Stats};
 pub use map::RandomState;
 use map::{
     Insertion as MapInsertion,
@@ -20,6 +26,7 @@ use std::{
 
 /// A lock-free set. This is currently implemented on top of
 /// [`Map`](::map::Map). To check more details about it, please see `Map` docs.
+/// For sorted iteration and range queries, see [`Ordered`].
 pub struct Set<T, H = RandomState> {
     inner: Map<T, (), H>,
 }
@@ -86,21 +93,23 @@ where
     /// way for the borrowed type and the stored type.
     pub fn contains<U>(&self, elem: &U) -> bool
     where
-        U: Hash + Ord,
+        U: ?Sized + Hash + Ord,
         T: Borrow<U>,
     {
         self.inner.get(elem).is_some()
     }
 
     /// Returns a guarded reference to the given element in the [`Set`]. This
-    /// may be useful for types with additional metadata. The method accepts
+    /// may be useful for types with additional metadata, or for interning,
+    /// where the `Set` owns the canonical copy of e.g. a string and lookups
+    /// are done with a borrowed form (such as `&str`). The method accepts
     /// a type resulted from borrowing the stored element. This method will
     /// only work correctly if [`Hash`] and [`Ord`] are implemented in the same
     /// way for the borrowed type and the stored type. If the element is not
     /// found, [`None`] is obviously returned.
     pub fn get<'set, U>(&'set self, elem: &U) -> Option<ReadGuard<'set, T>>
     where
-        U: Hash + Ord,
+        U: ?Sized + Hash + Ord,
         T: Borrow<U>,
     {
         self.inner.get(elem).map(ReadGuard::new)
@@ -126,6 +135,17 @@ where
         }
     }
 
+    /// Inserts the element into the [`Set`], replacing an equal element if
+    /// already present. The displaced element is returned, so resources tied
+    /// to it can be released. This mirrors `HashSet::replace` of the standard
+    /// library.
+    pub fn replace(&self, elem: T) -> Option<Removed<T>>
+    where
+        T: Hash + Ord,
+    {
+        self.inner.insert(elem, ()).map(Removed::new)
+    }
+
     /// Inserts _interactively_ the element into the [`Set`]. A passed closure
     /// tests if the insertion should proceed. The first argument of the
     /// closure is the element passed to `insert_with` and the second is the
@@ -213,7 +233,7 @@ where
     /// for the borrowed type and the stored type.
     pub fn remove<U>(&self, elem: &U) -> Option<Removed<T>>
     where
-        U: Hash + Ord,
+        U: ?Sized + Hash + Ord,
         T: Borrow<U>,
     {
         self.inner.remove(elem).map(Removed::new)
@@ -232,7 +252,7 @@ where
         mut interactive: F,
     ) -> Option<Removed<T>>
     where
-        U: Hash + Ord,
+        U: ?Sized + Hash + Ord,
         T: Borrow<U>,
         F: FnMut(&T) -> bool,
     {
@@ -241,6 +261,83 @@ where
             .map(Removed::new)
     }
 
+    /// Creates a lazy iterator over the elements in this [`Set`] or in the
+    /// other. Elements of both sets are yielded only once, from this `Set`.
+    pub fn union<'set>(&'set self, other: &'set Self) -> Union<'set, T, H>
+    where
+        T: Hash + Ord,
+    {
+        Union { first: self.iter(), second: other.difference(self) }
+    }
+
+    /// Creates a lazy iterator over the elements in this [`Set`] which are
+    /// also in the other.
+    pub fn intersection<'set>(
+        &'set self,
+        other: &'set Self,
+    ) -> Intersection<'set, T, H>
+    where
+        T: Hash + Ord,
+    {
+        Intersection { iter: self.iter(), other }
+    }
+
+    /// Creates a lazy iterator over the elements in this [`Set`] which are not
+    /// in the other.
+    pub fn difference<'set>(
+        &'set self,
+        other: &'set Self,
+    ) -> Difference<'set, T, H>
+    where
+        T: Hash + Ord,
+    {
+        Difference { iter: self.iter(), other }
+    }
+
+    /// Creates a lazy iterator over the elements in either this [`Set`] or the
+    /// other, but not in both.
+    pub fn symmetric_difference<'set>(
+        &'set self,
+        other: &'set Self,
+    ) -> SymmetricDifference<'set, T, H>
+    where
+        T: Hash + Ord,
+    {
+        SymmetricDifference {
+            first: self.difference(other),
+            second: other.difference(self),
+        }
+    }
+
+    /// Tests if every element of this [`Set`] is in the other. If the sets are
+    /// modified concurrently, the result might not reflect any single moment.
+    pub fn is_subset(&self, other: &Self) -> bool
+    where
+        T: Hash + Ord,
+    {
+        self.iter().all(|elem| other.contains::<T>(&elem))
+    }
+
+    /// Tests if every element of the other [`Set`] is in this one. If the sets
+    /// are modified concurrently, the result might not reflect any single
+    /// moment.
+    pub fn is_superset(&self, other: &Self) -> bool
+    where
+        T: Hash + Ord,
+    {
+        other.is_subset(self)
+    }
+
+    /// Tests if this [`Set`] and the other have no element in common. If the
+    /// sets are modified concurrently, the result might not reflect any single
+    /// moment.
+    pub fn is_disjoint(&self, other: &Self) -> bool
+    where
+        T: Hash + Ord,
+    {
+        self.intersection(other).next().is_none()
+    }
+
     /// Acts just like [`Extend::extend`] but does not require mutability.
     #[allow(unused_must_use)]
     pub fn extend<I>(&self, iterable: I)
@@ -263,6 +360,24 @@ where
     }
 }
 
+impl<T, H> Collection for Set<T, H>
+where
+    H: BuildHasher,
+    T: Hash + Ord,
+{
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn clear(&self) {
+        Collection::clear(&self.inner)
+    }
+
+    fn stats(&self) -> Stats {
+        self.inner.stats()
+    }
+}
+
 impl<T, H> fmt::Debug for Set<T, H>
 where
     H: fmt::Debug,
@@ -320,6 +435,19 @@ where
     }
 }
 
+impl<'elem, T, H> Extend<&'elem T> for Set<T, H>
+where
+    H: BuildHasher,
+    T: 'elem + Hash + Ord + Clone,
+{
+    fn extend<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = &'elem T>,
+    {
+        (&*self).extend(iterable.into_iter().cloned())
+    }
+}
+
 /// An [`insert_with`](Set::insert_with) operation result.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Insertion<T, E> {
@@ -556,6 +684,108 @@ impl<'set, T> Iterator for Iter<'set, T> {
     }
 }
 
+/// A lazy iterator over the union of two [`Set`]s. Created by
+/// [`Set::union`]. Membership is tested while iterating, so the same
+/// consistency guarantees of [`Iter`] apply.
+#[derive(Debug)]
+pub struct Union<'set, T, H>
+where
+    T: 'set,
+    H: 'set,
+{
+    first: Iter<'set, T>,
+    second: Difference<'set, T, H>,
+}
+
+impl<'set, T, H> Iterator for Union<'set, T, H>
+where
+    H: BuildHasher,
+    T: Hash + Ord,
+{
+    type Item = ReadGuard<'set, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.first.next().or_else(|| self.second.next())
+    }
+}
+
+/// A lazy iterator over the intersection of two [`Set`]s. Created by
+/// [`Set::intersection`]. Membership is tested while iterating, so the same
+/// consistency guarantees of [`Iter`] apply.
+#[derive(Debug)]
+pub struct Intersection<'set, T, H>
+where
+    T: 'set,
+    H: 'set,
+{
+    iter: Iter<'set, T>,
+    other: &'set Set<T, H>,
+}
+
+impl<'set, T, H> Iterator for Intersection<'set, T, H>
+where
+    H: BuildHasher,
+    T: Hash + Ord,
+{
+    type Item = ReadGuard<'set, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.find(|elem| other.contains::<T>(elem))
+    }
+}
+
+/// A lazy iterator over the difference of two [`Set`]s. Created by
+/// [`Set::difference`]. Membership is tested while iterating, so the same
+/// consistency guarantees of [`Iter`] apply.
+#[derive(Debug)]
+pub struct Difference<'set, T, H>
+where
+    T: 'set,
+    H: 'set,
+{
+    iter: Iter<'set, T>,
+    other: &'set Set<T, H>,
+}
+
+impl<'set, T, H> Iterator for Difference<'set, T, H>
+where
+    H: BuildHasher,
+    T: Hash + Ord,
+{
+    type Item = ReadGuard<'set, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.find(|elem| !other.contains::<T>(elem))
+    }
+}
+
+/// A lazy iterator over the symmetric difference of two [`Set`]s. Created by
+/// [`Set::symmetric_difference`]. Membership is tested while iterating, so
+/// the same consistency guarantees of [`Iter`] apply.
+#[derive(Debug)]
+pub struct SymmetricDifference<'set, T, H>
+where
+    T: 'set,
+    H: 'set,
+{
+    first: Difference<'set, T, H>,
+    second: Difference<'set, T, H>,
+}
+
+impl<'set, T, H> Iterator for SymmetricDifference<'set, T, H>
+where
+    H: BuildHasher,
+    T: Hash + Ord,
+{
+    type Item = ReadGuard<'set, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.first.next().or_else(|| self.second.next())
+    }
+}
+
 /// An iterator over owned elements of a [`Set`].
 pub struct IntoIter<T> {
     inner: MapIntoIter<T, ()>,
@@ -682,6 +912,56 @@ mod test {
         assert!(set.remove(&5).is_none());
     }
 
+    fn sorted<'set, I>(iter: I) -> Vec<u32>
+    where
+        I: Iterator<Item = ReadGuard<'set, u32>>,
+    {
+        let mut elems = iter.map(|elem| *elem).collect::<Vec<_>>();
+        elems.sort();
+        elems
+    }
+
+    #[test]
+    fn set_operations() {
+        let left = (0 .. 6).collect::<Set<u32>>();
+        let right = (3 .. 9).collect::<Set<u32>>();
+        assert_eq!(sorted(left.union(&right)), (0 .. 9).collect::<Vec<_>>());
+        assert_eq!(sorted(left.intersection(&right)), [3, 4, 5]);
+        assert_eq!(sorted(left.difference(&right)), [0, 1, 2]);
+        assert_eq!(sorted(right.difference(&left)), [6, 7, 8]);
+        assert_eq!(
+            sorted(left.symmetric_difference(&right)),
+            [0, 1, 2, 6, 7, 8]
+        );
+    }
+
+    #[test]
+    fn subset_superset_and_disjoint() {
+        let small = (2 .. 4).collect::<Set<u32>>();
+        let big = (0 .. 6).collect::<Set<u32>>();
+        let other = (6 .. 8).collect::<Set<u32>>();
+        assert!(small.is_subset(&big));
+        assert!(!big.is_subset(&small));
+        assert!(big.is_superset(&small));
+        assert!(!small.is_superset(&big));
+        assert!(small.is_subset(&small));
+        assert!(Set::new().is_subset(&small));
+        assert!(big.is_disjoint(&other));
+        assert!(!big.is_disjoint(&small));
+    }
+
+    #[test]
+    fn extends_with_owned_and_borrowed() {
+        let mut set = Set::new();
+        Extend::extend(&mut set, vec!["a".to_owned(), "b".to_owned()]);
+        let borrowed = ["b".to_owned(), "c".to_owned()];
+        Extend::extend(&mut set, &borrowed);
+        Extend::extend(&mut set, borrowed.iter().filter(|elem| *elem != "c"));
+        let mut elems = set.iter().map(|elem| elem.clone()).collect::<Vec<_>>();
+        elems.sort();
+        assert_eq!(elems, ["a", "b", "c"]);
+    }
+
     #[test]
     fn inserts_and_reinserts() {
         let set = Set::new();
@@ -693,6 +973,30 @@ mod test {
         set.insert(9).unwrap_err();
     }
 
+    #[test]
+    fn replace_returns_displaced() {
+        let set = Set::new();
+        assert!(set.replace(EqI { i: 1, j: 0 }).is_none());
+        let old = set.replace(EqI { i: 1, j: 5 }).unwrap();
+        assert_eq!(old.j, 0);
+        assert_eq!(set.get(&EqI { i: 1, j: 9 }).unwrap().j, 5);
+        let old = Removed::try_into(old).unwrap();
+        assert_eq!((old.i, old.j), (1, 0));
+    }
+
+    #[test]
+    fn get_interned() {
+        let set = Set::new();
+        set.insert("interned".to_owned()).unwrap();
+        let canonical = set.get("interned").unwrap();
+        assert_eq!(*canonical, "interned");
+        let again = set.get("interned").unwrap();
+        assert_eq!(canonical.as_ptr(), again.as_ptr());
+        assert!(set.get("other").is_none());
+        assert!(set.contains("interned"));
+        assert!(set.remove("interned").is_some());
+    }
+
     #[test]
     fn insert_with() {
         let set = Set::new();
@@ -0,0 +1,575 @@
+use collections::{Collection, Stats};
+use skiplist::{Detached, Node, NodePause, SkipList};
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt,
+    iter::FromIterator,
+    ops::{Bound, Deref, RangeBounds},
+    ptr::null,
+};
+
+/// A lock-free ordered set. Unlike [`Set`](super::Set), which hashes its
+/// elements, the elements are kept sorted by [`Ord`], so iteration yields
+/// them in ascending order and [`range`](Ordered::range) queries are
+/// supported.
+///
+/// # Design
+/// The elements are kept in a lock-free skiplist, the same one behind
+/// [`Priority`](::queue::Priority). Inserting, removing and searching are
+/// `O(log n)` on average. Removal marks the element's node and then unlinks
+/// it. Since other threads may be still reading a removed element, it is
+/// returned as a [`Removed`] guard instead of being moved out directly.
+pub struct Ordered<T> {
+    list: SkipList<T>,
+}
+
+impl<T> Ordered<T> {
+    /// Creates a new empty [`Ordered`] set.
+    pub fn new() -> Self {
+        Self { list: SkipList::new() }
+    }
+
+    /// Creates an iterator over guarded references to the elements, in
+    /// ascending order. Elements inserted or removed by other threads
+    /// meanwhile may or may not be seen.
+    pub fn iter<'set>(&'set self) -> Iter<'set, T> {
+        // Pausing because we will walk through shared nodes.
+        let pause = self.list.incin().pause();
+        let curr = self.list.first(&pause).map_or(null(), |node| node);
+        Iter { list: &self.list, curr, pause }
+    }
+}
+
+impl<T> Ordered<T>
+where
+    T: Ord,
+{
+    /// Tests if the given element is present on the [`Ordered`] set. The
+    /// method accepts a type resulted from borrowing the stored element. This
+    /// method will only work correctly if [`Ord`] is implemented in the same
+    /// way for the borrowed type and the stored type.
+    pub fn contains<U>(&self, elem: &U) -> bool
+    where
+        U: ?Sized + Ord,
+        T: Borrow<U>,
+    {
+        self.get(elem).is_some()
+    }
+
+    /// Returns a guarded reference to the given element in the [`Ordered`]
+    /// set. The method accepts a type resulted from borrowing the stored
+    /// element. This method will only work correctly if [`Ord`] is implemented
+    /// in the same way for the borrowed type and the stored type.
+    pub fn get<'set, U>(&'set self, elem: &U) -> Option<ReadGuard<'set, T>>
+    where
+        U: ?Sized + Ord,
+        T: Borrow<U>,
+    {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.list.incin().pause();
+        let item = {
+            let node = self.list.search(Bound::Included(elem), &pause)?;
+            if node.item().borrow() != elem {
+                return None;
+            }
+            node.item() as *const T
+        };
+        // Safe because the guard keeps the pause as long as the reference.
+        Some(ReadGuard { item: unsafe { &*item }, _pause: pause })
+    }
+
+    /// Returns a guarded reference to the smallest element, if any.
+    pub fn first<'set>(&'set self) -> Option<ReadGuard<'set, T>> {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.list.incin().pause();
+        let item = self.list.first(&pause)?.item() as *const T;
+        // Safe because the guard keeps the pause as long as the reference.
+        Some(ReadGuard { item: unsafe { &*item }, _pause: pause })
+    }
+
+    /// Inserts the element into the [`Ordered`] set. If the element was
+    /// already present, [`Err`]`(the_passed_value)` is returned.
+    pub fn insert(&self, elem: T) -> Result<(), T> {
+        self.list.insert_unique(elem)
+    }
+
+    /// Removes the given element inconditionally. The method accepts a type
+    /// resulted from borrowing the stored element. This method will only work
+    /// correctly if [`Ord`] is implemented in the same way for the borrowed
+    /// type and the stored type.
+    pub fn remove<U>(&self, elem: &U) -> Option<Removed<T>>
+    where
+        U: ?Sized + Ord,
+        T: Borrow<U>,
+    {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.list.incin().pause();
+        let node = self.list.search(Bound::Included(elem), &pause)?;
+
+        // If we lose the race, the element was removed by someone else.
+        if node.item().borrow() != elem || !self.list.take(node, &pause) {
+            return None;
+        }
+        // Safe because we took the node.
+        let inner = unsafe { Detached::new(node, self.list.incin()) };
+        Some(Removed { inner })
+    }
+
+    /// Removes the smallest element, if any.
+    pub fn pop_first(&self) -> Option<Removed<T>> {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.list.incin().pause();
+
+        loop {
+            let node = self.list.first(&pause)?;
+            if self.list.take(node, &pause) {
+                // Safe because we took the node.
+                let inner = unsafe { Detached::new(node, self.list.incin()) };
+                break Some(Removed { inner });
+            }
+        }
+    }
+
+    /// Creates an iterator over guarded references to the elements within the
+    /// given range, in ascending order. Just like [`iter`](Ordered::iter),
+    /// elements inserted or removed by other threads meanwhile may or may not
+    /// be seen.
+    pub fn range<'set, R>(&'set self, range: R) -> Range<'set, T, R>
+    where
+        R: RangeBounds<T>,
+    {
+        // Pausing because we will walk through shared nodes.
+        let pause = self.list.incin().pause();
+        let curr = self
+            .list
+            .search(range.start_bound(), &pause)
+            .map_or(null(), |node| node);
+        Range { iter: Iter { list: &self.list, curr, pause }, range }
+    }
+
+    /// Inserts elements from the given iterable. Elements already present are
+    /// discarded. Acts just like [`Extend::extend`] but does not require
+    /// mutability.
+    pub fn extend<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for elem in iterable {
+            let _ = self.insert(elem);
+        }
+    }
+}
+
+impl<T> Default for Ordered<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Collection for Ordered<T>
+where
+    T: Ord,
+{
+    fn len(&self) -> usize {
+        self.stats().len
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop_first() {}
+    }
+
+    fn stats(&self) -> Stats {
+        self.list.stats()
+    }
+}
+
+impl<T> fmt::Debug for Ordered<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Ordered {} list: {:?} {}", '{', self.list, '}')
+    }
+}
+
+impl<'set, T> IntoIterator for &'set Ordered<T> {
+    type Item = ReadGuard<'set, T>;
+
+    type IntoIter = Iter<'set, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> FromIterator<T> for Ordered<T>
+where
+    T: Ord,
+{
+    fn from_iter<I>(iterable: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let this = Self::new();
+        this.extend(iterable);
+        this
+    }
+}
+
+impl<T> Extend<T> for Ordered<T>
+where
+    T: Ord,
+{
+    fn extend<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        (&*self).extend(iterable)
+    }
+}
+
+/// A read-operation guard. This ensures no element allocation is freed while
+/// potential reads are performed.
+pub struct ReadGuard<'set, T>
+where
+    T: 'set,
+{
+    item: &'set T,
+    _pause: NodePause<'set, T>,
+}
+
+impl<'set, T> Deref for ReadGuard<'set, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item
+    }
+}
+
+impl<'set, T> fmt::Debug for ReadGuard<'set, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(fmtr)
+    }
+}
+
+impl<'set, T> PartialEq<T> for ReadGuard<'set, T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &T) -> bool {
+        **self == *other
+    }
+}
+
+impl<'set, T> PartialOrd<T> for ReadGuard<'set, T>
+where
+    T: PartialOrd,
+{
+    fn partial_cmp(&self, other: &T) -> Option<Ordering> {
+        (**self).partial_cmp(other)
+    }
+}
+
+impl<'set, T> Borrow<T> for ReadGuard<'set, T> {
+    fn borrow(&self) -> &T {
+        self.item
+    }
+}
+
+impl<'set, T> AsRef<T> for ReadGuard<'set, T> {
+    fn as_ref(&self) -> &T {
+        self.item
+    }
+}
+
+/// A removed element. Other threads might still be reading the element when
+/// it is removed, so it can only be mutated or moved out when no sensitive
+/// reads are being performed on the original [`Ordered`] set.
+pub struct Removed<T> {
+    inner: Detached<T>,
+}
+
+impl<T> Removed<T> {
+    /// Tries to acquire a mutable reference to the element. Succeeds only if
+    /// either the original [`Ordered`] set was dropped or no sensitive reads
+    /// are being performed.
+    pub fn try_as_mut(this: &mut Self) -> Option<&mut T> {
+        Detached::try_as_mut(&mut this.inner)
+    }
+
+    /// Tries to convert this wrapper into the element. Succeeds only if either
+    /// the original [`Ordered`] set was dropped or no sensitive reads are being
+    /// performed.
+    pub fn try_into(this: Self) -> Result<T, Self> {
+        Detached::try_into_inner(this.inner).map_err(|inner| Self { inner })
+    }
+}
+
+impl<T> Deref for Removed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.inner.item()
+    }
+}
+
+impl<T> fmt::Debug for Removed<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(fmtr)
+    }
+}
+
+impl<T> PartialEq<T> for Removed<T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &T) -> bool {
+        **self == *other
+    }
+}
+
+impl<T> PartialOrd<T> for Removed<T>
+where
+    T: PartialOrd,
+{
+    fn partial_cmp(&self, other: &T) -> Option<Ordering> {
+        (**self).partial_cmp(other)
+    }
+}
+
+impl<T> Borrow<T> for Removed<T> {
+    fn borrow(&self) -> &T {
+        self.deref()
+    }
+}
+
+impl<T> AsRef<T> for Removed<T> {
+    fn as_ref(&self) -> &T {
+        self.deref()
+    }
+}
+
+/// An iterator over the elements of an [`Ordered`] set, in ascending order.
+/// The `Item` of this iterator is a [`ReadGuard`].
+pub struct Iter<'set, T>
+where
+    T: 'set,
+{
+    list: &'set SkipList<T>,
+    // The next node to be yielded, loaded during our pause.
+    curr: *const Node<T>,
+    pause: NodePause<'set, T>,
+}
+
+impl<'set, T> Iterator for Iter<'set, T> {
+    type Item = ReadGuard<'set, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Safe because the node was loaded during our pause, and the guard
+        // keeps a pause as long as the reference.
+        let node: &'set Node<T> = unsafe { self.curr.as_ref()? };
+        self.curr = self.list.after(node, &self.pause).map_or(null(), |n| n);
+        Some(ReadGuard { item: node.item(), _pause: self.pause.clone() })
+    }
+}
+
+impl<'set, T> fmt::Debug for Iter<'set, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Iter {} curr: {:?}, pause: {:?} {}",
+            '{', self.curr, self.pause, '}'
+        )
+    }
+}
+
+/// An iterator over the elements of an [`Ordered`] set within a range, in
+/// ascending order. Created by [`range`](Ordered::range). The `Item` of this
+/// iterator is a [`ReadGuard`].
+pub struct Range<'set, T, R>
+where
+    T: 'set,
+{
+    iter: Iter<'set, T>,
+    range: R,
+}
+
+impl<'set, T, R> Iterator for Range<'set, T, R>
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    type Item = ReadGuard<'set, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let guard = self.iter.next()?;
+        let within = match self.range.end_bound() {
+            Bound::Included(end) => *guard <= *end,
+            Bound::Excluded(end) => *guard < *end,
+            Bound::Unbounded => true,
+        };
+
+        if within {
+            Some(guard)
+        } else {
+            // The elements are sorted, so the range is over.
+            self.iter.curr = null();
+            None
+        }
+    }
+}
+
+impl<'set, T, R> fmt::Debug for Range<'set, T, R>
+where
+    R: fmt::Debug,
+{
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Range {} iter: {:?}, range: {:?} {}",
+            '{', self.iter, self.range, '}'
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn inserts_and_removes() {
+        let set = Ordered::new();
+        assert!(set.first().is_none());
+        assert_eq!(set.insert(5), Ok(()));
+        assert_eq!(set.insert(3), Ok(()));
+        assert_eq!(set.insert(5), Err(5));
+        assert!(set.contains(&5));
+        assert!(!set.contains(&4));
+        assert_eq!(*set.get(&3).unwrap(), 3);
+        assert_eq!(*set.first().unwrap(), 3);
+        assert_eq!(set.len(), 2);
+
+        assert_eq!(*set.remove(&5).unwrap(), 5);
+        assert!(set.remove(&5).is_none());
+        assert!(!set.contains(&5));
+        assert_eq!(set.insert(5), Ok(()));
+        assert_eq!(*set.pop_first().unwrap(), 3);
+        assert_eq!(*set.pop_first().unwrap(), 5);
+        assert!(set.pop_first().is_none());
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn iterates_in_order() {
+        let set =
+            vec![5, 3, 8, 1, 9, 2, 7, 3].into_iter().collect::<Ordered<i32>>();
+        let items = set.iter().map(|guard| *guard).collect::<Vec<_>>();
+        assert_eq!(items, [1, 2, 3, 5, 7, 8, 9]);
+
+        let items = set.range(3 .. 8).map(|guard| *guard).collect::<Vec<_>>();
+        assert_eq!(items, [3, 5, 7]);
+        let bounds = (Bound::Excluded(3), Bound::Included(8));
+        let items = set.range(bounds).map(|guard| *guard).collect::<Vec<_>>();
+        assert_eq!(items, [5, 7, 8]);
+        let items = set.range(4 ..).map(|guard| *guard).collect::<Vec<_>>();
+        assert_eq!(items, [5, 7, 8, 9]);
+        let items = set.range(.. 3).map(|guard| *guard).collect::<Vec<_>>();
+        assert_eq!(items, [1, 2]);
+        assert_eq!(set.range(10 ..).count(), 0);
+    }
+
+    #[test]
+    fn borrowed_lookup() {
+        let set = Ordered::new();
+        set.insert(String::from("b")).unwrap();
+        set.insert(String::from("a")).unwrap();
+        assert!(set.contains("a"));
+        assert_eq!(*set.get("b").unwrap(), "b");
+        let removed = set.remove("a").unwrap();
+        assert_eq!(Removed::try_into(removed).unwrap(), "a");
+        assert!(!set.contains("a"));
+    }
+
+    #[test]
+    fn drops_everything() {
+        let item = Arc::new(());
+        let set = Ordered::new();
+        for i in 0 .. 100 {
+            set.insert((i, item.clone())).unwrap();
+        }
+        let removed = set.remove(&(50, item.clone())).unwrap();
+        drop(set);
+        drop(removed);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+        const NVAL: usize = 64;
+
+        let set = Arc::new(Ordered::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let set = set.clone();
+            handles.push(thread::spawn(move || {
+                // Net number of values this thread put into the set.
+                let mut balance = 0isize;
+                for j in 0 .. NITER {
+                    let val = (j * 7 + i) % NVAL;
+                    if j % 2 == 0 {
+                        if set.insert(val).is_ok() {
+                            balance += 1;
+                        }
+                    } else if set.remove(&val).is_some() {
+                        balance -= 1;
+                    }
+                    let items = set.iter().map(|g| *g).collect::<Vec<_>>();
+                    assert!(items.windows(2).all(|pair| pair[0] < pair[1]));
+                }
+                balance
+            }));
+        }
+
+        let mut balance = 0;
+        for handle in handles {
+            balance += handle.join().expect("thread failed");
+        }
+        assert_eq!(set.len() as isize, balance);
+        assert_eq!(set.stats().nodes, set.len());
+        set.clear();
+        assert!(set.is_empty());
+        assert_eq!(set.stats().nodes, 0);
+    }
+
+    #[test]
+    fn concurrent_insert_is_unique() {
+        const NTHREAD: usize = 8;
+        const NVAL: usize = 500;
+
+        let set = Arc::new(Ordered::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let set = set.clone();
+            handles.push(thread::spawn(move || {
+                (0 .. NVAL).filter(|&val| set.insert(val).is_ok()).count()
+            }));
+        }
+
+        let mut inserted = 0;
+        for handle in handles {
+            inserted += handle.join().expect("thread failed");
+        }
+        assert_eq!(inserted, NVAL);
+        let items = set.iter().map(|g| *g).collect::<Vec<_>>();
+        assert_eq!(items, (0 .. NVAL).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,573 @@
+use collections::Stats;
+use incin::{Incinerator, Pause};
+use owned_alloc::OwnedAlloc;
+use std::{
+    array,
+    borrow::Borrow,
+    cell::Cell,
+    cmp::Ordering as CmpOrdering,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    mem::forget,
+    ops::Bound,
+    ptr::{null, null_mut, NonNull},
+    sync::{
+        atomic::{AtomicPtr, Ordering::*},
+        Arc, Weak,
+    },
+};
+
+/// The maximum number of levels of the skiplist. Higher levels are
+/// truncated to this one.
+const MAX_HEIGHT: usize = 16;
+
+/// The incinerator of the nodes of a [`SkipList`].
+pub type Incin<T> = Incinerator<OwnedAlloc<Node<T>>>;
+
+/// A pause of the incinerator of a [`SkipList`].
+pub type NodePause<'list, T> = Pause<'list, OwnedAlloc<Node<T>>>;
+
+/// A lock-free skiplist, which keeps items in ascending order. It is the base
+/// of the ordered collections, such as [`Priority`](::queue::Priority) and
+/// [`Ordered`](::set::Ordered).
+///
+/// A node is removed by first marking the lowest bit of its next pointers,
+/// starting with the bottom level, and then unlinking it from every level.
+/// Other threads help unlinking marked nodes they find on the way. Every
+/// method taking a pause expects it to come from this list's incinerator, and
+/// the returned nodes are only valid during that pause.
+pub struct SkipList<T> {
+    head: Tower<T>,
+    incin: Arc<Incin<T>>,
+}
+
+impl<T> SkipList<T> {
+    /// Creates a new empty skiplist.
+    pub fn new() -> Self {
+        Self { head: new_tower(), incin: Arc::new(Incinerator::new()) }
+    }
+
+    /// The incinerator of the nodes of this skiplist.
+    pub fn incin(&self) -> &Arc<Incin<T>> {
+        &self.incin
+    }
+
+    /// Returns the first node not removed, if any.
+    pub fn first<'pause>(
+        &self,
+        _pause: &'pause NodePause<T>,
+    ) -> Option<&'pause Node<T>> {
+        // Safe because the pointer is loaded during the pause.
+        unsafe { first_unmarked(self.head[0].load(Acquire)) }
+    }
+
+    /// Returns the node after the given one which was not removed, if any. The
+    /// given node might have been removed.
+    pub fn after<'pause>(
+        &self,
+        node: &'pause Node<T>,
+        _pause: &'pause NodePause<T>,
+    ) -> Option<&'pause Node<T>> {
+        // Safe because the pointer is loaded during the pause.
+        unsafe { first_unmarked(unmark(node.next[0].load(Acquire))) }
+    }
+
+    /// Counts the items and the nodes reachable from the bottom level.
+    pub fn stats(&self) -> Stats {
+        // Pausing because we will walk through shared nodes.
+        let _pause = self.incin.pause();
+        let mut stats = Stats::default();
+        let mut curr = self.head[0].load(Acquire);
+
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator.
+        while let Some(nnptr) = NonNull::new(curr) {
+            let next = unsafe { nnptr.as_ref().next[0].load(Acquire) };
+            stats.nodes += 1;
+            if !is_marked(next) {
+                stats.len += 1;
+            }
+            curr = unmark(next);
+        }
+
+        stats
+    }
+}
+
+impl<T> SkipList<T>
+where
+    T: Ord,
+{
+    /// Inserts the item after every equal item in the list, so equal items are
+    /// kept in insertion order.
+    pub fn insert(&self, item: T) {
+        // Linking without uniqueness never fails.
+        let _ = self.link(item, false);
+    }
+
+    /// Inserts the item only if no equal item is in the list. Otherwise, the
+    /// item is given back.
+    pub fn insert_unique(&self, item: T) -> Result<(), T> {
+        self.link(item, true)
+    }
+
+    /// Returns the first node not removed which is within the given lower
+    /// bound, if any.
+    pub fn search<'pause, U>(
+        &self,
+        bound: Bound<&U>,
+        pause: &'pause NodePause<T>,
+    ) -> Option<&'pause Node<T>>
+    where
+        U: ?Sized + Ord,
+        T: Borrow<U>,
+    {
+        let succ = match bound {
+            Bound::Included(key) => self.find(key, false, pause).1[0],
+            Bound::Excluded(key) => self.find(key, true, pause).1[0],
+            Bound::Unbounded => return self.first(pause),
+        };
+        // Safe because the pointer was loaded during the pause.
+        unsafe { succ.as_ref() }
+    }
+
+    /// Removes the given node from the list. Returns `false` if another thread
+    /// removed it first. On success, the caller is responsible for the node,
+    /// e.g. through [`Detached`].
+    pub fn take(&self, node: &Node<T>, pause: &NodePause<T>) -> bool {
+        // Marking the bottom level is what takes the node.
+        let mut next = node.next[0].load(Acquire);
+        loop {
+            if is_marked(next) {
+                return false;
+            }
+
+            match node.next[0].compare_exchange(
+                next,
+                mark(next),
+                AcqRel,
+                Acquire,
+            ) {
+                Ok(_) => break,
+                Err(new) => next = new,
+            }
+        }
+
+        for level in (1 .. node.height).rev() {
+            let mut next = node.next[level].load(Acquire);
+            while !is_marked(next) {
+                match node.next[level].compare_exchange(
+                    next,
+                    mark(next),
+                    AcqRel,
+                    Acquire,
+                ) {
+                    Ok(_) => break,
+                    Err(new) => next = new,
+                }
+            }
+        }
+
+        self.unlink(node, pause);
+        true
+    }
+
+    fn link(&self, item: T, unique: bool) -> Result<(), T> {
+        // Pausing because of ABA problem and use-after-free.
+        let pause = self.incin.pause();
+        let height = random_height();
+        let node = OwnedAlloc::new(Node { item, height, next: new_tower() });
+        let node_nnptr = node.into_raw();
+        let node_ptr = node_nnptr.as_ptr();
+        // Safe because we own the node until it is linked, and after that it
+        // is only deleted via incinerator, and we have a pause.
+        let node = unsafe { &*node_ptr };
+
+        let (mut preds, mut succs) = loop {
+            let (preds, succs) = self.find(&node.item, !unique, &pause);
+
+            // Safe because the pointers were loaded during the pause.
+            let found = unsafe { succs[0].as_ref() };
+            if unique && found.map_or(false, |succ| succ.item == node.item) {
+                // Safe because the node was never shared.
+                let alloc = unsafe { OwnedAlloc::from_raw(node_nnptr) };
+                let (node, _) = alloc.move_inner();
+                return Err(node.item);
+            }
+
+            for level in 0 .. height {
+                // Relaxed because the node is not shared yet.
+                node.next[level].store(succs[level], Relaxed);
+            }
+
+            // Safe because the pointers were loaded during the pause.
+            let res = unsafe {
+                (*preds[0])[0]
+                    .compare_exchange(succs[0], node_ptr, AcqRel, Acquire)
+            };
+
+            if res.is_ok() {
+                break (preds, succs);
+            }
+        };
+
+        'levels: for level in 1 .. height {
+            loop {
+                let next = node.next[level].load(Acquire);
+                if is_marked(next) {
+                    // Somebody is removing the node. Let's stop linking it.
+                    break 'levels;
+                }
+
+                if next != succs[level] {
+                    let res = node.next[level].compare_exchange(
+                        next,
+                        succs[level],
+                        AcqRel,
+                        Acquire,
+                    );
+                    if res.is_err() {
+                        continue;
+                    }
+                }
+
+                // Safe because the pointers were loaded during the pause.
+                let res = unsafe {
+                    (*preds[level])[level].compare_exchange(
+                        succs[level],
+                        node_ptr,
+                        AcqRel,
+                        Acquire,
+                    )
+                };
+
+                if res.is_ok() {
+                    break;
+                }
+
+                let (new_preds, new_succs) =
+                    self.find(&node.item, !unique, &pause);
+                preds = new_preds;
+                succs = new_succs;
+            }
+        }
+
+        if is_marked(node.next[0].load(Acquire)) {
+            // The node was removed while we were linking it. We might have
+            // linked it again at some level after the remover unlinked it, so
+            // let's unlink it before resuming the incinerator.
+            self.unlink(node, &pause);
+        }
+
+        Ok(())
+    }
+
+    // Finds, for each level, the last node whose value is less than the key
+    // (or equal to it, if `after_equal`), and the node after it. Marked nodes
+    // found on the way are unlinked. The returned pointers are only valid
+    // during the passed pause.
+    fn find<U>(
+        &self,
+        key: &U,
+        after_equal: bool,
+        _pause: &NodePause<T>,
+    ) -> ([*const Tower<T>; MAX_HEIGHT], [*mut Node<T>; MAX_HEIGHT])
+    where
+        U: ?Sized + Ord,
+        T: Borrow<U>,
+    {
+        'retry: loop {
+            let mut preds = [null::<Tower<T>>(); MAX_HEIGHT];
+            let mut succs = [null_mut(); MAX_HEIGHT];
+            let mut pred = &self.head as *const Tower<T>;
+
+            for level in (0 .. MAX_HEIGHT).rev() {
+                // Safe because we paused the incinerator and we only delete
+                // nodes via incinerator.
+                let mut curr = unsafe { (*pred)[level].load(Acquire) };
+                if is_marked(curr) {
+                    // The predecessor is being removed.
+                    continue 'retry;
+                }
+
+                while let Some(nnptr) = NonNull::new(curr) {
+                    // Safe because of the pause, as above.
+                    let node = unsafe { nnptr.as_ref() };
+                    let next = node.next[level].load(Acquire);
+
+                    if is_marked(next) {
+                        // Helping to unlink a removed node.
+                        let res = unsafe {
+                            (*pred)[level].compare_exchange(
+                                curr,
+                                unmark(next),
+                                AcqRel,
+                                Acquire,
+                            )
+                        };
+                        if res.is_err() {
+                            continue 'retry;
+                        }
+                        curr = unmark(next);
+                        continue;
+                    }
+
+                    let advance = match node.item.borrow().cmp(key) {
+                        CmpOrdering::Less => true,
+                        CmpOrdering::Equal => after_equal,
+                        CmpOrdering::Greater => false,
+                    };
+
+                    if !advance {
+                        break;
+                    }
+                    pred = &node.next;
+                    curr = next;
+                }
+
+                preds[level] = pred;
+                succs[level] = curr;
+            }
+
+            break (preds, succs);
+        }
+    }
+
+    // Unlinks the given removed node from every level. Other marked nodes
+    // found on the way are also unlinked. The node must have been loaded
+    // during the passed pause.
+    fn unlink(&self, target: &Node<T>, _pause: &NodePause<T>) {
+        let target_ptr = target as *const Node<T> as *mut Node<T>;
+
+        'retry: loop {
+            // The last node whose value is less than the target's.
+            let mut base = &self.head as *const Tower<T>;
+
+            for level in (0 .. MAX_HEIGHT).rev() {
+                let mut pred = base;
+                // Safe because we paused the incinerator and we only delete
+                // nodes via incinerator.
+                let mut curr = unsafe { (*pred)[level].load(Acquire) };
+                if is_marked(curr) {
+                    // The predecessor is being removed.
+                    continue 'retry;
+                }
+
+                while let Some(nnptr) = NonNull::new(curr) {
+                    // Safe because of the pause, as above.
+                    let node = unsafe { nnptr.as_ref() };
+                    let next = node.next[level].load(Acquire);
+
+                    if is_marked(next) {
+                        let res = unsafe {
+                            (*pred)[level].compare_exchange(
+                                curr,
+                                unmark(next),
+                                AcqRel,
+                                Acquire,
+                            )
+                        };
+                        if res.is_err() {
+                            continue 'retry;
+                        }
+                        if curr == target_ptr {
+                            break;
+                        }
+                        curr = unmark(next);
+                    } else if node.item < target.item {
+                        base = &node.next;
+                        pred = base;
+                        curr = next;
+                    } else if node.item == target.item && level < target.height
+                    {
+                        // Equal values are ordered by insertion, so the target
+                        // may be after any of them.
+                        pred = &node.next;
+                        curr = next;
+                    } else {
+                        break;
+                    }
+                }
+            }
+
+            break;
+        }
+    }
+}
+
+impl<T> Drop for SkipList<T> {
+    fn drop(&mut self) {
+        let mut curr = *self.head[0].get_mut();
+        while let Some(nnptr) = NonNull::new(curr) {
+            // This is safe because we only store pointers allocated via
+            // `OwnedAlloc`. Also, we have exclusive access to this pointer, and
+            // removed nodes are not linked anymore.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            curr = *node.next[0].get_mut();
+        }
+    }
+}
+
+impl<T> fmt::Debug for SkipList<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "SkipList {} head: {:?}, incin: {:?} {}",
+            '{', self.head[0], self.incin, '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for SkipList<T> where T: Send {}
+unsafe impl<T> Sync for SkipList<T> where T: Send + Sync {}
+
+/// A node of a [`SkipList`].
+#[repr(align(/* at least */ 2))]
+pub struct Node<T> {
+    item: T,
+    height: usize,
+    next: Tower<T>,
+}
+
+impl<T> Node<T> {
+    /// The item stored in this node.
+    pub fn item(&self) -> &T {
+        &self.item
+    }
+}
+
+/// A node taken out of a [`SkipList`]. Other threads might still be reading
+/// the item when the node is taken, so it can only be moved out or mutated
+/// when no sensitive reads are being performed.
+pub struct Detached<T> {
+    nnptr: NonNull<Node<T>>,
+    origin: Weak<Incin<T>>,
+}
+
+impl<T> Detached<T> {
+    /// Wraps a node which the caller took with [`SkipList::take`]. The given
+    /// incinerator must be the list's one. Unsafe because the node must not be
+    /// wrapped twice.
+    pub unsafe fn new(node: &Node<T>, origin: &Arc<Incin<T>>) -> Self {
+        Self { nnptr: NonNull::from(node), origin: Arc::downgrade(origin) }
+    }
+
+    /// The item of the taken node.
+    pub fn item(&self) -> &T {
+        // Safe because we own the allocation, and it is only mutated with
+        // exclusive access.
+        unsafe { &self.nnptr.as_ref().item }
+    }
+
+    /// Tries to acquire a mutable reference to the item. Succeeds only if
+    /// either the original list was dropped or no sensitive reads are being
+    /// performed.
+    pub fn try_as_mut(this: &mut Self) -> Option<&mut T> {
+        let success = match this.origin.upgrade() {
+            None => true,
+            Some(arc) => arc.try_clear(),
+        };
+
+        if success {
+            // Safe because we own the allocation and nobody is reading it.
+            // The node is not linked anymore, so nobody will read it again.
+            Some(unsafe { &mut this.nnptr.as_mut().item })
+        } else {
+            None
+        }
+    }
+
+    /// Tries to move the item out. Succeeds only if either the original list
+    /// was dropped or no sensitive reads are being performed.
+    pub fn try_into_inner(mut this: Self) -> Result<T, Self> {
+        let success = match this.origin.upgrade() {
+            None => true,
+            Some(arc) => arc.try_clear(),
+        };
+
+        if success {
+            // Safe because we own the allocation and nobody is reading it.
+            let node = unsafe { OwnedAlloc::from_raw(this.nnptr) };
+            // There is no other way of dropping the weak and forgetting
+            // ourselves. Rust does not let us move fields of a `Drop` struct.
+            unsafe { (&mut this.origin as *mut Weak<_>).drop_in_place() }
+            forget(this);
+            let (node, _) = node.move_inner();
+            Ok(node.item)
+        } else {
+            Err(this)
+        }
+    }
+}
+
+impl<T> Drop for Detached<T> {
+    fn drop(&mut self) {
+        // Safe because we own the allocation.
+        let node = unsafe { OwnedAlloc::from_raw(self.nnptr) };
+        match self.origin.upgrade() {
+            // Other threads might still be reading it.
+            Some(incin) => incin.add(node),
+            None => drop(node),
+        }
+    }
+}
+
+unsafe impl<T> Send for Detached<T> where T: Send {}
+unsafe impl<T> Sync for Detached<T> where T: Sync {}
+
+type Tower<T> = [AtomicPtr<Node<T>>; MAX_HEIGHT];
+
+fn new_tower<T>() -> Tower<T> {
+    array::from_fn(|_| AtomicPtr::new(null_mut()))
+}
+
+// Skips the removed nodes starting from the given one. Unsafe because the
+// pointer must have been loaded during a pause which lives for `'pause`.
+unsafe fn first_unmarked<'pause, T>(
+    mut curr: *mut Node<T>,
+) -> Option<&'pause Node<T>> {
+    loop {
+        let nnptr = NonNull::new(curr)?;
+        // Safe because the caller paused the incinerator and we only delete
+        // nodes via incinerator.
+        let node = &*nnptr.as_ptr();
+        let next = node.next[0].load(Acquire);
+
+        if !is_marked(next) {
+            break Some(node);
+        }
+        curr = unmark(next);
+    }
+}
+
+// The lowest bit of a node's next pointer marks the node as removed.
+fn is_marked<T>(ptr: *mut Node<T>) -> bool {
+    ptr as usize & 1 == 1
+}
+
+fn mark<T>(ptr: *mut Node<T>) -> *mut Node<T> {
+    (ptr as usize | 1) as *mut Node<T>
+}
+
+fn unmark<T>(ptr: *mut Node<T>) -> *mut Node<T> {
+    (ptr as usize & !1) as *mut Node<T>
+}
+
+// Each level is half as likely as the previous one.
+fn random_height() -> usize {
+    thread_local! {
+        static STATE: Cell<u64> =
+            Cell::new(RandomState::new().build_hasher().finish() | 1);
+    }
+
+    STATE.with(|state| {
+        // Xorshift. The state is never zero.
+        let mut bits = state.get();
+        bits ^= bits << 13;
+        bits ^= bits >> 7;
+        bits ^= bits << 17;
+        state.set(bits);
+        (bits.trailing_zeros() as usize + 1).min(MAX_HEIGHT)
+    })
+}
@@ -0,0 +1,61 @@
+use super::Stack;
+use future;
+
+impl<T> Stack<T> {
+    /// Creates a future which resolves to a value popped from the top of the
+    /// stack, waiting asynchronously until one is available. The task is woken
+    /// up by [`push`](Stack::push). Requires the `futures` feature.
+    pub fn pop_async<'stack>(&'stack self) -> PopFuture<'stack, T> {
+        future::PopFuture::new(self, &self.waiters, Stack::pop)
+    }
+}
+
+/// A future based on [`pop`](Stack::pop) operation of the [`Stack`]. Created
+/// by [`pop_async`](Stack::pop_async). Dropping it before completion is fine.
+pub type PopFuture<'stack, T> = future::PopFuture<'stack, Stack<T>, T>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use executor::block_on;
+    use std::{sync::Arc, thread, time::Duration};
+
+    #[test]
+    fn ready_if_not_empty() {
+        let stack = Stack::new();
+        stack.push(3);
+        assert_eq!(block_on(stack.pop_async()), 3);
+    }
+
+    #[test]
+    fn woken_up_by_push() {
+        const NTHREAD: usize = 4;
+        const NITER: usize = 200;
+
+        let stack = Arc::new(Stack::new());
+        let mut consumers = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let stack = stack.clone();
+            consumers.push(thread::spawn(move || {
+                (0 .. NITER).map(|_| block_on(stack.pop_async())).sum::<usize>()
+            }));
+        }
+
+        // Giving the consumers time to wait.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. NTHREAD * NITER {
+            if i % 50 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            stack.push(i);
+        }
+
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.join().expect("thread failed");
+        }
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+    }
+}
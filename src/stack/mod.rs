@@ -0,0 +1,956 @@
+#[cfg(feature = "futures")]
+mod future;
+
+#[cfg(feature = "futures")]
+pub use self::future::PopFuture;
+
+use collections::{Collection, Stats};
+use incin::Pause;
+use owned_alloc::OwnedAlloc;
+use recycle::{NodeCache, Recycled};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    iter::FromIterator,
+    mem::{self, ManuallyDrop},
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{AtomicPtr, Ordering::*},
+        Arc,
+    },
+};
+use wait::WaitList;
+
+/// A lock-free stack. LIFO/FILO semanthics are fully respected.
+pub struct Stack<T> {
+    top: AtomicPtr<Node<T>>,
+    incin: SharedIncin<T>,
+    cache: Arc<NodeCache<Node<T>>>,
+    waiters: WaitList,
+}
+
+impl<T> Stack<T> {
+    /// Creates a new empty stack.
+    pub fn new() -> Self {
+        Self::with_incin(SharedIncin::new())
+    }
+
+    /// Creates an empty queue using the passed shared incinerator.
+    pub fn with_incin(incin: SharedIncin<T>) -> Self {
+        Self {
+            top: AtomicPtr::new(null_mut()),
+            incin,
+            cache: Arc::new(NodeCache::new()),
+            waiters: WaitList::new(),
+        }
+    }
+
+    /// Returns the shared incinerator used by this [`Stack`].
+    pub fn incin(&self) -> SharedIncin<T> {
+        self.incin.clone()
+    }
+
+    /// Creates an iterator over copies of the values currently in the stack,
+    /// from the top to the bottom, without popping them. The whole iteration
+    /// happens under a single incinerator pause. Values pushed or popped by
+    /// other threads meanwhile may or may not be seen. Since popping moves
+    /// values out of the stack, only `Copy` values can be observed this way.
+    pub fn iter<'stack>(&'stack self) -> Iter<'stack, T>
+    where
+        T: Copy,
+    {
+        // Pausing because we will walk through shared nodes.
+        let pause = self.incin.inner.pause();
+        let curr = self.top.load(Acquire);
+        Iter { curr, pause }
+    }
+
+    /// Creates an iterator over `T`s, based on [`pop`](Stack::pop) operation of
+    /// the [`Stack`].
+    pub fn pop_iter<'stack>(&'stack self) -> PopIter<'stack, T> {
+        PopIter { stack: self }
+    }
+
+    /// Pushes a new value onto the top of the stack. If there are threads
+    /// blocked in [`pop_wait`](Stack::pop_wait) (or tasks waiting on
+    /// `pop_async`), one of them is woken up.
+    pub fn push(&self, val: T) {
+        // Let's first create a node.
+        let mut target =
+            self.cache.alloc(Node::new(val, self.top.load(Acquire)));
+
+        loop {
+            // Let's try to publish our changes. `SeqCst` because of the wait
+            // list.
+            let new_top = target.raw().as_ptr();
+            match self.top.compare_exchange(
+                target.next,
+                new_top,
+                SeqCst,
+                Relaxed,
+            ) {
+                Ok(_) => {
+                    // Let's be sure we do not deallocate the pointer.
+                    target.into_raw();
+                    break;
+                },
+
+                Err(ptr) => target.next = ptr,
+            }
+        }
+
+        self.waiters.notify_one();
+    }
+
+    /// Pushes the elements of the given iterable onto the top of the stack,
+    /// in order, so the last one ends up at the top. The nodes are linked
+    /// locally first, and then the whole chain is published with a single
+    /// successful CAS, so this is cheaper than calling [`push`](Stack::push)
+    /// in a loop when there are many threads.
+    pub fn push_iter<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut chain = Chain { top: null_mut(), bottom: null_mut() };
+        let mut len = 0;
+
+        for val in iterable {
+            len += 1;
+            let node = self.cache.alloc(Node::new(val, chain.top));
+            chain.top = node.into_raw().as_ptr();
+            if chain.bottom.is_null() {
+                chain.bottom = chain.top;
+            }
+        }
+
+        if chain.top.is_null() {
+            return;
+        }
+
+        // Safe because the chain is not shared yet and its nodes are not null.
+        let bottom = unsafe { &mut *chain.bottom };
+        bottom.next = self.top.load(Acquire);
+
+        loop {
+            // Let's try to publish our changes, just like in `push`.
+            match self.top.compare_exchange(
+                bottom.next,
+                chain.top,
+                SeqCst,
+                Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(ptr) => bottom.next = ptr,
+            }
+        }
+
+        // The nodes now belong to the stack.
+        mem::forget(chain);
+        for _ in 0 .. len {
+            self.waiters.notify_one();
+        }
+    }
+
+    /// Pops a single element from the top of the stack.
+    pub fn pop(&self) -> Option<T> {
+        // We need this because of ABA problem and use-after-free.
+        let pause = self.incin.inner.pause();
+        // First, let's load our top.
+        let mut top = self.top.load(Acquire);
+
+        loop {
+            // If top is null, we have nothing. Try operator (?) handles it.
+            let mut nnptr = NonNull::new(top)?;
+            // The replacement for top is its "next". This is only possible
+            // because of incinerator. Otherwise, we would face the "ABA
+            // problem".
+            //
+            // Note this dereferral is safe because we only delete nodes via
+            // incinerator and we have a pause now.
+            match self.top.compare_exchange(
+                top,
+                unsafe { nnptr.as_ref().next },
+                AcqRel,
+                Acquire,
+            ) {
+                Ok(_) => {
+                    // Done with an element. Let's first get the "val" to be
+                    // returned.
+                    //
+                    // This derreferal and read are safe since we drop the
+                    // node via incinerator and we never drop the inner value
+                    // when dropping the node in the incinerator.
+                    let val =
+                        unsafe { (&mut *nnptr.as_mut().val as *mut T).read() };
+                    // Safe because we already removed the node and we are
+                    // adding to the incinerator rather than
+                    // dropping it directly.
+                    let alloc = unsafe { OwnedAlloc::from_raw(nnptr) };
+                    pause.add_to_incin(Recycled::new(alloc, &self.cache));
+                    break Some(val);
+                },
+
+                Err(new_top) => top = new_top,
+            }
+        }
+    }
+
+    /// Pops the element from the top of the stack only if it satisfies the
+    /// given predicate. The test and the removal happen atomically: the
+    /// predicate is called again if the top changes before the removal. Since
+    /// other threads may pop the value while it is being tested, only `Copy`
+    /// values can be tested this way.
+    pub fn pop_if<F>(&self, mut pred: F) -> Option<T>
+    where
+        F: FnMut(&T) -> bool,
+        T: Copy,
+    {
+        // We need this because of ABA problem and use-after-free.
+        let pause = self.incin.inner.pause();
+        let mut top = self.top.load(Acquire);
+
+        loop {
+            let nnptr = NonNull::new(top)?;
+            // Safe because we only delete nodes via incinerator and we have a
+            // pause now. Popping only reads the value, and `Copy` values do
+            // not own resources that could be released by whoever popped it.
+            let node = unsafe { nnptr.as_ref() };
+            let val = *node.val;
+            if !pred(&val) {
+                break None;
+            }
+
+            match self.top.compare_exchange(top, node.next, AcqRel, Acquire) {
+                Ok(_) => {
+                    // Safe because we already removed the node and we are
+                    // adding to the incinerator rather than dropping it
+                    // directly. The value is `Copy`, so it does not matter
+                    // that it is not read out of the node.
+                    let alloc = unsafe { OwnedAlloc::from_raw(nnptr) };
+                    pause.add_to_incin(Recycled::new(alloc, &self.cache));
+                    break Some(val);
+                },
+
+                Err(new_top) => top = new_top,
+            }
+        }
+    }
+
+    /// Pops a single element from the top of the stack, blocking the current
+    /// thread until one is available. The thread is parked rather than
+    /// spinning, and it is woken up by [`push`](Stack::push).
+    pub fn pop_wait(&self) -> T {
+        loop {
+            if let Some(val) = self.pop() {
+                break val;
+            }
+
+            let waiter = self.waiters.register();
+            // Checking again because a push might have happened before we
+            // registered ourselves.
+            match self.pop() {
+                Some(val) => {
+                    waiter.cancel(&self.waiters);
+                    break val;
+                },
+
+                None => waiter.wait(),
+            }
+        }
+    }
+
+    /// Detaches all the elements of the stack at once, with a single atomic
+    /// operation, and returns an iterator over them, from the top to the
+    /// bottom. Elements not consumed by the iterator are dropped with it.
+    pub fn pop_all<'stack>(&'stack self) -> PopAll<'stack, T> {
+        let top = self.top.swap(null_mut(), AcqRel);
+        PopAll { stack: self, top, left: usize::max_value() }
+    }
+
+    /// Detaches up to `n` elements from the top of the stack at once, with a
+    /// single successful CAS, and returns an iterator over them, from the top
+    /// to the bottom. Elements not consumed by the iterator are dropped with
+    /// it.
+    pub fn pop_n<'stack>(&'stack self, n: usize) -> PopAll<'stack, T> {
+        if n == 0 {
+            return PopAll { stack: self, top: null_mut(), left: 0 };
+        }
+
+        // We need this because of ABA problem and use-after-free.
+        let _pause = self.incin.inner.pause();
+        let mut top = self.top.load(Acquire);
+
+        loop {
+            let mut last = match NonNull::new(top) {
+                Some(nnptr) => nnptr,
+                None => break PopAll { stack: self, top, left: 0 },
+            };
+            let mut left = 1;
+
+            // Safe because we only delete nodes via incinerator and we have a
+            // pause now. The next field of a node is never changed after the
+            // node is published.
+            while left < n {
+                match NonNull::new(unsafe { last.as_ref().next }) {
+                    Some(next) => last = next,
+                    None => break,
+                }
+                left += 1;
+            }
+
+            let new_top = unsafe { last.as_ref().next };
+            match self.top.compare_exchange(top, new_top, AcqRel, Acquire) {
+                Ok(_) => break PopAll { stack: self, top, left },
+                Err(found) => top = found,
+            }
+        }
+    }
+
+    /// Pushes elements from the given iterable. Acts just like
+    /// [`Extend::extend`] but does not require mutability.
+    pub fn extend<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for elem in iterable {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        while let Some(_) = self.next() {}
+    }
+}
+
+impl<T> Iterator for Stack<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let top = self.top.get_mut();
+
+        NonNull::new(*top).map(|nnptr| {
+            // This is safe because we only store pointers allocated via
+            // `OwnedAlloc`. Also, we have exclusive access to this pointer.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            *top = node.next;
+            // This read is we never drop the inner value when dropping the
+            // node.
+            unsafe { (&mut *node.val as *mut T).read() }
+        })
+    }
+}
+
+impl<T> Extend<T> for Stack<T> {
+    fn extend<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        (&*self).extend(iterable)
+    }
+}
+
+impl<'stack, T> IntoIterator for &'stack Stack<T> {
+    type Item = T;
+
+    type IntoIter = PopIter<'stack, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pop_iter()
+    }
+}
+
+impl<'stack, T> Extend<T> for &'stack Stack<T> {
+    fn extend<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Stack::extend(self, iterable)
+    }
+}
+
+impl<T> FromIterator<T> for Stack<T> {
+    fn from_iter<I>(iterable: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let this = Self::new();
+        this.extend(iterable);
+        this
+    }
+}
+
+impl<T> Collection for Stack<T> {
+    fn len(&self) -> usize {
+        self.stats().len
+    }
+
+    fn clear(&self) {
+        while let Some(_) = self.pop() {}
+    }
+
+    fn stats(&self) -> Stats {
+        // Pausing because we will walk through shared nodes.
+        let _pause = self.incin.inner.pause();
+        let mut stats = Stats::default();
+        let mut node_ptr = self.top.load(Acquire);
+
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator. The next field of a node is never
+        // changed after the node is published.
+        while let Some(nnptr) = NonNull::new(node_ptr) {
+            stats.len += 1;
+            stats.nodes += 1;
+            node_ptr = unsafe { nnptr.as_ref().next };
+        }
+
+        stats
+    }
+}
+
+/// Serializes the values currently in the stack, from the top to the bottom,
+/// without popping them.
+///
+/// `T: Copy` is required, and this is a limitation rather than an oversight. A
+/// concurrent [`pop`](Stack::pop) moves the value out of its node and hands it
+/// to the caller, who may drop it while the serializer still reads the node.
+/// The node memory stays valid thanks to the incinerator, but the value
+/// itself does not, so only values without destructors or owned resources
+/// can be read there. Other types must be taken out of the stack first, e.g.
+/// by serializing a [`Vec`] collected with [`pop_iter`](Stack::pop_iter).
+#[cfg(feature = "serde")]
+impl<T> Serialize for Stack<T>
+where
+    T: Serialize + Copy,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Deserializes a sequence from the top to the bottom, i.e. in the same
+/// order it is serialized.
+#[cfg(feature = "serde")]
+impl<'de, T> Deserialize<'de> for Stack<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        Ok(items.into_iter().rev().collect())
+    }
+}
+
+impl<T> fmt::Debug for Stack<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Stack {} top: {:?}, incin: {:?}, waiters: {:?} {}",
+            '{', self.top, self.incin, self.waiters, '}'
+        )
+    }
+}
+
+unsafe impl<T> Send for Stack<T> where T: Send {}
+unsafe impl<T> Sync for Stack<T> where T: Send {}
+
+/// An iterator based on [`pop`](Stack::pop) operation of the [`Stack`].
+pub struct PopIter<'stack, T>
+where
+    T: 'stack,
+{
+    stack: &'stack Stack<T>,
+}
+
+impl<'stack, T> Iterator for PopIter<'stack, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.stack.pop()
+    }
+}
+
+impl<'stack, T> fmt::Debug for PopIter<'stack, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "PopIter {} stack: {:?} {}", '{', self.stack, '}')
+    }
+}
+
+/// An iterator over copies of the values of a [`Stack`], created by
+/// [`iter`](Stack::iter). It does not pop the values.
+pub struct Iter<'stack, T>
+where
+    T: 'stack,
+{
+    curr: *mut Node<T>,
+    pause: Pause<'stack, Recycled<Node<T>>>,
+}
+
+impl<'stack, T> Iterator for Iter<'stack, T>
+where
+    T: Copy,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // Safe to derefer the nodes because we paused the incinerator and we
+        // only delete nodes via incinerator. The next field of a node is never
+        // changed after the node is published.
+        let node = unsafe { NonNull::new(self.curr)?.as_ref() };
+        self.curr = node.next;
+        // Popping only reads the value, and `Copy` values do not own
+        // resources that could be released by whoever popped it.
+        Some(*node.val)
+    }
+}
+
+impl<'stack, T> fmt::Debug for Iter<'stack, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "Iter {} curr: {:?}, pause: {:?} {}",
+            '{', self.curr, self.pause, '}'
+        )
+    }
+}
+
+/// An iterator over the elements detached by [`pop_all`](Stack::pop_all) or
+/// [`pop_n`](Stack::pop_n).
+pub struct PopAll<'stack, T>
+where
+    T: 'stack,
+{
+    stack: &'stack Stack<T>,
+    top: *mut Node<T>,
+    // The last detached node might still point to the stack.
+    left: usize,
+}
+
+impl<'stack, T> Iterator for PopAll<'stack, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        let mut nnptr = NonNull::new(self.top)?;
+        self.left -= 1;
+        // Safe because the nodes were detached from the stack, so only we can
+        // take their values. The next field of a node is never changed after
+        // the node is published, and the value is never dropped by the node.
+        let val = unsafe {
+            self.top = nnptr.as_ref().next;
+            (&mut *nnptr.as_mut().val as *mut T).read()
+        };
+        // Other threads might have loaded the node before it was detached, so
+        // we are adding it to the incinerator rather than dropping it
+        // directly.
+        let alloc = unsafe { OwnedAlloc::from_raw(nnptr) };
+        self.stack.incin.inner.add(Recycled::new(alloc, &self.stack.cache));
+        Some(val)
+    }
+}
+
+impl<'stack, T> Drop for PopAll<'stack, T> {
+    fn drop(&mut self) {
+        while let Some(_) = self.next() {}
+    }
+}
+
+impl<'stack, T> fmt::Debug for PopAll<'stack, T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmtr,
+            "PopAll {} stack: {:?}, top: {:?}, left: {:?} {}",
+            '{', self.stack, self.top, self.left, '}'
+        )
+    }
+}
+
+make_shared_incin! {
+    { "[`Stack`]" }
+    pub SharedIncin<T> of Recycled<Node<T>>
+}
+
+impl<T> fmt::Debug for SharedIncin<T> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "SharedIncin {} inner: {:?} {}", '{', self.inner, '}')
+    }
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    val: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+impl<T> Node<T> {
+    fn new(val: T, next: *mut Node<T>) -> Self {
+        Self { val: ManuallyDrop::new(val), next }
+    }
+}
+
+// Nodes linked locally before being pushed onto a stack. Frees them in case of
+// panic.
+struct Chain<T> {
+    top: *mut Node<T>,
+    bottom: *mut Node<T>,
+}
+
+impl<T> Drop for Chain<T> {
+    fn drop(&mut self) {
+        while let Some(nnptr) = NonNull::new(self.top) {
+            // This is safe because the nodes were allocated via `OwnedAlloc`
+            // and they are not shared.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            // The bottom of the chain points to null until it is published.
+            self.top = node.next;
+            // Safe because the value is never used again.
+            unsafe { ManuallyDrop::drop(&mut node.val) }
+        }
+    }
+}
+
+// Testing the safety of `unsafe` in this module is done with random operations
+// via fuzzing
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread, time::Duration};
+
+    #[test]
+    fn on_empty_first_pop_is_none() {
+        let stack = Stack::<usize>::new();
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn on_empty_last_pop_is_none() {
+        let stack = Stack::new();
+        stack.push(3);
+        stack.push(1234);
+        stack.pop();
+        stack.pop();
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn order() {
+        let stack = Stack::new();
+        stack.push(4);
+        stack.push(3);
+        stack.push(5);
+        stack.push(6);
+        assert_eq!(stack.pop(), Some(6));
+        assert_eq!(stack.pop(), Some(5));
+        assert_eq!(stack.pop(), Some(3));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        use serde_json;
+
+        let stack = Stack::new();
+        stack.extend(vec![1, 2, 3, 4]);
+        stack.pop();
+        let json = serde_json::to_string(&stack).unwrap();
+        assert_eq!(json, "[3,2,1]");
+        assert_eq!(stack.len(), 3);
+
+        let stack = serde_json::from_str::<Stack<i32>>(&json).unwrap();
+        assert_eq!(stack.pop_iter().collect::<Vec<_>>(), [3, 2, 1]);
+    }
+
+    #[test]
+    fn extend_and_collect() {
+        let mut stack = (0 .. 3).collect::<Stack<_>>();
+        Extend::extend(&mut stack, 3 .. 5);
+        let mut shared = &stack;
+        Extend::extend(&mut shared, 5 .. 7);
+        assert_eq!(stack.pop_iter().collect::<Vec<_>>(), [6, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn iter_does_not_pop() {
+        let stack = Stack::new();
+        assert_eq!(stack.iter().next(), None);
+        stack.extend(vec![1, 2, 3, 4]);
+        stack.pop();
+        assert_eq!(stack.iter().collect::<Vec<_>>(), [3, 2, 1]);
+
+        let mut iter = stack.iter();
+        assert_eq!(iter.next(), Some(3));
+        stack.push(5);
+        assert_eq!(iter.collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(stack.len(), 4);
+    }
+
+    #[test]
+    fn pop_if_tests_the_top() {
+        let stack = Stack::new();
+        assert_eq!(stack.pop_if(|_| true), None);
+        stack.extend(vec![1, 5, 2, 7]);
+        assert_eq!(stack.pop_if(|&val| val > 4), Some(7));
+        assert_eq!(stack.pop_if(|&val| val > 4), None);
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop_if(|&val| val > 4), Some(5));
+        assert_eq!(stack.len(), 1);
+    }
+
+    #[test]
+    fn pop_wait_wakes_up() {
+        const NTHREAD: usize = 4;
+        const NITER: usize = 500;
+
+        let stack = Arc::new(Stack::new());
+        let mut consumers = Vec::with_capacity(NTHREAD);
+
+        for _ in 0 .. NTHREAD {
+            let stack = stack.clone();
+            consumers.push(thread::spawn(move || {
+                (0 .. NITER).map(|_| stack.pop_wait()).sum::<usize>()
+            }));
+        }
+
+        // Giving the consumers time to block.
+        thread::sleep(Duration::from_millis(20));
+        for i in 0 .. NTHREAD * NITER / 2 {
+            if i % 50 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            stack.push_iter(vec![2 * i, 2 * i + 1]);
+        }
+
+        let mut sum = 0;
+        for consumer in consumers {
+            sum += consumer.join().expect("thread failed");
+        }
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn push_iter_order() {
+        let stack = Stack::new();
+        stack.push_iter(Vec::new());
+        assert!(stack.pop().is_none());
+        stack.push(0);
+        stack.push_iter(1 .. 4);
+        stack.push(4);
+        assert_eq!(stack.pop_iter().collect::<Vec<_>>(), [4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn push_iter_no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 500;
+        const BATCH: usize = 8;
+
+        let stack = Arc::new(Stack::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let stack = stack.clone();
+            handles.push(thread::spawn(move || {
+                let mut sum = 0;
+                for j in 0 .. NITER {
+                    let start = (i * NITER + j) * BATCH;
+                    stack.push_iter(start .. start + BATCH);
+                    sum += stack.pop().unwrap();
+                }
+                sum
+            }));
+        }
+
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.join().expect("thread failed");
+        }
+        sum += stack.pop_iter().sum::<usize>();
+
+        let total = NTHREAD * NITER * BATCH;
+        assert_eq!(sum, total * (total - 1) / 2);
+    }
+
+    #[test]
+    fn owned_and_shared_iteration() {
+        let stack = (0 .. 4).collect::<Stack<_>>();
+        let mut popped = Vec::new();
+        for val in &stack {
+            popped.push(val);
+            if val == 2 {
+                break;
+            }
+        }
+        assert_eq!(popped, [3, 2]);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), [1, 0]);
+
+        let item = Arc::new(());
+        let stack = (0 .. 4).map(|_| item.clone()).collect::<Stack<_>>();
+        let mut stack = stack.into_iter();
+        stack.next();
+        drop(stack);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn pop_all_detaches_everything() {
+        let stack = Stack::new();
+        assert!(stack.pop_all().next().is_none());
+        stack.extend(vec![1, 2, 3, 4]);
+        let mut popped = stack.pop_all();
+        assert!(stack.pop().is_none());
+        assert_eq!(popped.next(), Some(4));
+        stack.push(5);
+        assert_eq!(popped.collect::<Vec<_>>(), [3, 2, 1]);
+        assert_eq!(stack.pop(), Some(5));
+
+        let item = Arc::new(());
+        let stack = Stack::new();
+        stack.extend(vec![item.clone(), item.clone()]);
+        drop(stack.pop_all());
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn pop_n_detaches_from_the_top() {
+        let stack = Stack::new();
+        assert!(stack.pop_n(3).next().is_none());
+        stack.extend(vec![1, 2, 3, 4, 5]);
+        assert!(stack.pop_n(0).next().is_none());
+        let mut popped = stack.pop_n(2);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(popped.next(), Some(5));
+        assert_eq!(popped.collect::<Vec<_>>(), [4]);
+        assert_eq!(stack.pop_n(5).collect::<Vec<_>>(), [2, 1]);
+        assert!(stack.is_empty());
+
+        let item = Arc::new(());
+        let stack = Stack::new();
+        stack.extend(vec![item.clone(), item.clone(), item.clone()]);
+        drop(stack.pop_n(2));
+        assert_eq!(stack.len(), 1);
+        drop(stack);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn pop_n_no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+
+        let stack = Arc::new(Stack::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let stack = stack.clone();
+            handles.push(thread::spawn(move || {
+                let mut sum = 0;
+                for j in 0 .. NITER {
+                    stack.push(i * NITER + j);
+                    if j % 4 == 0 {
+                        sum += stack.pop_n(3).sum::<usize>();
+                    }
+                }
+                sum
+            }));
+        }
+
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.join().expect("thread failed");
+        }
+        sum += stack.pop_all().sum::<usize>();
+
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+    }
+
+    #[test]
+    fn pop_all_no_data_corruption() {
+        const NTHREAD: usize = 8;
+        const NITER: usize = 2000;
+
+        let stack = Arc::new(Stack::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let stack = stack.clone();
+            handles.push(thread::spawn(move || {
+                let mut sum = 0;
+                for j in 0 .. NITER {
+                    stack.push(i * NITER + j);
+                    if j % 10 == 0 {
+                        sum += stack.pop_all().sum::<usize>();
+                    } else if let Some(val) = stack.pop() {
+                        sum += val;
+                    }
+                }
+                sum
+            }));
+        }
+
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.join().expect("thread failed");
+        }
+        sum += stack.pop_all().sum::<usize>();
+
+        let total = NTHREAD * NITER;
+        assert_eq!(sum, total * (total - 1) / 2);
+    }
+
+    #[test]
+    fn no_data_corruption() {
+        const NTHREAD: usize = 20;
+        const NITER: usize = 800;
+        const NMOD: usize = 55;
+
+        let stack = Arc::new(Stack::new());
+        let mut handles = Vec::with_capacity(NTHREAD);
+
+        for i in 0 .. NTHREAD {
+            let stack = stack.clone();
+            handles.push(thread::spawn(move || {
+                for j in 0 .. NITER {
+                    let val = (i * NITER) + j;
+                    stack.push(val);
+                    if (val + 1) % NMOD == 0 {
+                        if let Some(val) = stack.pop() {
+                            assert!(val < NITER * NTHREAD);
+                        }
+                    }
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.join().expect("thread failed");
+        }
+
+        let expected = NITER * NTHREAD - NITER * NTHREAD / NMOD;
+        let mut res = 0;
+        while let Some(val) = stack.pop() {
+            assert!(val < NITER * NTHREAD);
+            res += 1;
+        }
+
+        assert_eq!(res, expected);
+    }
+}
@@ -0,0 +1,399 @@
+use incin::Incinerator;
+use owned_alloc::OwnedAlloc;
+use std::{
+    fmt,
+    mem::ManuallyDrop,
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{fence, AtomicPtr, AtomicUsize, Ordering::*},
+        Arc,
+    },
+    thread::{self, Thread},
+    time::Instant,
+};
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll, Waker};
+
+const WAITING: usize = 0;
+const NOTIFIED: usize = 1;
+const CANCELLED: usize = 2;
+
+/// A lock-free list of threads blocked waiting for some condition of a
+/// collection (e.g. "the queue is not empty"). The waiting side must register
+/// itself and only then check the condition again before blocking. The side
+/// changing the condition must notify after the change, and the change must
+/// be a `SeqCst` operation (or be followed by a `SeqCst` fence).
+///
+/// A cancelled registration is removed right away if it is at the top of the
+/// list, or otherwise by the next notification which finds it.
+///
+/// Registering fences, so that notifying does not need to: when nobody ever
+/// waits, notifying costs a single `SeqCst` load, which is a plain load on
+/// most architectures. A `SeqCst` read-modify-write costs the same as an
+/// `AcqRel` one on x86 as well.
+pub struct WaitList {
+    top: AtomicPtr<Node>,
+    // Registered entries, including cancelled ones not removed yet. Used so
+    // notifying an empty list is cheap.
+    count: AtomicUsize,
+    incin: Incinerator<OwnedAlloc<Node>>,
+}
+
+impl WaitList {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Self {
+            top: AtomicPtr::new(null_mut()),
+            count: AtomicUsize::new(0),
+            incin: Incinerator::new(),
+        }
+    }
+
+    /// Registers the current thread as waiting. The condition must be checked
+    /// again after this, since it might have changed before the registration.
+    pub fn register(&self) -> Waiter {
+        self.register_with(Wake::Thread(thread::current()))
+    }
+
+    /// Registers an asynchronous task as waiting. Just like
+    /// [`register`](WaitList::register), the condition must be checked again
+    /// after this.
+    #[cfg(feature = "futures")]
+    pub fn register_task(&self, waker: Waker) -> Waiter {
+        self.register_with(Wake::Task(waker))
+    }
+
+    fn register_with(&self, wake: Wake) -> Waiter {
+        let entry =
+            Arc::new(Entry { state: AtomicUsize::new(WAITING), wake });
+        let mut node = OwnedAlloc::new(Node {
+            entry: ManuallyDrop::new(entry.clone()),
+            next: self.top.load(Acquire),
+        });
+
+        loop {
+            let new_top = node.raw().as_ptr();
+            match self.top.compare_exchange(
+                node.next,
+                new_top,
+                Release,
+                Relaxed,
+            ) {
+                Ok(_) => {
+                    node.into_raw();
+                    break;
+                },
+
+                Err(ptr) => node.next = ptr,
+            }
+        }
+
+        // Release so a notifier which sees the count also sees the node.
+        self.count.fetch_add(1, Release);
+        // Pairs with the notifier's `SeqCst` change and load of the count:
+        // either the notifier sees this registration, or the check after it
+        // sees the notifier's change.
+        fence(SeqCst);
+        Waiter { entry }
+    }
+
+    /// Wakes up a single waiting thread, if any. Cancelled entries found on
+    /// the way are discarded. The change of the condition must have been a
+    /// `SeqCst` operation.
+    pub fn notify_one(&self) {
+        // Pairs with the fence in `register`.
+        if self.count.load(SeqCst) == 0 {
+            return;
+        }
+
+        while let Some(entry) = self.pop() {
+            if entry.notify() {
+                break;
+            }
+        }
+    }
+
+    /// Wakes up all waiting threads. Used when the condition became true for
+    /// every waiter, e.g. the other side disconnected. Just like in
+    /// [`notify_one`](WaitList::notify_one), the change must have been a
+    /// `SeqCst` operation.
+    pub fn notify_all(&self) {
+        // Pairs with the fence in `register`.
+        if self.count.load(SeqCst) == 0 {
+            return;
+        }
+
+        while let Some(entry) = self.pop() {
+            entry.notify();
+        }
+    }
+
+    /// The number of registered entries, including cancelled ones not removed
+    /// yet.
+    #[cfg(test)]
+    pub fn registered(&self) -> usize {
+        self.count.load(Acquire)
+    }
+
+    // Removes the cancelled entries at the top of the list. Otherwise, a list
+    // whose waiters keep giving up (e.g. on timeouts) would grow until the
+    // next notification.
+    fn prune(&self) {
+        while self.pop_if(Entry::is_cancelled).is_some() {}
+    }
+
+    fn pop(&self) -> Option<Arc<Entry>> {
+        self.pop_if(|_| true)
+    }
+
+    // Pops the top entry if it satisfies the given predicate.
+    fn pop_if<F>(&self, pred: F) -> Option<Arc<Entry>>
+    where
+        F: Fn(&Entry) -> bool,
+    {
+        // We need this because of ABA problem and use-after-free.
+        let pause = self.incin.pause();
+        let mut top = self.top.load(Acquire);
+
+        loop {
+            let mut nnptr = NonNull::new(top)?;
+            // Safe because we only delete nodes via incinerator and we have a
+            // pause now.
+            let (next, accepted) = unsafe {
+                let node = nnptr.as_ref();
+                (node.next, pred(&node.entry))
+            };
+            if !accepted {
+                break None;
+            }
+            match self.top.compare_exchange(top, next, AcqRel, Acquire) {
+                Ok(_) => {
+                    // Safe since we removed the node and the node never drops
+                    // the entry by itself.
+                    let entry = unsafe {
+                        (&mut *nnptr.as_mut().entry as *mut Arc<Entry>).read()
+                    };
+                    // Safe because we already removed the node and we are
+                    // adding to the incinerator rather than dropping it
+                    // directly.
+                    pause.add_to_incin(unsafe { OwnedAlloc::from_raw(nnptr) });
+                    self.count.fetch_sub(1, Relaxed);
+                    break Some(entry);
+                },
+
+                Err(new_top) => top = new_top,
+            }
+        }
+    }
+}
+
+impl Drop for WaitList {
+    fn drop(&mut self) {
+        let top = self.top.get_mut();
+        while let Some(nnptr) = NonNull::new(*top) {
+            // This is safe because we only store pointers allocated via
+            // `OwnedAlloc`. Also, we have exclusive access to this pointer.
+            let mut node = unsafe { OwnedAlloc::from_raw(nnptr) };
+            *top = node.next;
+            // Safe because the entry is never used again.
+            unsafe { ManuallyDrop::drop(&mut node.entry) }
+        }
+    }
+}
+
+impl fmt::Debug for WaitList {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "WaitList {} count: {:?} {}", '{', self.count, '}')
+    }
+}
+
+/// A registration of a thread in a [`WaitList`].
+pub struct Waiter {
+    entry: Arc<Entry>,
+}
+
+impl Waiter {
+    /// Tests whether this waiter is still waiting and would wake up the same
+    /// task as the given waker.
+    #[cfg(feature = "futures")]
+    pub fn will_wake(&self, waker: &Waker) -> bool {
+        let waiting = self.entry.state.load(Acquire) == WAITING;
+        match &self.entry.wake {
+            Wake::Task(registered) => waiting && registered.will_wake(waker),
+            Wake::Thread(_) => false,
+        }
+    }
+
+    /// Tests whether this waiter was notified. Used to wait on several lists
+    /// at once.
+    pub fn is_notified(&self) -> bool {
+        self.entry.state.load(Acquire) == NOTIFIED
+    }
+
+    /// Blocks the current thread until notified. Must only be called by the
+    /// registered thread.
+    pub fn wait(&self) {
+        while self.entry.state.load(Acquire) != NOTIFIED {
+            thread::park();
+        }
+    }
+
+    /// Blocks the current thread until notified or until the deadline is
+    /// reached. Returns whether it was notified. Must only be called by the
+    /// registered thread, and the registration should be cancelled on timeout.
+    pub fn wait_until(&self, deadline: Instant) -> bool {
+        loop {
+            if self.entry.state.load(Acquire) == NOTIFIED {
+                break true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+
+    /// Cancels the registration. Should be called if the condition was found
+    /// to be true after the registration. If a notification was already
+    /// delivered to this waiter, it is passed to another one.
+    pub fn cancel(self, list: &WaitList) {
+        let res = self.entry.state.compare_exchange(
+            WAITING,
+            CANCELLED,
+            AcqRel,
+            Acquire,
+        );
+        match res {
+            Ok(_) => list.prune(),
+            Err(_) => list.notify_one(),
+        }
+    }
+
+    /// Ends the registration after the condition was found to be true and
+    /// used. Unlike [`cancel`](Waiter::cancel), a notification already
+    /// delivered to this waiter counts as used and is not passed on.
+    pub fn finish(self, list: &WaitList) {
+        let res = self.entry.state.compare_exchange(
+            WAITING,
+            CANCELLED,
+            AcqRel,
+            Acquire,
+        );
+        if res.is_ok() {
+            list.prune();
+        }
+    }
+}
+
+impl fmt::Debug for Waiter {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmtr, "Waiter {} state: {:?} {}", '{', self.entry.state, '}')
+    }
+}
+
+/// The registration of an asynchronous task in a [`WaitList`], kept across
+/// polls of a future or a stream. Its owner must [`cancel`](TaskWaiter::cancel)
+/// it when dropped.
+#[cfg(feature = "futures")]
+#[derive(Debug, Default)]
+pub struct TaskWaiter {
+    waiter: Option<Waiter>,
+}
+
+#[cfg(feature = "futures")]
+impl TaskWaiter {
+    /// Creates a waiter which is not registered yet.
+    pub fn new() -> Self {
+        Self { waiter: None }
+    }
+
+    /// Tries the given operation. If it fails, the task of the given context
+    /// is registered in the list, and the operation is tried again before
+    /// returning [`Poll::Pending`].
+    pub fn poll<F, A>(
+        &mut self,
+        list: &WaitList,
+        cx: &mut Context,
+        mut attempt: F,
+    ) -> Poll<A>
+    where
+        F: FnMut() -> Option<A>,
+    {
+        if let Some(output) = attempt() {
+            // Any notification we received was used by this attempt.
+            if let Some(waiter) = self.waiter.take() {
+                waiter.finish(list);
+            }
+            return Poll::Ready(output);
+        }
+
+        if let Some(waiter) = &self.waiter {
+            if waiter.will_wake(cx.waker()) {
+                return Poll::Pending;
+            }
+        }
+
+        // Either the first poll, or the task was woken up (or moved) without
+        // succeeding. Let's register again with the current waker.
+        self.cancel(list);
+        let waiter = list.register_task(cx.waker().clone());
+        // Checking again because the condition might have changed before we
+        // registered ourselves.
+        match attempt() {
+            Some(output) => {
+                waiter.cancel(list);
+                Poll::Ready(output)
+            },
+
+            None => {
+                self.waiter = Some(waiter);
+                Poll::Pending
+            },
+        }
+    }
+
+    /// Cancels the registration, if any. A notification received but not
+    /// used is passed on.
+    pub fn cancel(&mut self, list: &WaitList) {
+        if let Some(waiter) = self.waiter.take() {
+            waiter.cancel(list);
+        }
+    }
+}
+
+enum Wake {
+    Thread(Thread),
+    #[cfg(feature = "futures")]
+    Task(Waker),
+}
+
+struct Entry {
+    state: AtomicUsize,
+    wake: Wake,
+}
+
+impl Entry {
+    fn is_cancelled(&self) -> bool {
+        self.state.load(Acquire) == CANCELLED
+    }
+
+    // Returns whether the entry was still waiting.
+    fn notify(&self) -> bool {
+        let res =
+            self.state.compare_exchange(WAITING, NOTIFIED, AcqRel, Acquire);
+        if res.is_ok() {
+            match &self.wake {
+                Wake::Thread(thread) => thread.unpark(),
+                #[cfg(feature = "futures")]
+                Wake::Task(waker) => waker.wake_by_ref(),
+            }
+        }
+        res.is_ok()
+    }
+}
+
+struct Node {
+    entry: ManuallyDrop<Arc<Entry>>,
+    next: *mut Node,
+}